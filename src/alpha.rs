@@ -0,0 +1,127 @@
+//! Hiding data in the color of fully transparent pixels.
+//!
+//! A pixel with an alpha of 0 looks the same whatever its color is, so all bits of its
+//! color samples can be used, not just the low ones: 3 bytes per pixel in 8 bit RGBA
+//! images. The alpha samples are never changed, so the same pixels are found again.
+//!
+//! The data has the same header as in [`crate::lsb`], and with a [`StegoKey`] the
+//! samples are used in a shuffled order.
+
+use crate::Result;
+use crate::lsb::{self, StegoKey};
+use crate::pixels::Pixels;
+
+/// index of every byte of the color samples of fully transparent pixels
+///
+/// empty for images without an alpha channel
+fn slots(pixels: &Pixels, key: Option<StegoKey>) -> Vec<usize> {
+    let ihdr = pixels.ihdr();
+    if !ihdr.color_type().has_alpha() {
+        return Vec::new();
+    }
+    // alpha images have 8 or 16 bit samples, so pixels are whole bytes
+    let sample_len = ihdr.bit_depth() as usize / 8;
+    let pixel_len = ihdr.color_type().channels() * sample_len;
+    let color_len = pixel_len - sample_len;
+
+    let mut slots: Vec<usize> = pixels
+        .data()
+        .chunks_exact(pixel_len)
+        .enumerate()
+        .filter(|(_, p)| p[color_len..].iter().all(|b| *b == 0))
+        .flat_map(|(i, _)| i * pixel_len..i * pixel_len + color_len)
+        .collect();
+    if let Some(key) = key {
+        lsb::shuffle(&mut slots, key);
+    }
+    slots
+}
+
+/// number of fully transparent pixels
+pub fn transparent_pixels(pixels: &Pixels) -> usize {
+    let ihdr = pixels.ihdr();
+    let color_len = (ihdr.color_type().channels() - 1) * (ihdr.bit_depth() as usize / 8);
+    slots(pixels, None)
+        .len()
+        .checked_div(color_len)
+        .unwrap_or(0)
+}
+
+/// number of bytes that can be hidden in the fully transparent pixels
+pub fn capacity(pixels: &Pixels) -> usize {
+    slots(pixels, None).len().saturating_sub(lsb::HEADER_LEN)
+}
+
+/// hide data in the color samples of the fully transparent pixels
+pub fn embed(pixels: &mut Pixels, data: &[u8], key: Option<StegoKey>) -> Result<()> {
+    let slots = slots(pixels, key);
+    let bits = lsb::to_bits(data, slots.len() * 8)?;
+    let samples = pixels.data_mut();
+    for (slot, byte) in slots.iter().zip(bits.chunks(8)) {
+        samples[*slot] = byte.iter().fold(0, |acc, bit| acc << 1 | bit);
+    }
+    Ok(())
+}
+
+/// read the data hidden in the fully transparent pixels, None if there is none
+pub fn extract(pixels: &Pixels, key: Option<StegoKey>) -> Result<Option<Vec<u8>>> {
+    let slots = slots(pixels, key);
+    let samples = pixels.data();
+    lsb::from_bits(slots.len() * 8, |k| {
+        (samples[slots[k / 8]] >> (7 - k % 8)) & 1
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    fn dice() -> Pixels {
+        Pixels::from_png(&test_util::dice()).unwrap()
+    }
+
+    #[test]
+    fn test_embed_extract() {
+        let mut pixels = dice();
+        let original = pixels.clone();
+        let data: Vec<u8> = (0..=255).collect();
+        embed(&mut pixels, &data, None).unwrap();
+        assert_eq!(extract(&pixels, None).unwrap(), Some(data));
+
+        // only pixels that were invisible changed, and they still are
+        for (a, b) in original.data().chunks(4).zip(pixels.data().chunks(4)) {
+            if a != b {
+                assert_eq!((a[3], b[3]), (0, 0));
+            }
+        }
+        assert_eq!(transparent_pixels(&pixels), transparent_pixels(&original));
+    }
+
+    #[test]
+    fn test_keyed() {
+        let key = StegoKey::from([5; 32]);
+        let mut pixels = dice();
+        embed(&mut pixels, b"Hello World!", Some(key)).unwrap();
+        assert_eq!(
+            extract(&pixels, Some(key)).unwrap().as_deref(),
+            Some(&b"Hello World!"[..])
+        );
+        assert!(extract(&pixels, None).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_capacity() {
+        let pixels = dice();
+        let transparent = transparent_pixels(&pixels);
+        assert!(transparent > 0);
+        assert_eq!(capacity(&pixels), transparent * 3 - lsb::HEADER_LEN);
+
+        let mut pixels = dice();
+        let data = vec![0; capacity(&pixels) + 1];
+        assert!(matches!(
+            embed(&mut pixels, &data, None),
+            Err(crate::Error::Lsb(lsb::LsbError::TooLarge { .. }))
+        ));
+    }
+}
@@ -0,0 +1,645 @@
+//! Looking for signs of hidden data in a png (steganalysis).
+//!
+//! The pixels are checked channel by channel with three statistical attacks on the
+//! least significant bits:
+//!
+//! - the chi-square attack (Westfeld and Pfitzmann): embedding random bits evens out the
+//!   counts of every pair of values 2k and 2k+1, which natural images rarely do
+//! - RS analysis (Fridrich, Goljan and Du): flipping the low bits makes groups of
+//!   neighboring samples less smooth in a clean image, but hardly in one full of data
+//! - sample pair analysis (Dumitrescu, Wu and Wang): the same idea for pairs of
+//!   neighboring samples, solved exactly for the share of samples carrying data
+//!
+//! RS and sample pair analysis estimate the embedding rate, the share of samples whose
+//! low bit carries data. Small images and large flat areas make all of them less
+//! reliable. The chunks are checked too, for ones no decoder knows, private ones, data
+//! that looks like it was hidden by PNGecret and data after IEND.
+
+use std::fmt::Display;
+
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+
+use crate::Result;
+use crate::ihdr::ColorType;
+use crate::palette::{self, Palette};
+use crate::pixels::Pixels;
+use crate::png::Png;
+use crate::text_chunk::{TextChunk, TextKind};
+
+// ancillary chunks of the spec and its registered extensions
+const KNOWN: [&str; 30] = [
+    "cHRM", "gAMA", "iCCP", "sBIT", "sRGB", "cICP", "mDCV", "cLLI", "bKGD", "hIST", "tRNS", "eXIf",
+    "pHYs", "sPLT", "tIME", "tEXt", "zTXt", "iTXt", "acTL", "fcTL", "fdAT", "oFFs", "pCAL", "sCAL",
+    "gIFg", "gIFx", "gIFt", "sTER", "dSIG", "fRAc",
+];
+// every magic PNGecret starts its data with begins with these bytes
+const PNGECRET_MAGIC: [u8; 3] = [0x89, b'P', b'G'];
+// samples in a group of RS analysis, and the mask flipping the middle two
+const RS_MASK: [bool; 4] = [false, true, true, false];
+// the chi-square test needs a handful of samples in every pair of values
+const MIN_EXPECTED: f64 = 5.0;
+// an embedding rate this high counts as certain
+const CERTAIN_RATE: f64 = 0.3;
+
+/// results of the statistical attacks on one channel
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelAnalysis {
+    /// red, green, blue, gray or alpha
+    pub name: &'static str,
+    /// probability that the low bits are random, None if there are too few samples
+    pub chi_square: Option<f64>,
+    /// embedding rate estimated by RS analysis
+    pub rs: Option<f64>,
+    /// embedding rate estimated by sample pair analysis
+    pub sample_pairs: Option<f64>,
+}
+
+impl ChannelAnalysis {
+    /// mean of the estimated embedding rates
+    pub fn embedding_rate(&self) -> Option<f64> {
+        let rates: Vec<f64> = [self.rs, self.sample_pairs].into_iter().flatten().collect();
+        (!rates.is_empty()).then(|| rates.iter().sum::<f64>() / rates.len() as f64)
+    }
+}
+
+/// something about the chunks or pixels that normal images don't have
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Finding {
+    // ancillary chunk that is neither in the spec nor a registered extension
+    UnknownChunk(String),
+    PrivateChunk(String),
+    // data in the chunk starts like data hidden by PNGecret
+    PngecretData(String),
+    ChunkAfterIend(String),
+    // number of bytes after IEND that aren't a chunk
+    TrailingData(usize),
+    // fully transparent pixels with many different invisible colors
+    VariedTransparentPixels { varied: usize, transparent: usize },
+    // palette colors just one level apart, like ones added to hide data
+    NearDuplicateColors(usize),
+}
+
+impl Display for Finding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Finding::UnknownChunk(ct) => write!(f, "{ct} is an unknown ancillary chunk"),
+            Finding::PrivateChunk(ct) => write!(f, "{ct} is a private chunk"),
+            Finding::PngecretData(ct) => write!(f, "{ct} holds data hidden by PNGecret"),
+            Finding::ChunkAfterIend(ct) => write!(f, "{ct} comes after IEND"),
+            Finding::TrailingData(n) => write!(f, "{n} bytes of data after IEND"),
+            Finding::VariedTransparentPixels {
+                varied,
+                transparent,
+            } => write!(
+                f,
+                "{varied} of {transparent} fully transparent pixels have different colors"
+            ),
+            Finding::NearDuplicateColors(n) => {
+                write!(f, "{n} pairs of palette colors are almost the same")
+            }
+        }
+    }
+}
+
+/// a finding and where it is
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Flag {
+    /// index of the chunk, None if it's about the png as a whole
+    pub index: Option<usize>,
+    pub finding: Finding,
+}
+
+impl Display for Flag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.index {
+            Some(index) => write!(f, "chunk {index}: {}", self.finding),
+            None => write!(f, "{}", self.finding),
+        }
+    }
+}
+
+/// everything found in a png
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    /// every channel of the pixels, empty if they can't be analyzed
+    pub channels: Vec<ChannelAnalysis>,
+    /// why the pixels weren't analyzed
+    pub skipped: Option<String>,
+    pub flags: Vec<Flag>,
+}
+
+impl Report {
+    /// highest embedding rate estimated for a channel
+    pub fn embedding_rate(&self) -> Option<f64> {
+        self.channels
+            .iter()
+            .filter_map(ChannelAnalysis::embedding_rate)
+            .reduce(f64::max)
+    }
+
+    /// how likely data is hidden in the pixels, from 0 to 1
+    ///
+    /// based on the statistics only, the flags come on top. An embedding rate of
+    /// [`CERTAIN_RATE`] or a certain chi-square attack give 1
+    pub fn suspicion(&self) -> f64 {
+        let rate = self.embedding_rate().unwrap_or(0.0) / CERTAIN_RATE;
+        let chi_square = self
+            .channels
+            .iter()
+            .filter_map(|c| c.chi_square)
+            .fold(0.0, f64::max);
+        rate.max(chi_square).clamp(0.0, 1.0)
+    }
+}
+
+/// analyze the pixels and chunks of png
+///
+/// pixels that can't be decoded are skipped, the chunks are still checked
+pub fn analyze(png: &Png) -> Result<Report> {
+    let mut flags = chunk_flags(png);
+    let (channels, skipped) = analyze_pixels(png, &mut flags)
+        .unwrap_or_else(|e| (Vec::new(), Some(format!("they can't be decoded: {e}"))));
+
+    Ok(Report {
+        channels,
+        skipped,
+        flags,
+    })
+}
+
+/// every channel of the pixels of png and why they were skipped, flags of the
+/// palette and transparent pixels are added to flags
+fn analyze_pixels(
+    png: &Png,
+    flags: &mut Vec<Flag>,
+) -> Result<(Vec<ChannelAnalysis>, Option<String>)> {
+    let ihdr = png.ihdr()?;
+    let names: &[&'static str] = match ihdr.color_type() {
+        ColorType::Grayscale => &["gray"],
+        ColorType::Rgb => &["red", "green", "blue"],
+        ColorType::Indexed => &[],
+        ColorType::GrayscaleAlpha => &["gray", "alpha"],
+        ColorType::Rgba => &["red", "green", "blue", "alpha"],
+    };
+    Ok(if ihdr.color_type() == ColorType::Indexed {
+        if let Some(n) = near_duplicates(png)? {
+            flags.push(Flag {
+                index: None,
+                finding: Finding::NearDuplicateColors(n),
+            });
+        }
+        let reason = "pixels of indexed images are palette indices, not samples";
+        (Vec::new(), Some(reason.to_string()))
+    } else if ihdr.bit_depth() < 8 {
+        let reason = format!("{} bit samples are too small", ihdr.bit_depth());
+        (Vec::new(), Some(reason))
+    } else {
+        let pixels = Pixels::from_png(png)?;
+        let channels = names
+            .iter()
+            .enumerate()
+            .map(|(c, name)| {
+                let samples = samples(&pixels, c, names.len());
+                ChannelAnalysis {
+                    name,
+                    chi_square: chi_square(&samples, ihdr.bit_depth()),
+                    rs: rs_analysis(&samples),
+                    sample_pairs: sample_pair_analysis(&samples),
+                }
+            })
+            .collect();
+        if ihdr.color_type().has_alpha() {
+            flags.extend(transparent_flag(&pixels, names.len()));
+        }
+        (channels, None)
+    })
+}
+
+/// chunks that are unknown, private, hold PNGecret data or come after IEND
+fn chunk_flags(png: &Png) -> Vec<Flag> {
+    let mut flags = Vec::new();
+    let mut flag = |index, finding| flags.push(Flag { index, finding });
+    let iend = png
+        .chunks()
+        .iter()
+        .position(|c| &c.chunk_type().bytes() == b"IEND");
+
+    for (i, chunk) in png.chunks().iter().enumerate() {
+        let ct = chunk.chunk_type().to_string();
+        // unknown critical chunks are a violation, see validate
+        if !chunk.chunk_type().is_critical() {
+            if !chunk.chunk_type().is_public() {
+                flag(Some(i), Finding::PrivateChunk(ct.clone()));
+            } else if !KNOWN.contains(&ct.as_str()) {
+                flag(Some(i), Finding::UnknownChunk(ct.clone()));
+            }
+        }
+        let data = match TextKind::from_chunk_type(&ct) {
+            Some(_) => TextChunk::try_from(chunk)
+                .ok()
+                .and_then(|t| BASE64.decode(t.text()).ok())
+                .unwrap_or_default(),
+            None => chunk.data().to_vec(),
+        };
+        if data.starts_with(&PNGECRET_MAGIC) {
+            flag(Some(i), Finding::PngecretData(ct.clone()));
+        }
+        if iend.is_some_and(|end| i > end) {
+            flag(Some(i), Finding::ChunkAfterIend(ct));
+        }
+    }
+
+    if !png.trailing_data().is_empty() {
+        flag(None, Finding::TrailingData(png.trailing_data().len()));
+    }
+    flags
+}
+
+/// number of palette color pairs that are only one level apart, None if there are none
+fn near_duplicates(png: &Png) -> Result<Option<usize>> {
+    let palette = Palette::from_png(png)?;
+    let entries = palette.entries();
+    let n = (0..entries.len())
+        .flat_map(|i| (i + 1..entries.len()).map(move |j| (i, j)))
+        // one level in blue is the smallest distance there is, besides 0
+        .filter(|&(i, j)| (1..=2).contains(&palette::distance(entries[i], entries[j])))
+        .count();
+    Ok((n > 0).then_some(n))
+}
+
+/// flag if most fully transparent pixels have colors of their own
+///
+/// editors clear them or keep the color they had, data hidden in them is noise
+fn transparent_flag(pixels: &Pixels, channels: usize) -> Option<Flag> {
+    let sample_len = pixels.ihdr().bit_depth() as usize / 8;
+    let pixel_len = channels * sample_len;
+    let mut colors: Vec<&[u8]> = pixels
+        .data()
+        .chunks_exact(pixel_len)
+        .filter(|p| p[pixel_len - sample_len..].iter().all(|b| *b == 0))
+        .map(|p| &p[..pixel_len - sample_len])
+        .collect();
+    let transparent = colors.len();
+    colors.sort_unstable();
+    colors.dedup();
+    let varied = colors.len();
+    (transparent >= 16 && varied * 2 > transparent).then_some(Flag {
+        index: None,
+        finding: Finding::VariedTransparentPixels {
+            varied,
+            transparent,
+        },
+    })
+}
+
+/// every sample of channel c in pixels with the given number of channels
+fn samples(pixels: &Pixels, c: usize, channels: usize) -> Vec<i32> {
+    let sample_len = pixels.ihdr().bit_depth() as usize / 8;
+    pixels
+        .data()
+        .chunks_exact(sample_len)
+        .skip(c)
+        .step_by(channels)
+        .map(|s| s.iter().fold(0, |acc, b| acc << 8 | *b as i32))
+        .collect()
+}
+
+/// probability that the low bits of samples are random, from the chi-square attack
+fn chi_square(samples: &[i32], bit_depth: u8) -> Option<f64> {
+    let mut counts = vec![0u64; 1 << bit_depth];
+    for s in samples {
+        counts[*s as usize] += 1;
+    }
+
+    // random low bits make both values of a pair equally common
+    let (mut chi, mut categories) = (0.0, 0);
+    for pair in counts.chunks_exact(2) {
+        let expected = (pair[0] + pair[1]) as f64 / 2.0;
+        if expected >= MIN_EXPECTED {
+            chi += (pair[0] as f64 - expected).powi(2) / expected;
+            categories += 1;
+        }
+    }
+    if categories < 2 {
+        return None;
+    }
+    let freedom = (categories - 1) as f64;
+    Some(1.0 - gamma_p(freedom / 2.0, chi / 2.0))
+}
+
+/// embedding rate estimated by RS analysis
+fn rs_analysis(samples: &[i32]) -> Option<f64> {
+    // flipping swaps 2k and 2k+1, shifted flipping swaps 2k-1 and 2k
+    let flip = |x: i32| x ^ 1;
+    let shifted = |x: i32| ((x + 1) ^ 1) - 1;
+    let smoothness = |g: &[i32]| -> i32 { g.windows(2).map(|w| (w[1] - w[0]).abs()).sum() };
+
+    // share of regular minus share of singular groups, with the mask and shifted
+    let regular_minus_singular = |samples: &[i32]| -> (f64, f64) {
+        let (mut rm, mut sm, mut rn, mut sn) = (0, 0, 0, 0);
+        let groups = samples.chunks_exact(RS_MASK.len());
+        let count = groups.len();
+        for group in groups {
+            let f = smoothness(group);
+            let apply = |op: &dyn Fn(i32) -> i32| -> i32 {
+                let changed: Vec<i32> = group
+                    .iter()
+                    .zip(RS_MASK)
+                    .map(|(x, m)| if m { op(*x) } else { *x })
+                    .collect();
+                smoothness(&changed)
+            };
+            match apply(&flip).cmp(&f) {
+                std::cmp::Ordering::Greater => rm += 1,
+                std::cmp::Ordering::Less => sm += 1,
+                std::cmp::Ordering::Equal => {}
+            }
+            match apply(&shifted).cmp(&f) {
+                std::cmp::Ordering::Greater => rn += 1,
+                std::cmp::Ordering::Less => sn += 1,
+                std::cmp::Ordering::Equal => {}
+            }
+        }
+        let n = count as f64;
+        ((rm - sm) as f64 / n, (rn - sn) as f64 / n)
+    };
+
+    if samples.len() < RS_MASK.len() * 100 {
+        return None;
+    }
+    let (d0, dn0) = regular_minus_singular(samples);
+    let flipped: Vec<i32> = samples.iter().map(|x| flip(*x)).collect();
+    let (d1, dn1) = regular_minus_singular(&flipped);
+
+    // the differences are quadratic in the embedding rate, z is its root closest to 0
+    let a = 2.0 * (d1 + d0);
+    let b = dn0 - dn1 - d1 - 3.0 * d0;
+    let c = d0 - dn0;
+    let z = smaller_root(a, b, c)?;
+    Some((z / (z - 0.5)).clamp(0.0, 1.0))
+}
+
+/// embedding rate estimated by sample pair analysis
+fn sample_pair_analysis(samples: &[i32]) -> Option<f64> {
+    let (mut x, mut y, mut k) = (0u64, 0u64, 0u64);
+    for pair in samples.windows(2) {
+        let (u, v) = (pair[0], pair[1]);
+        if (v % 2 == 0 && u < v) || (v % 2 == 1 && u > v) {
+            x += 1;
+        }
+        if (v % 2 == 0 && u > v) || (v % 2 == 1 && u < v) {
+            y += 1;
+        }
+        // equal, or different in the low bit only
+        if u / 2 == v / 2 {
+            k += 1;
+        }
+    }
+    if k == 0 {
+        return None;
+    }
+    let n = samples.len() as f64 - 1.0;
+    let (x, y, k) = (x as f64, y as f64, k as f64);
+    // k/2 p² + (2x - n) p + y - x = 0, natural images have x ≈ y
+    let a = k / 2.0;
+    let b = 2.0 * x - n;
+    let c = y - x;
+    let disc = b * b - 4.0 * a * c;
+    if disc < 0.0 {
+        return None;
+    }
+    let p = ((-b - disc.sqrt()) / (2.0 * a)).min((-b + disc.sqrt()) / (2.0 * a));
+    Some(p.clamp(0.0, 1.0))
+}
+
+/// root of a z² + b z + c with the smaller absolute value
+fn smaller_root(a: f64, b: f64, c: f64) -> Option<f64> {
+    if a.abs() < f64::EPSILON {
+        return (b.abs() >= f64::EPSILON).then(|| -c / b);
+    }
+    let disc = b * b - 4.0 * a * c;
+    if disc < 0.0 {
+        return None;
+    }
+    let roots = [
+        (-b + disc.sqrt()) / (2.0 * a),
+        (-b - disc.sqrt()) / (2.0 * a),
+    ];
+    Some(if roots[0].abs() <= roots[1].abs() {
+        roots[0]
+    } else {
+        roots[1]
+    })
+}
+
+/// regularized lower incomplete gamma function P(a, x)
+///
+/// a series below a + 1, a continued fraction above (Numerical Recipes 6.2)
+fn gamma_p(a: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    let prefix = (a * x.ln() - x - ln_gamma(a)).exp();
+    if x < a + 1.0 {
+        let (mut term, mut sum, mut n) = (1.0 / a, 1.0 / a, a);
+        while term.abs() > sum.abs() * 1e-15 {
+            n += 1.0;
+            term *= x / n;
+            sum += term;
+        }
+        (sum * prefix).min(1.0)
+    } else {
+        // modified Lentz's method
+        let tiny = 1e-300;
+        let mut b = x + 1.0 - a;
+        let mut c = 1.0 / tiny;
+        let mut d = 1.0 / b;
+        let mut h = d;
+        for i in 1..1000 {
+            let an = -(i as f64) * (i as f64 - a);
+            b += 2.0;
+            d = an * d + b;
+            d = if d.abs() < tiny { tiny } else { d };
+            c = b + an / c;
+            c = if c.abs() < tiny { tiny } else { c };
+            d = 1.0 / d;
+            let delta = d * c;
+            h *= delta;
+            if (delta - 1.0).abs() < 1e-15 {
+                break;
+            }
+        }
+        (1.0 - prefix * h).max(0.0)
+    }
+}
+
+/// natural logarithm of the gamma function (Lanczos approximation)
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 6] = [
+        76.18009172947146,
+        -86.50532032941677,
+        24.01409824083091,
+        -1.231739572450155,
+        0.1208650973866179e-2,
+        -0.5395239384953e-5,
+    ];
+    let tmp = x + 5.5;
+    let tmp = tmp - (x + 0.5) * tmp.ln();
+    let series = COEFFICIENTS
+        .iter()
+        .enumerate()
+        .fold(1.000000000190015, |acc, (i, c)| {
+            acc + c / (x + 1.0 + i as f64)
+        });
+    -tmp + (2.5066282746310005 * series / x).ln()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+    use crate::lsb::{self, LsbOptions};
+    use crate::test_util::{dice, dice_indexed};
+
+    /// pseudo random bytes, like encrypted data
+    fn noise(len: usize) -> Vec<u8> {
+        let mut state = 0x2545f4914f6cdd1du64;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                (state >> 32) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_gamma_p() {
+        // chi-square with 2 degrees of freedom has P(1, x) = 1 - e^-x
+        for x in [0.1f64, 1.0, 3.0, 10.0] {
+            assert!((gamma_p(1.0, x) - (1.0 - (-x).exp())).abs() < 1e-9);
+        }
+        assert!((ln_gamma(5.0) - 24f64.ln()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_clean_and_full() {
+        let mut png = dice();
+        let clean = analyze(&png).unwrap();
+        assert_eq!(clean.channels.len(), 4);
+        assert!(clean.suspicion() < 0.5, "{clean:?}");
+
+        // every low bit of the color channels carries data
+        let mut pixels = Pixels::from_png(&png).unwrap();
+        let capacity = lsb::capacity(&pixels, &LsbOptions::default()).unwrap();
+        lsb::embed(&mut pixels, &noise(capacity), &LsbOptions::default()).unwrap();
+        pixels.write_to(&mut png).unwrap();
+        let full = analyze(&png).unwrap();
+        assert!(full.embedding_rate().unwrap() > 0.5, "{full:?}");
+        assert!(full.suspicion() > 0.9);
+        assert!(full.embedding_rate() > clean.embedding_rate());
+    }
+
+    #[test]
+    fn test_chunk_flags() {
+        let mut png = dice();
+        let options = crate::EncodeOptions {
+            passphrase: Some("passphrase".to_string()),
+            ..Default::default()
+        };
+        crate::encode(&mut png, "ruSt", b"secret", &options).unwrap();
+        let mut bytes = png.as_bytes();
+        bytes.extend_from_slice(b"garbage");
+        let png = Png::try_from(bytes.as_slice()).unwrap();
+
+        let flags: Vec<Finding> = analyze(&png)
+            .unwrap()
+            .flags
+            .into_iter()
+            .map(|f| f.finding)
+            .collect();
+        assert!(flags.contains(&Finding::PrivateChunk("ruSt".to_string())));
+        assert!(flags.contains(&Finding::PngecretData("ruSt".to_string())));
+        assert!(flags.contains(&Finding::TrailingData(7)));
+        // RuSt is critical, which validate already complains about
+        assert!(!flags.iter().any(|f| f.to_string().contains("RuSt")));
+    }
+
+    #[test]
+    fn test_truncated_idat() {
+        let mut png = dice();
+        crate::encode(&mut png, "ruSt", b"secret", &Default::default()).unwrap();
+        let idat = png
+            .chunks()
+            .iter()
+            .position(|c| &c.chunk_type().bytes() == b"IDAT")
+            .unwrap();
+        let data = png.chunks()[idat].data();
+        let truncated = Chunk::new(
+            ChunkType::from_str("IDAT").unwrap(),
+            data[..data.len() / 2].to_vec(),
+        );
+        png.replace_chunk(idat, truncated).unwrap();
+
+        // the chunks are still checked
+        let report = analyze(&png).unwrap();
+        assert!(report.channels.is_empty());
+        assert!(report.skipped.is_some());
+        assert!(
+            report
+                .flags
+                .iter()
+                .any(|f| f.finding == Finding::PrivateChunk("ruSt".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_transparent_pixels() {
+        let mut png = dice();
+        assert!(
+            !analyze(&png)
+                .unwrap()
+                .flags
+                .iter()
+                .any(|f| matches!(f.finding, Finding::VariedTransparentPixels { .. }))
+        );
+
+        let mut pixels = Pixels::from_png(&png).unwrap();
+        let capacity = crate::alpha::capacity(&pixels);
+        crate::alpha::embed(&mut pixels, &noise(capacity), None).unwrap();
+        pixels.write_to(&mut png).unwrap();
+        assert!(
+            analyze(&png)
+                .unwrap()
+                .flags
+                .iter()
+                .any(|f| matches!(f.finding, Finding::VariedTransparentPixels { .. }))
+        );
+    }
+
+    #[test]
+    fn test_indexed() {
+        let mut png = dice_indexed();
+        let report = analyze(&png).unwrap();
+        assert!(report.channels.is_empty());
+        assert!(report.skipped.is_some());
+        assert!(report.flags.is_empty(), "{report:?}");
+
+        // hiding a lot adds colors one level apart to the palette
+        let capacity = palette::capacity(&png).unwrap();
+        palette::embed(&mut png, &noise(capacity), None).unwrap();
+        let flags = analyze(&png).unwrap().flags;
+        assert!(matches!(
+            flags[..],
+            [Flag {
+                finding: Finding::NearDuplicateColors(_),
+                ..
+            }]
+        ));
+    }
+}
@@ -1,6 +1,9 @@
-use std::path::PathBuf;
+use std::{ffi::OsString, path::PathBuf};
 
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use pngecret::crypt::x25519::Recipient;
+use pngecret::lsb::{Channels, MAX_BITS};
+use pngecret::signature::VerifyingKey;
 
 #[derive(Parser)]
 #[command(name = "PNGecret", version, about, long_about = None)]
@@ -22,53 +25,388 @@ pub enum PngecretArgs {
 
     /// Print all chunks in a PNG file
     Print(PrintArgs),
+
+    /// Hide a secret message in the pixels of a PNG file
+    Embed(EmbedArgs),
+
+    /// Extract a secret message from the pixels of a PNG file
+    Extract(ExtractArgs),
+
+    /// Check a PNG file against the chunk rules of the spec
+    Validate(ValidateArgs),
+
+    /// Show how much can be hidden in a PNG file with every method
+    Capacity(CapacityArgs),
+
+    /// Look for signs of hidden data in a PNG file
+    Analyze(AnalyzeArgs),
+
+    /// Create a key pair to encrypt messages to or sign them with
+    Keygen(KeygenArgs),
+
+    /// Sign the pixels of a PNG file, to prove later they weren't changed
+    Sign(SignArgs),
+
+    /// Check the signature of a secret message, or of the image itself, in a PNG file
+    Verify(VerifyArgs),
 }
 
 #[derive(Parser, Debug)]
 pub struct EncodeArgs {
-    /// PNG file to hide message in
+    /// PNG file to hide message in ("-" reads from stdin)
     pub file: PathBuf,
 
     /// Chunk Type where message is hidden  
     /// (for more info, look at the PNG structure doc)
     pub chunk_type: String,
 
-    /// Message to hide
-    pub msg: String,
+    #[command(flatten)]
+    pub payload: PayloadArgs,
+
+    #[command(flatten)]
+    pub seal: SealArgs,
+
+    /// Split the message over multiple chunks with at most this many bytes each
+    #[arg(long, value_name = "BYTES")]
+    pub max_chunk_size: Option<usize>,
+
+    /// Keyword of the text chunk, if the chunk type is tEXt, zTXt or iTXt [default: Comment]
+    #[arg(short, long)]
+    pub keyword: Option<String>,
 
-    /// Encrypt the message using a passphrase
-    #[arg(short, long, value_name = "PASSPHRASE")]
-    pub encrypt: Option<String>,
+    /// Sign the message with the signing key in this file (see keygen --sign)
+    #[arg(long, value_name = "KEY FILE")]
+    pub sign: Option<PathBuf>,
 
-    /// Optional output file
+    /// Sign the critical chunks too, so changes to the image are detected
+    #[arg(long, requires = "sign")]
+    pub sign_critical: bool,
+
+    /// Optional output file ("-" writes to stdout)
     #[arg(short, long, value_name = "OUTPUT FILE")]
     pub output: Option<PathBuf>,
 }
 
 #[derive(Parser, Debug)]
 pub struct DecodeArgs {
-    /// PNG file to decode hidden message from
+    /// PNG file to decode hidden message from ("-" reads from stdin)
     pub file: PathBuf,
 
     /// Type of chunk the message is hidden in
     pub chunk_type: String,
 
-    /// Decrypt the message using a passphrase
-    #[arg(short, long, value_name = "PASSPHRASE")]
-    pub decrypt: Option<String>,
+    #[command(flatten)]
+    pub passphrase: DecryptArgs,
+
+    /// Keyword of the text chunk, if the chunk type is tEXt, zTXt or iTXt [default: Comment]
+    #[arg(short, long)]
+    pub keyword: Option<String>,
+
+    /// Which of the chunks of this type holds the message, counting from 0 [default: the last]
+    #[arg(long)]
+    pub index: Option<usize>,
+
+    /// Only decode the message if it is signed by this public key (can be repeated)
+    #[arg(long, value_name = "PUBLIC KEY")]
+    pub verify_with: Vec<VerifyingKey>,
+
+    /// Save the hidden file, under its original name or the given path ("-" writes to stdout)
+    #[arg(short = 'x', long, value_name = "PATH", num_args = 0..=1)]
+    pub extract: Option<Option<PathBuf>>,
 }
 
 #[derive(Parser, Debug)]
 pub struct RemoveArgs {
-    /// PNG file to remove message from
+    /// PNG file to remove message from ("-" reads from stdin)
     pub file: PathBuf,
 
     /// Type of chunk the message is hidden in
     pub chunk_type: String,
+
+    /// Keyword of the text chunk, if the chunk type is tEXt, zTXt or iTXt [default: Comment]
+    #[arg(short, long)]
+    pub keyword: Option<String>,
+
+    /// Which of the chunks of this type holds the message, counting from 0 [default: the last]
+    #[arg(long)]
+    pub index: Option<usize>,
+
+    /// Remove every chunk of this type
+    #[arg(long, conflicts_with = "index")]
+    pub all: bool,
+
+    /// Optional output file ("-" writes to stdout)
+    #[arg(short, long, value_name = "OUTPUT FILE")]
+    pub output: Option<PathBuf>,
 }
 
 #[derive(Parser, Debug)]
 pub struct PrintArgs {
-    /// PNG file to print
+    /// PNG file to print ("-" reads from stdin)
+    pub file: PathBuf,
+}
+
+#[derive(Parser, Debug)]
+pub struct ValidateArgs {
+    /// PNG file to validate ("-" reads from stdin)
+    pub file: PathBuf,
+}
+
+#[derive(Parser, Debug)]
+pub struct CapacityArgs {
+    /// PNG file to check ("-" reads from stdin)
+    pub file: PathBuf,
+
+    /// Channels used by lsb embedding, any of r, g, b and a
+    #[arg(long, default_value = "rgb")]
+    pub channels: Channels,
+
+    /// Take the overhead of encryption into account
+    #[arg(short, long)]
+    pub encrypted: bool,
+
+    /// Take the overhead of encrypting to this many recipients into account
+    #[arg(long, value_name = "N", conflicts_with = "encrypted")]
+    pub recipients: Option<usize>,
+
+    /// Cipher used to encrypt the message
+    #[arg(long, value_enum, default_value_t)]
+    pub cipher: CipherArg,
+
+    /// Take the overhead of storing a file with this name into account
+    #[arg(long, value_name = "NAME")]
+    pub file_name: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+pub struct AnalyzeArgs {
+    /// PNG file to analyze ("-" reads from stdin)
+    pub file: PathBuf,
+}
+
+#[derive(Parser, Debug)]
+pub struct EmbedArgs {
+    /// PNG file to hide message in ("-" reads from stdin)
+    pub file: PathBuf,
+
+    #[command(flatten)]
+    pub payload: PayloadArgs,
+
+    #[command(flatten)]
+    pub seal: SealArgs,
+
+    #[command(flatten)]
+    pub lsb: LsbArgs,
+
+    /// Hide the message in the color of fully transparent pixels, whole bytes at a time
+    /// (falls back to the low bits if there are too few of them)
+    #[arg(long)]
+    pub transparent: bool,
+
+    /// Change samples by +1 or -1 instead of replacing their low bit (only with --bits 1)
+    #[arg(long)]
+    pub matching: bool,
+
+    /// Show how much the pixels changed and how suspicious they look afterwards
+    #[arg(long)]
+    pub report: bool,
+
+    /// Optional output file ("-" writes to stdout)
+    #[arg(short, long, value_name = "OUTPUT FILE")]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Parser, Debug)]
+pub struct ExtractArgs {
+    /// PNG file to extract hidden message from ("-" reads from stdin)
+    pub file: PathBuf,
+
+    #[command(flatten)]
+    pub lsb: LsbArgs,
+
+    #[command(flatten)]
+    pub passphrase: DecryptArgs,
+
+    #[command(flatten)]
+    pub argon2: Argon2Args,
+
+    /// Save the hidden file, under its original name or the given path ("-" writes to stdout)
+    #[arg(short = 'x', long, value_name = "PATH", num_args = 0..=1)]
+    pub extract: Option<Option<PathBuf>>,
+}
+
+#[derive(Parser, Debug)]
+pub struct KeygenArgs {
+    /// File to write the secret key to, it mustn't exist yet ("-" writes to stdout)
+    #[arg(short, long, value_name = "KEY FILE")]
+    pub output: Option<PathBuf>,
+
+    /// Create a key to sign messages with instead of one to decrypt them
+    #[arg(long)]
+    pub sign: bool,
+}
+
+#[derive(Parser, Debug)]
+pub struct SignArgs {
+    /// PNG file to sign ("-" reads from stdin)
     pub file: PathBuf,
+
+    /// Sign with the signing key in this file (see keygen --sign)
+    #[arg(long, value_name = "KEY FILE")]
+    pub key: PathBuf,
+
+    /// Optional output file ("-" writes to stdout)
+    #[arg(short, long, value_name = "OUTPUT FILE")]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Parser, Debug)]
+pub struct VerifyArgs {
+    /// PNG file with the signed message or image ("-" reads from stdin)
+    pub file: PathBuf,
+
+    /// Type of chunk the message is hidden in, leave it out to check the signature
+    /// of the image created by sign
+    pub chunk_type: Option<String>,
+
+    /// Keyword of the text chunk, if the chunk type is tEXt, zTXt or iTXt [default: Comment]
+    #[arg(short, long)]
+    pub keyword: Option<String>,
+
+    /// Public key the message has to be signed by (can be repeated)
+    #[arg(long, value_name = "PUBLIC KEY", required = true)]
+    pub verify_with: Vec<VerifyingKey>,
+
+    /// Accept a re-encoded image or edited critical chunks as long as the pixels are the same
+    /// (only without a chunk type)
+    #[arg(long)]
+    pub allow_reencoded: bool,
+}
+
+/// what is hidden
+#[derive(Args, Debug)]
+pub struct PayloadArgs {
+    /// Message to hide (any text, or raw bytes, "-" reads from stdin)
+    #[arg(required_unless_present = "payload_file")]
+    pub msg: Option<OsString>,
+
+    /// Hide this file instead of a message ("-" reads from stdin)
+    #[arg(
+        short = 'f',
+        long = "file",
+        value_name = "FILE",
+        conflicts_with = "msg"
+    )]
+    pub payload_file: Option<PathBuf>,
+}
+
+/// how the message is encrypted and compressed before it is hidden
+#[derive(Args, Debug)]
+pub struct SealArgs {
+    /// Encrypt the message, with a passphrase that is asked for (or the given one,
+    /// which ends up in shell history and process listings)
+    #[arg(short, long, value_name = "PASSPHRASE", num_args = 0..=1, group = "key")]
+    pub encrypt: Option<Option<String>>,
+
+    /// Encrypt the message with the passphrase in the first line of this file
+    #[arg(long, value_name = "FILE", group = "key")]
+    pub passphrase_file: Option<PathBuf>,
+
+    /// Encrypt the message with the passphrase in this environment variable
+    #[arg(long, value_name = "VAR", group = "key")]
+    pub passphrase_env: Option<String>,
+
+    /// Encrypt the message to this public key instead of a passphrase (can be repeated)
+    #[arg(short, long = "recipient", value_name = "PUBLIC KEY", group = "key")]
+    pub recipients: Vec<Recipient>,
+
+    /// Cipher used to encrypt the message
+    #[arg(long, value_enum, default_value_t, requires = "key")]
+    pub cipher: CipherArg,
+
+    #[command(flatten)]
+    pub argon2: Argon2Args,
+
+    /// Compress the message before hiding it
+    #[arg(short = 'z', long)]
+    pub compress: bool,
+
+    /// Compression level from 0 (fastest) to 9 (smallest), implies --compress
+    #[arg(long, value_name = "LEVEL", value_parser = clap::value_parser!(u32).range(0..=9))]
+    pub compression_level: Option<u32>,
+}
+
+/// the passphrase a message is decrypted with
+#[derive(Args, Debug)]
+pub struct DecryptArgs {
+    /// Decrypt the message, with a passphrase that is asked for (or the given one,
+    /// which ends up in shell history and process listings)
+    #[arg(short, long, value_name = "PASSPHRASE", num_args = 0..=1, group = "key")]
+    pub decrypt: Option<Option<String>>,
+
+    /// Decrypt the message with the passphrase in the first line of this file
+    #[arg(long, value_name = "FILE", group = "key")]
+    pub passphrase_file: Option<PathBuf>,
+
+    /// Decrypt the message with the passphrase in this environment variable
+    #[arg(long, value_name = "VAR", group = "key")]
+    pub passphrase_env: Option<String>,
+
+    /// Decrypt a message encrypted to recipients with the secret keys in this file
+    /// (can be repeated)
+    #[arg(short, long = "identity", value_name = "KEY FILE", group = "key")]
+    pub identities: Vec<PathBuf>,
+
+    /// Decrypt a message encrypted by an older version of PNGecret (with the Vigenère cipher)
+    #[arg(long)]
+    pub legacy: bool,
+}
+
+/// cost of deriving a key from the passphrase
+#[derive(Args, Debug)]
+pub struct Argon2Args {
+    /// Memory used by Argon2id to derive the key from the passphrase (in KiB)
+    #[arg(long, value_name = "KIB", default_value_t = 19 * 1024, requires = "key")]
+    pub argon2_memory: u32,
+
+    /// Number of Argon2id iterations
+    #[arg(long, value_name = "N", default_value_t = 2, requires = "key")]
+    pub argon2_iterations: u32,
+
+    /// Degree of parallelism of Argon2id
+    #[arg(long, value_name = "N", default_value_t = 1, requires = "key")]
+    pub argon2_parallelism: u32,
+}
+
+/// where in the pixels the message is hidden
+#[derive(Args, Debug)]
+pub struct LsbArgs {
+    /// Channels to hide the message in, any of r, g, b and a
+    /// (in grayscale images r, g and b mean the gray channel)
+    #[arg(long, default_value = "rgb")]
+    pub channels: Channels,
+
+    /// Number of low bits used in every sample
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u8).range(1..=MAX_BITS as i64))]
+    pub bits: u8,
+
+    /// Scatter the message over the image in an order only this key reproduces
+    /// (defaults to the passphrase, without either the samples are used in order)
+    #[arg(long, value_name = "KEY")]
+    pub stego_key: Option<String>,
+
+    /// Use the samples in busy areas of the image first, where changes are harder to detect
+    #[arg(long)]
+    pub adaptive: bool,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default)]
+pub enum CipherArg {
+    /// XChaCha20-Poly1305
+    #[default]
+    #[value(name = "chacha20-poly1305")]
+    ChaCha20Poly1305,
+    /// AES-256-GCM
+    #[value(name = "aes-256-gcm")]
+    Aes256Gcm,
 }
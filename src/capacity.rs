@@ -0,0 +1,171 @@
+//! How much data every hiding method can hold in a png.
+//!
+//! The numbers are what the carrier can hold, the overhead of the payload container and
+//! the encryption envelope ([`crate::secret::overhead`]) still has to be subtracted.
+
+use std::fmt::Display;
+
+use crate::Result;
+use crate::alpha;
+use crate::chunk::Chunk;
+use crate::ihdr::ColorType;
+use crate::lsb::{self, Channels, LsbOptions};
+use crate::palette;
+use crate::pixels::Pixels;
+use crate::png::Png;
+
+/// a way of hiding data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    /// a separate chunk, see [`crate::encode`]
+    Chunk,
+    /// the low bits of every sample, see [`crate::embed_payload`]
+    Lsb { bits: u8 },
+    /// the palette indices of indexed images, see [`crate::palette`]
+    Palette,
+    /// the color of fully transparent pixels, see [`crate::alpha`]
+    Alpha,
+}
+
+impl Display for Method {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Method::Chunk => write!(f, "chunk"),
+            Method::Lsb { bits: 1 } => write!(f, "lsb (1 bit)"),
+            Method::Lsb { bits } => write!(f, "lsb ({bits} bits)"),
+            Method::Palette => write!(f, "palette"),
+            Method::Alpha => write!(f, "alpha"),
+        }
+    }
+}
+
+/// how much one method can hold
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Estimate {
+    pub method: Method,
+    /// bytes the method can hold, None if it doesn't work for this image
+    pub capacity: Option<usize>,
+    /// why the method doesn't work, or what to keep in mind when using it
+    pub note: String,
+}
+
+/// capacity of every method, lsb with 1 to 3 bits in the given channels
+pub fn estimate(png: &Png, channels: Channels) -> Result<Vec<Estimate>> {
+    let ihdr = png.ihdr()?;
+    let pixels = Pixels::from_png(png)?;
+    let image_data: usize = png
+        .chunks()
+        .iter()
+        .filter(|c| &c.chunk_type().bytes() == b"IDAT")
+        .map(|c| c.length() as usize)
+        .sum();
+
+    let mut estimates = vec![Estimate {
+        method: Method::Chunk,
+        capacity: Some(Chunk::MAX_LENGTH),
+        note: format!(
+            "more if split over multiple chunks, but chunks bigger than the image data ({image_data} bytes) stand out"
+        ),
+    }];
+
+    for bits in 1..=3 {
+        let options = LsbOptions {
+            channels,
+            bits,
+            ..Default::default()
+        };
+        let (capacity, note) = match lsb::capacity(&pixels, &options) {
+            Ok(capacity) => (Some(capacity), format!("in the {channels} channels")),
+            Err(e) => (None, e.to_string()),
+        };
+        estimates.push(Estimate {
+            method: Method::Lsb { bits },
+            capacity,
+            note,
+        });
+    }
+
+    estimates.push(match ihdr.color_type() {
+        // one bit per pixel with a look-alike color in the palette
+        ColorType::Indexed => match palette::capacity(png) {
+            Ok(capacity) => Estimate {
+                method: Method::Palette,
+                capacity: Some(capacity),
+                note: "one bit per pixel with a look-alike color in the palette".to_string(),
+            },
+            Err(e) => Estimate {
+                method: Method::Palette,
+                capacity: None,
+                note: e.to_string(),
+            },
+        },
+        _ => Estimate {
+            method: Method::Palette,
+            capacity: None,
+            note: "image has no palette".to_string(),
+        },
+    });
+
+    estimates.push(if ihdr.color_type().has_alpha() {
+        // the color samples of invisible pixels can be anything
+        Estimate {
+            method: Method::Alpha,
+            capacity: Some(alpha::capacity(&pixels)),
+            note: format!(
+                "{} fully transparent pixels, falls back to lsb if too few",
+                alpha::transparent_pixels(&pixels)
+            ),
+        }
+    } else {
+        Estimate {
+            method: Method::Alpha,
+            capacity: None,
+            note: "image has no alpha channel".to_string(),
+        }
+    });
+
+    Ok(estimates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{dice, dice_indexed};
+
+    #[test]
+    fn test_estimate_dice() {
+        let png = dice();
+        let estimates = estimate(&png, Channels::default()).unwrap();
+        let methods: Vec<String> = estimates.iter().map(|e| e.method.to_string()).collect();
+        assert_eq!(
+            methods,
+            [
+                "chunk",
+                "lsb (1 bit)",
+                "lsb (2 bits)",
+                "lsb (3 bits)",
+                "palette",
+                "alpha"
+            ]
+        );
+
+        // 50x50 pixels with 3 color channels
+        for (bits, estimate) in (1..=3).zip(&estimates[1..4]) {
+            assert_eq!(estimate.capacity, Some(50 * 50 * 3 * bits / 8 - 9));
+        }
+        assert_eq!(estimates[4].capacity, None);
+        assert!(estimates[5].capacity.is_some());
+    }
+
+    #[test]
+    fn test_estimate_indexed() {
+        let png = dice_indexed();
+        let estimates = estimate(&png, Channels::default()).unwrap();
+        assert!(estimates[1..4].iter().all(|e| e.capacity.is_none()));
+        assert_eq!(
+            estimates[4].capacity,
+            Some(crate::palette::capacity(&png).unwrap())
+        );
+        assert!(estimates[4].capacity.unwrap() > 0);
+    }
+}
@@ -6,6 +6,8 @@ use std::{
 };
 
 use crate::chunk_type::ChunkType;
+use crate::ihdr::Ihdr;
+use crate::text_chunk::TextChunk;
 
 const CRC_PNG: Crc<u32> = Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
 
@@ -20,23 +22,35 @@ pub struct Chunk {
 }
 
 impl Chunk {
+    /// maximum length of the data of a chunk, see section 5.3 of the PNG spec
+    pub const MAX_LENGTH: usize = (1 << 31) - 1;
+
+    /// create a new chunk
+    ///
+    /// # Panics
+    ///
+    /// if data is longer than [`Chunk::MAX_LENGTH`], use [`Chunk::try_new`] if it could be
     pub fn new(chunk_type: ChunkType, data: Vec<u8>) -> Chunk {
-        // NOTE: if the message is longer than 4.2 million characters this will panic
-        // but unless you are going to encode War and Peace ~1.4 times this shouldn't happen
-        let length = data
-            .len()
-            .try_into()
-            .expect("Your message is too long, what the hell are you trying to hide");
+        Chunk::try_new(chunk_type, data)
+            .expect("Your message is too long, what the hell are you trying to hide")
+    }
+
+    /// create a new chunk, failing if data is longer than [`Chunk::MAX_LENGTH`]
+    pub fn try_new(chunk_type: ChunkType, data: Vec<u8>) -> crate::Result<Chunk> {
+        if data.len() > Chunk::MAX_LENGTH {
+            return Err(ChunkError::TooLong(data.len()).into());
+        }
+        let length = data.len() as u32;
         let mut msg = chunk_type.bytes().to_vec();
         msg.extend_from_slice(&data);
         let crc = CRC_PNG.checksum(&msg);
 
-        Chunk {
+        Ok(Chunk {
             length,
             chunk_type,
             data,
             crc,
-        }
+        })
     }
 
     pub fn length(&self) -> u32 {
@@ -75,6 +89,10 @@ impl TryFrom<&[u8]> for Chunk {
     type Error = crate::Error;
 
     fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        // length, type and crc take 12 bytes, anything less can't be a chunk
+        if bytes.len() < 12 {
+            return Err(ChunkError::TooShort(bytes.len()).into());
+        }
         let mut reader = BufReader::new(bytes);
 
         // first 4 bytes should be length
@@ -91,16 +109,26 @@ impl TryFrom<&[u8]> for Chunk {
         let mut rest_buf = vec![];
         reader.read_to_end(&mut rest_buf)?;
         let data = rest_buf[..rest_buf.len() - 4].to_vec();
-        let crc = <u32>::from_be_bytes(rest_buf[rest_buf.len() - 4..].try_into()?);
+        let mut crc_buf = [0; 4];
+        crc_buf.copy_from_slice(&rest_buf[rest_buf.len() - 4..]);
+        let crc = <u32>::from_be_bytes(crc_buf);
 
         // create new chunk to see if length and CRC checksum are correct
         // the chunk type is already valid here, since the try_from succeeded
-        let chunk = Chunk::new(chunk_type, data);
+        let chunk = Chunk::try_new(chunk_type, data)?;
         if length != chunk.length() {
-            return Err(Box::new(ChunkError::BadLength(length)));
+            return Err(ChunkError::BadLength {
+                declared: length,
+                actual: chunk.length(),
+            }
+            .into());
         }
         if crc != chunk.crc() {
-            return Err(Box::new(ChunkError::BadChecksum(crc)));
+            return Err(ChunkError::BadChecksum {
+                stored: crc,
+                computed: chunk.crc(),
+            }
+            .into());
         }
 
         Ok(chunk)
@@ -109,6 +137,13 @@ impl TryFrom<&[u8]> for Chunk {
 
 impl Display for Chunk {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // text chunks are shown decoded (keyword, language and decompressed text)
+        if let Ok(text) = TextChunk::try_from(self) {
+            return write!(f, "{}\t{}", self.chunk_type, text);
+        }
+        if let Ok(ihdr) = Ihdr::try_from(self) {
+            return write!(f, "{}\t{}", self.chunk_type, ihdr);
+        }
         write!(
             f,
             "{}\t{}",
@@ -119,16 +154,37 @@ impl Display for Chunk {
 }
 
 #[derive(Debug)]
-enum ChunkError {
-    BadLength(u32),
-    BadChecksum(u32),
+#[non_exhaustive]
+pub enum ChunkError {
+    // length field doesn't match the actual length of the data
+    BadLength { declared: u32, actual: u32 },
+    // crc field doesn't match the crc calculated from type and data
+    BadChecksum { stored: u32, computed: u32 },
+    // fewer bytes than length, type and crc need
+    TooShort(usize),
+    // data is longer than Chunk::MAX_LENGTH
+    TooLong(usize),
 }
 
 impl Display for ChunkError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            ChunkError::BadLength(l) => write!(f, "Chunk has an incorrect length: {l}"),
-            ChunkError::BadChecksum(s) => write!(f, "Chunk has an incorrect checksum: {s}"),
+            ChunkError::BadLength { declared, actual } => write!(
+                f,
+                "Chunk has an incorrect length: {declared} (data is {actual} bytes long)"
+            ),
+            ChunkError::BadChecksum { stored, computed } => write!(
+                f,
+                "Chunk has an incorrect checksum: {stored} (expected {computed})"
+            ),
+            ChunkError::TooShort(l) => {
+                write!(f, "Chunk is only {l} bytes long, but needs at least 12")
+            }
+            ChunkError::TooLong(l) => write!(
+                f,
+                "Chunk data is {l} bytes long, but can be at most {} bytes long",
+                Chunk::MAX_LENGTH
+            ),
         }
     }
 }
@@ -170,6 +226,16 @@ mod tests {
         assert_eq!(chunk.crc(), 2882656334);
     }
 
+    #[test]
+    fn test_try_new_too_long() {
+        let chunk_type = ChunkType::from_str("RuSt").unwrap();
+        let chunk = Chunk::try_new(chunk_type, vec![0; Chunk::MAX_LENGTH + 1]);
+        assert!(matches!(
+            chunk,
+            Err(crate::Error::Chunk(ChunkError::TooLong(_)))
+        ));
+    }
+
     #[test]
     fn test_chunk_length() {
         let chunk = testing_chunk();
@@ -241,7 +307,23 @@ mod tests {
 
         let chunk = Chunk::try_from(chunk_data.as_ref());
 
-        assert!(chunk.is_err());
+        assert!(matches!(
+            chunk,
+            Err(crate::Error::Chunk(ChunkError::BadChecksum {
+                stored: 2882656333,
+                computed: 2882656334
+            }))
+        ));
+    }
+
+    #[test]
+    fn test_too_short_chunk_from_bytes() {
+        let chunk = Chunk::try_from([0, 0, 0, 0, 82, 117, 83, 116].as_ref());
+
+        assert!(matches!(
+            chunk,
+            Err(crate::Error::Chunk(ChunkError::TooShort(8)))
+        ));
     }
 
     #[test]
@@ -264,4 +346,13 @@ mod tests {
 
         let _chunk_string = format!("{}", chunk);
     }
+
+    #[test]
+    fn test_text_chunk_display() {
+        let chunk = crate::TextChunk::compressed("Comment", "squeezed")
+            .unwrap()
+            .to_chunk()
+            .unwrap();
+        assert_eq!(chunk.to_string(), "zTXt\tComment: squeezed");
+    }
 }
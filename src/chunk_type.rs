@@ -1,6 +1,6 @@
 use std::{error::Error, fmt::Display, str::FromStr};
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ChunkType {
     bytes: [u8; 4],
 }
@@ -49,7 +49,7 @@ impl TryFrom<[u8; 4]> for ChunkType {
     fn try_from(bytes: [u8; 4]) -> Result<Self, Self::Error> {
         for b in bytes {
             if !b.is_ascii_alphabetic() {
-                return Err(Box::new(ChunkTypeError::NonAsciiAlphabeticByte(b)));
+                return Err(ChunkTypeError::NonAsciiAlphabeticByte(b).into());
             }
         }
         Ok(ChunkType { bytes })
@@ -60,18 +60,12 @@ impl FromStr for ChunkType {
     type Err = crate::Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s.len() != 4 {
-            return Err(Box::new(ChunkTypeError::BadLength(s.len())));
-        }
-        let bytes = s.as_bytes();
-        for b in bytes {
-            if !b.is_ascii_alphabetic() {
-                return Err(Box::new(ChunkTypeError::NonAsciiAlphabeticByte(*b)));
-            }
-        }
-        Ok(ChunkType {
-            bytes: bytes.try_into()?,
-        })
+        // conversion to [u8; 4] only fails if the type isn't 4 bytes long
+        let bytes: [u8; 4] = s
+            .as_bytes()
+            .try_into()
+            .map_err(|_| ChunkTypeError::BadLength(s.len()))?;
+        ChunkType::try_from(bytes)
     }
 }
 
@@ -83,7 +77,8 @@ impl Display for ChunkType {
 
 // Error type for nicer error messages
 #[derive(Debug)]
-enum ChunkTypeError {
+#[non_exhaustive]
+pub enum ChunkTypeError {
     // byte is not an ascii alphabetic character
     NonAsciiAlphabeticByte(u8),
     // type is not 4 ascii characters long
@@ -1,86 +1,650 @@
-use std::fs::{read, write};
-use std::str::FromStr;
+use std::env;
+use std::fs::{File, OpenOptions, read, read_to_string, write};
+use std::io::{self, IsTerminal, Read, Write, stdin, stdout};
+use std::path::{Path, PathBuf};
 
-use crate::Result;
-use crate::args::{DecodeArgs, EncodeArgs, PrintArgs, RemoveArgs};
-use crate::chunk::Chunk;
-use crate::chunk_type::ChunkType;
-use crate::crypt::{decrypt, encrypt};
-use crate::png::Png;
+use pngecret::capacity::Method;
+use pngecret::crypt::x25519::Identity;
+use pngecret::crypt::{Cipher, Kdf};
+use pngecret::lsb::StegoKey;
+use pngecret::pixels::Pixels;
+use pngecret::png::PngError;
+use pngecret::signature::{Digest, SignatureError, SigningKey, VerifyingKey};
+use pngecret::validate::Severity;
+use pngecret::{DecodeOptions, EncodeOptions, LsbOptions, Payload, Png, Result};
+
+use crate::args::{
+    AnalyzeArgs, Argon2Args, CapacityArgs, CipherArg, DecodeArgs, DecryptArgs, EmbedArgs,
+    EncodeArgs, ExtractArgs, KeygenArgs, LsbArgs, PayloadArgs, PrintArgs, RemoveArgs, SealArgs,
+    SignArgs, ValidateArgs, VerifyArgs,
+};
 
 pub fn encode(args: EncodeArgs) -> Result<()> {
+    // read the message first, it fails early if stdin is used twice
+    let payload = read_payload(args.payload, is_stdio(&args.file))?;
+
+    // read file as bytes and turn it into PNG struct
+    let mut png = read_png(&args.file)?;
+
+    // hide (optionally encrypted) message in a new chunk
+    let signing_key = match &args.sign {
+        Some(path) => Some(SigningKey::parse_file(&read_to_string(path)?)?),
+        None => None,
+    };
+    let options = EncodeOptions {
+        max_chunk_size: args.max_chunk_size,
+        keyword: args.keyword,
+        signing_key,
+        sign_critical_chunks: args.sign_critical,
+        ..encode_options(args.seal)?
+    };
+    pngecret::encode_payload(&mut png, &args.chunk_type, &payload, &options)?;
+
+    // save modified png into file
+    write_output(&args.output.unwrap_or(args.file), &png.as_bytes())?;
+
+    Ok(())
+}
+
+pub fn decode(args: DecodeArgs) -> Result<()> {
     // read file as bytes and turn it into PNG struct
-    let img_bytes = read(&args.file)?;
-    let mut png = Png::try_from(img_bytes.as_slice())?;
+    let png = read_png(&args.file)?;
+
+    // find chunk in png and get the (optionally decrypted) payload
+    let options = DecodeOptions {
+        keyword: args.keyword,
+        index: args.index,
+        trusted_keys: args.verify_with,
+        ..decode_options(args.passphrase)?
+    };
+    let Some(payload) = pngecret::decode_payload(&png, &args.chunk_type, &options)? else {
+        eprintln!("No chunk of given type found!");
+        return Ok(());
+    };
+
+    write_payload(&payload, args.extract)
+}
 
-    // optionally encrypt the message
-    let msg;
-    if let Some(pass) = args.encrypt {
-        msg = encrypt(&args.msg, &pass)?;
+pub fn remove(args: RemoveArgs) -> Result<()> {
+    // read file as bytes and turn it into PNG struct
+    let mut png = read_png(&args.file)?;
+
+    // find the chunks holding the message (or all of this type) and remove them
+    let options = DecodeOptions {
+        keyword: args.keyword,
+        index: args.index,
+        ..Default::default()
+    };
+    let removed = if args.all {
+        pngecret::remove_all(&mut png, &args.chunk_type, &options)?
     } else {
-        msg = args.msg;
+        pngecret::remove(&mut png, &args.chunk_type, &options)?
+    };
+    for chunk in removed {
+        eprintln!("Removed chunk: {}", chunk);
     }
 
-    // build new chunk
-    let chunk_type = ChunkType::from_str(&args.chunk_type)?;
-    let chunk = Chunk::new(chunk_type, msg.as_bytes().to_vec());
+    // write changes
+    write_output(&args.output.unwrap_or(args.file), &png.as_bytes())?;
+
+    Ok(())
+}
+
+pub fn print(args: PrintArgs) -> Result<()> {
+    // read file as bytes and turn it into PNG struct
+    let png = read_png(&args.file)?;
+
+    // print chunks
+    println!("{}", png);
+
+    Ok(())
+}
 
-    // append chunk to png
-    png.append_chunk(chunk);
+pub fn embed(args: EmbedArgs) -> Result<()> {
+    // read the message first, it fails early if stdin is used twice
+    let payload = read_payload(args.payload, is_stdio(&args.file))?;
+
+    // read file as bytes and turn it into PNG struct
+    let mut png = read_png(&args.file)?;
+
+    // hide (optionally encrypted) message in the pixels
+    let options = encode_options(args.seal)?;
+    let lsb_options = LsbOptions {
+        transparent: args.transparent,
+        matching: args.matching,
+        ..lsb_options(args.lsb, options.passphrase.as_deref(), options.kdf, &png)?
+    };
+    let original = args.report.then(|| png.as_bytes());
+    let method = pngecret::embed_payload(&mut png, &payload, &options, &lsb_options)?;
+    if args.transparent && method != Method::Alpha {
+        eprintln!("Too few fully transparent pixels, fell back to {method}");
+    }
+    if let Some(original) = original {
+        report_distortion(&Png::try_from(original.as_slice())?, &png)?;
+    }
 
     // save modified png into file
-    if let Some(f) = args.output {
-        write(f, png.as_bytes())?;
-    } else {
-        write(args.file, png.as_bytes())?;
+    write_output(&args.output.unwrap_or(args.file), &png.as_bytes())?;
+
+    Ok(())
+}
+
+/// how much embedding changed the pixels, and how suspicious they look now
+///
+/// printed to stderr, stdout may be the image
+fn report_distortion(original: &Png, changed: &Png) -> Result<()> {
+    let distortion = Pixels::from_png(original)?.distortion(&Pixels::from_png(changed)?);
+    eprintln!(
+        "Changed samples: {} of {} ({:.2}%)",
+        distortion.changed,
+        distortion.samples,
+        distortion.changed as f64 * 100.0 / distortion.samples.max(1) as f64
+    );
+    if let Some(mse) = distortion.mse {
+        let psnr = match distortion.psnr() {
+            Some(psnr) => format!("{psnr:.1} dB"),
+            None => "infinite".to_string(),
+        };
+        eprintln!("MSE: {mse:.4}, PSNR: {psnr}");
+    }
+    let before = pngecret::analyze::analyze(original)?;
+    let after = pngecret::analyze::analyze(changed)?;
+    if before.skipped.is_none() {
+        eprintln!(
+            "Suspicion: {:.2} before, {:.2} after (see pngecret analyze)",
+            before.suspicion(),
+            after.suspicion()
+        );
+    }
+    Ok(())
+}
+
+pub fn extract(args: ExtractArgs) -> Result<()> {
+    // read file as bytes and turn it into PNG struct
+    let png = read_png(&args.file)?;
+
+    // read the (optionally decrypted) payload from the pixels
+    let options = decode_options(args.passphrase)?;
+    let lsb_options = lsb_options(
+        args.lsb,
+        options.passphrase.as_deref(),
+        kdf(args.argon2),
+        &png,
+    )?;
+    let mut payload = pngecret::extract_payload(&png, &options, &lsb_options)?;
+    // messages embedded before the samples were shuffled by the passphrase are still in order
+    if payload.is_none() && lsb_options.key.is_some() {
+        let in_order = LsbOptions {
+            key: None,
+            ..lsb_options
+        };
+        payload = pngecret::extract_payload(&png, &options, &in_order)?;
+    }
+    let Some(payload) = payload else {
+        eprintln!("No message found in the pixels!");
+        return Ok(());
+    };
+
+    write_payload(&payload, args.extract)
+}
+
+pub fn validate(args: ValidateArgs) -> Result<()> {
+    // read file as bytes and turn it into PNG struct
+    let png = read_png(&args.file)?;
+
+    // print every violation, but only fail on errors
+    let violations = png.validate();
+    for v in &violations {
+        println!("{v}");
+    }
+    let errors = violations
+        .iter()
+        .filter(|v| v.severity() == Severity::Error)
+        .count();
+    if errors > 0 {
+        return Err(PngError::Invalid(errors).into());
     }
+    println!("PNG is valid");
 
     Ok(())
 }
 
-pub fn decode(args: DecodeArgs) -> Result<()> {
+pub fn capacity(args: CapacityArgs) -> Result<()> {
     // read file as bytes and turn it into PNG struct
-    let img_bytes = read(&args.file)?;
-    let png = Png::try_from(img_bytes.as_slice())?;
-
-    // find chunk in png and print data
-    if let Some(chunk) = png.chunk_by_type(&args.chunk_type) {
-        // decrypt with passphrase if given
-        let msg = chunk.data_as_string()?;
-        if let Some(pass) = args.decrypt {
-            println!("{}", decrypt(&msg, &pass)?);
-        } else {
-            println!("{msg}");
+    let png = read_png(&args.file)?;
+
+    // the same payload and options encode would use, only without data
+    let payload = match &args.file_name {
+        Some(name) => Payload::file(name, Vec::new())?,
+        None => Payload::message(Vec::new()),
+    };
+    let options = EncodeOptions {
+        passphrase: args.encrypted.then(String::new),
+        recipients: vec![Identity::generate().recipient(); args.recipients.unwrap_or(0)],
+        cipher: cipher(args.cipher),
+        ..Default::default()
+    };
+    let overhead = pngecret::secret::overhead(&payload, &options);
+
+    println!("Image: {}", png.ihdr()?);
+    println!("Overhead: {overhead} bytes");
+    for estimate in pngecret::capacity::estimate(&png, args.channels)? {
+        match estimate.capacity {
+            Some(capacity) => println!(
+                "{:<14}{:>12} bytes   {}",
+                estimate.method.to_string(),
+                capacity.saturating_sub(overhead),
+                estimate.note
+            ),
+            None => println!(
+                "{:<14}{:>12}         {}",
+                estimate.method.to_string(),
+                "-",
+                estimate.note
+            ),
         }
+    }
+
+    Ok(())
+}
+
+pub fn analyze(args: AnalyzeArgs) -> Result<()> {
+    // read file as bytes and turn it into PNG struct
+    let png = read_png(&args.file)?;
+
+    let report = pngecret::analyze::analyze(&png)?;
+    let percent = |v: Option<f64>| match v {
+        Some(v) => format!("{:.1}%", v * 100.0),
+        None => "-".to_string(),
+    };
+
+    println!("Image: {}", png.ihdr()?);
+    match &report.skipped {
+        Some(reason) => println!("Pixels not analyzed: {reason}"),
+        None => {
+            println!(
+                "{:<10}{:>12}{:>10}{:>14}",
+                "channel", "chi-square", "RS", "sample pairs"
+            );
+            for c in &report.channels {
+                println!(
+                    "{:<10}{:>12}{:>10}{:>14}",
+                    c.name,
+                    percent(c.chi_square),
+                    percent(c.rs),
+                    percent(c.sample_pairs)
+                );
+            }
+            println!(
+                "Estimated embedding rate: {}",
+                percent(report.embedding_rate())
+            );
+            let suspicion = report.suspicion();
+            let level = match suspicion {
+                s if s < 0.3 => "low",
+                s if s < 0.7 => "medium",
+                _ => "high",
+            };
+            println!("Suspicion: {suspicion:.2} ({level})");
+        }
+    }
+    for flag in &report.flags {
+        println!("Flag: {flag}");
+    }
+
+    Ok(())
+}
+
+pub fn keygen(args: KeygenArgs) -> Result<()> {
+    let (secret, public) = if args.sign {
+        let key = SigningKey::generate();
+        (key.to_string(), key.verifying_key().to_string())
     } else {
-        println!("No chunk of given type found!");
+        let identity = Identity::generate();
+        (identity.to_string(), identity.recipient().to_string())
+    };
+    let key_file = format!("# public key: {public}\n{secret}\n");
+
+    match args.output {
+        Some(path) if !is_stdio(&path) => {
+            // only we may read the secret key, and an existing one is never overwritten
+            let mut options = OpenOptions::new();
+            options.write(true).create_new(true);
+            #[cfg(unix)]
+            std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+            let mut f = options.open(&path).map_err(|e| match e.kind() {
+                io::ErrorKind::AlreadyExists => io::Error::new(
+                    e.kind(),
+                    format!(
+                        "{} already exists, keys are never overwritten",
+                        path.display()
+                    ),
+                ),
+                _ => e,
+            })?;
+            f.write_all(key_file.as_bytes())?;
+            eprintln!("Public key: {public}");
+        }
+        _ => write_output(Path::new("-"), key_file.as_bytes())?,
     }
 
     Ok(())
 }
 
-pub fn remove(args: RemoveArgs) -> Result<()> {
+pub fn sign(args: SignArgs) -> Result<()> {
     // read file as bytes and turn it into PNG struct
-    let img_bytes = read(&args.file)?;
-    let mut png = Png::try_from(img_bytes.as_slice())?;
+    let mut png = read_png(&args.file)?;
 
-    // find chunk in png and remove it
-    let removed_chunk = png.remove_first_chunk(&args.chunk_type)?;
-    println!("Removed chunk: {}", removed_chunk);
+    // sign the pixels and critical chunks
+    let key = SigningKey::parse_file(&read_to_string(&args.key)?)?;
+    pngecret::provenance::sign(&mut png, &key)?;
 
-    // write changes
-    write(args.file, png.as_bytes())?;
+    // save signed png into file
+    write_output(&args.output.unwrap_or(args.file), &png.as_bytes())?;
 
     Ok(())
 }
 
-pub fn print(args: PrintArgs) -> Result<()> {
+pub fn verify(args: VerifyArgs) -> Result<()> {
     // read file as bytes and turn it into PNG struct
-    let img_bytes = read(&args.file)?;
-    let png = Png::try_from(img_bytes.as_slice())?;
+    let png = read_png(&args.file)?;
 
-    // print chunks
-    println!("{}", png);
+    // fails if the signature is missing, invalid or from someone else
+    let Some(chunk_type) = args.chunk_type else {
+        return verify_image(&png, &args.verify_with, args.allow_reencoded);
+    };
+    let options = DecodeOptions {
+        keyword: args.keyword,
+        trusted_keys: args.verify_with,
+        ..Default::default()
+    };
+    let signer = pngecret::verify(&png, &chunk_type, &options)?;
+    println!("Signature is valid, signed by {signer}");
+
+    Ok(())
+}
+
+/// report what changed since the image was signed, only failing if the pixels changed
+fn verify_image(png: &Png, trusted: &[VerifyingKey], allow_reencoded: bool) -> Result<()> {
+    let report = pngecret::provenance::verify(png, trusted)?;
+    let changed = |c: bool| if c { "changed" } else { "unchanged" };
+    println!("Signature is valid, signed by {}", report.signer);
+    println!("Pixels: {}", changed(report.pixels_changed));
+    println!(
+        "Critical chunks: {}",
+        changed(report.critical_chunks_changed)
+    );
+
+    let mut changed = Vec::new();
+    if report.pixels_changed {
+        changed.push(Digest::Pixels);
+    }
+    if report.critical_chunks_changed {
+        if report.pixels_changed || !allow_reencoded {
+            changed.push(Digest::CriticalChunks);
+        } else {
+            println!(
+                "The pixels are the same, but the image was re-encoded or its critical chunks were edited"
+            );
+        }
+    }
+    if !changed.is_empty() {
+        return Err(SignatureError::Changed(changed).into());
+    }
+
+    Ok(())
+}
+
+/// options to compress and encrypt the message with
+fn encode_options(args: SealArgs) -> Result<EncodeOptions> {
+    let passphrase = passphrase(
+        args.encrypt,
+        args.passphrase_file,
+        args.passphrase_env,
+        true,
+    )?;
+    Ok(EncodeOptions {
+        passphrase,
+        recipients: args.recipients,
+        cipher: cipher(args.cipher),
+        kdf: kdf(args.argon2),
+        compression_level: match (args.compress, args.compression_level) {
+            (_, Some(level)) => Some(level),
+            (true, None) => Some(6),
+            (false, None) => None,
+        },
+        ..Default::default()
+    })
+}
+
+/// passphrase or identities to decrypt the message with
+fn decode_options(args: DecryptArgs) -> Result<DecodeOptions> {
+    let mut identities = Vec::new();
+    for path in args.identities {
+        let found = Identity::parse_file(&read_to_string(&path)?)?;
+        if found.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("No secret key found in {}", path.display()),
+            )
+            .into());
+        }
+        identities.extend(found);
+    }
+
+    Ok(DecodeOptions {
+        passphrase: passphrase(
+            args.decrypt,
+            args.passphrase_file,
+            args.passphrase_env,
+            false,
+        )?,
+        identities,
+        legacy: args.legacy,
+        ..Default::default()
+    })
+}
+
+/// the passphrase from the argument, a file, an environment variable or the terminal
+///
+/// a passphrase that is asked for has to be typed twice if confirm is set
+fn passphrase(
+    arg: Option<Option<String>>,
+    file: Option<PathBuf>,
+    env: Option<String>,
+    confirm: bool,
+) -> Result<Option<String>> {
+    let passphrase = match (arg, file, env) {
+        (Some(Some(passphrase)), _, _) => {
+            eprintln!(
+                "Warning: passphrases given as an argument end up in shell history and process listings, leave it out to be asked for it"
+            );
+            passphrase
+        }
+        (Some(None), _, _) => {
+            let passphrase = prompt("Passphrase: ")?;
+            if confirm && prompt("Confirm passphrase: ")? != passphrase {
+                return Err(
+                    io::Error::new(io::ErrorKind::InvalidInput, "Passphrases don't match").into(),
+                );
+            }
+            passphrase
+        }
+        (None, Some(path), _) => {
+            // only the first line, editors like to add a newline at the end
+            let content = read_to_string(path)?;
+            content.lines().next().unwrap_or_default().to_string()
+        }
+        (None, None, Some(var)) => env::var(&var).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Environment variable {var} is not set or not valid unicode"),
+            )
+        })?,
+        (None, None, None) => return Ok(None),
+    };
+
+    if passphrase.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Passphrase is empty").into());
+    }
+    Ok(Some(passphrase))
+}
+
+fn cipher(arg: CipherArg) -> Cipher {
+    match arg {
+        CipherArg::ChaCha20Poly1305 => Cipher::XChaCha20Poly1305,
+        CipherArg::Aes256Gcm => Cipher::Aes256Gcm,
+    }
+}
+
+fn kdf(args: Argon2Args) -> Kdf {
+    Kdf::Argon2id {
+        memory: args.argon2_memory,
+        iterations: args.argon2_iterations,
+        parallelism: args.argon2_parallelism,
+    }
+}
+
+/// where to hide the message in png, shuffled by the stego key or else the passphrase
+///
+/// a reused passphrase is derived with the kdf that encrypts the message
+fn lsb_options(args: LsbArgs, passphrase: Option<&str>, kdf: Kdf, png: &Png) -> Result<LsbOptions> {
+    let key = match (args.stego_key.as_deref(), passphrase) {
+        (Some(key), _) => Some(StegoKey::derive(key, &png.ihdr()?, Kdf::default())?),
+        (None, Some(passphrase)) => Some(StegoKey::derive(passphrase, &png.ihdr()?, kdf)?),
+        (None, None) => None,
+    };
+    Ok(LsbOptions {
+        channels: args.channels,
+        bits: args.bits,
+        key,
+        adaptive: args.adaptive,
+        ..Default::default()
+    })
+}
+
+/// true if path means stdin or stdout
+fn is_stdio(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+/// read the file at path, or stdin if path is "-"
+fn read_input(path: &Path) -> Result<Vec<u8>> {
+    if is_stdio(path) {
+        let mut data = Vec::new();
+        stdin().read_to_end(&mut data)?;
+        return Ok(data);
+    }
+    Ok(read(path)?)
+}
+
+/// write data to the file at path, or stdout if path is "-"
+fn write_output(path: &Path, data: &[u8]) -> Result<()> {
+    if is_stdio(path) {
+        let mut out = stdout().lock();
+        out.write_all(data)?;
+        out.flush()?;
+        return Ok(());
+    }
+    Ok(write(path, data)?)
+}
+
+fn read_png(path: &Path) -> Result<Png> {
+    let img_bytes = read_input(path)?;
+    Png::try_from(img_bytes.as_slice())
+}
+
+/// the message, or the file to hide
+///
+/// the message is read from stdin if it (or the file) is "-", unless the png came from there
+fn read_payload(args: PayloadArgs, png_from_stdin: bool) -> Result<Payload> {
+    let from_stdin = match (&args.payload_file, &args.msg) {
+        (Some(path), _) => is_stdio(path),
+        (None, Some(msg)) => msg == "-",
+        (None, None) => false,
+    };
+    if from_stdin {
+        if png_from_stdin {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "The PNG is already read from stdin, the message can't be read from there too",
+            )
+            .into());
+        }
+        return Ok(Payload::message(read_input(Path::new("-"))?));
+    }
+
+    match (args.payload_file, args.msg) {
+        (Some(path), _) => {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            Payload::file(&name, read(&path)?)
+        }
+        (None, Some(msg)) => Ok(Payload::message(msg.into_encoded_bytes())),
+        // clap makes sure one of them is given
+        (None, None) => unreachable!(),
+    }
+}
+
+/// ask for a passphrase on the terminal without echoing it
+fn prompt(text: &str) -> io::Result<String> {
+    rpassword::prompt_password(text).map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!(
+                "Can't ask for the passphrase ({e}), use --passphrase-file or --passphrase-env"
+            ),
+        )
+    })
+}
+
+/// print the message, or save the file if extract is given
+fn write_payload(payload: &Payload, extract: Option<Option<PathBuf>>) -> Result<()> {
+    match (extract, payload.file_name()) {
+        // explicit path (or stdout), overwriting is fine
+        (Some(Some(path)), _) => {
+            write_output(&path, payload.data())?;
+            eprintln!(
+                "Extracted {} bytes to {}",
+                payload.data().len(),
+                path.display()
+            );
+        }
+        // original name, never overwrite anything
+        (Some(None), Some(name)) => {
+            let mut f = File::create_new(name).map_err(|e| match e.kind() {
+                io::ErrorKind::AlreadyExists => io::Error::new(
+                    e.kind(),
+                    format!("{name} already exists, give --extract a path"),
+                ),
+                _ => e,
+            })?;
+            f.write_all(payload.data())?;
+            eprintln!("Extracted {} bytes to {name}", payload.data().len());
+        }
+        (Some(None), None) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "No file name is stored in the image, give --extract a path",
+            )
+            .into());
+        }
+        (None, Some(name)) => {
+            eprintln!(
+                "Found hidden file {name} ({} bytes), use --extract to save it",
+                payload.data().len()
+            );
+        }
+        // print data as is, it doesn't have to be text
+        (None, None) => {
+            let mut out = stdout().lock();
+            out.write_all(payload.data())?;
+            // only end the line for people, pipes get exactly the hidden bytes
+            if out.is_terminal() && !payload.data().ends_with(b"\n") {
+                writeln!(out)?;
+            }
+            out.flush()?;
+        }
+    }
 
     Ok(())
 }
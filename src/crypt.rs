@@ -1,85 +1,426 @@
+//! Authenticated encryption of hidden data.
+//!
+//! Encrypted data is stored as a versioned binary envelope:
+//!
+//! ```text
+//! magic     4 bytes   0x89 "PGE"
+//! version   1 byte    currently 1
+//! cipher    1 byte    1 = XChaCha20-Poly1305, 2 = AES-256-GCM
+//! kdf       1 byte    1 = Argon2id, 2 = X25519 recipients
+//! params    12 / 1 + 80 * n bytes (depends on kdf, Argon2id stores memory, iterations
+//!           and parallelism as big endian u32, recipients their number n and the
+//!           random file key wrapped for each of them, see [`x25519`])
+//! salt     16 bytes
+//! nonce    24 / 12 bytes (depends on cipher)
+//! ciphertext + 16 byte tag
+//! ```
+//!
+//! Everything in front of the ciphertext is authenticated as well, so changing
+//! any byte of the envelope makes decryption fail.
+
 use std::{error::Error, fmt::Display};
 
+use aes_gcm::Aes256Gcm;
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::XChaCha20Poly1305;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, KeyInit, OsRng, Payload};
+use hkdf::Hkdf;
+use sha2::Sha256;
+
 use crate::Result;
 
-// just all symbols on my keyboard, don't care about anything else let's be real
-// if this is changed and longer than 255, need to change u8 in string_to_numbers and numbers_to_string
-const SYMBOLS: [char; 106] = [
-    'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i', 'j', 'k', 'l', 'm', 'n', 'o', 'p', 'q', 'r', 's',
-    't', 'u', 'v', 'w', 'x', 'y', 'z', 'A', 'B', 'C', 'D', 'E', 'F', 'G', 'H', 'I', 'J', 'K', 'L',
-    'M', 'N', 'O', 'P', 'Q', 'R', 'S', 'T', 'U', 'V', 'W', 'X', 'Y', 'Z', 'ä', 'ö', 'ü', 'ß', '0',
-    '1', '2', '3', '4', '5', '6', '7', '8', '9', '^', '°', '!', '"', '²', '§', '³', '$', '%', '&',
-    '/', '{', '(', '[', ')', ']', '=', '}', '?', '\\', '´', '`', '@', '€', '+', '*', '~', '\'',
-    '#', '<', '>', '|', 'µ', ',', ';', '.', ':', '-', '_', ' ',
-];
+pub mod vigenere;
+pub mod x25519;
+
+use x25519::{Identity, Recipient};
+
+/// first bytes of every encrypted envelope
+///
+/// 0x89 is never the first byte of valid UTF-8, so envelopes can't be mistaken for
+/// plain text messages (or the output of the old Vigenère cipher)
+pub const MAGIC: [u8; 4] = [0x89, b'P', b'G', b'E'];
+const VERSION: u8 = 1;
+const SALT_LEN: usize = 16;
+const TAG_LEN: usize = 16;
+// refuse to derive keys with more than 1 GiB of memory or absurd iteration counts and
+// lanes, so a crafted image can't make us OOM or spin forever
+const MAX_ARGON2_MEMORY: u32 = 1024 * 1024;
+const MAX_ARGON2_ITERATIONS: u32 = 100;
+const MAX_ARGON2_PARALLELISM: u32 = 16;
+// kdf id of envelopes encrypted to recipients, their key is derived from a random file key
+const RECIPIENTS_ID: u8 = 2;
+const PAYLOAD_INFO: &[u8] = b"pngecret payload";
+
+/// AEAD cipher used to encrypt the data
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Cipher {
+    #[default]
+    XChaCha20Poly1305,
+    Aes256Gcm,
+}
+
+impl Cipher {
+    fn id(&self) -> u8 {
+        match self {
+            Cipher::XChaCha20Poly1305 => 1,
+            Cipher::Aes256Gcm => 2,
+        }
+    }
+
+    fn from_id(id: u8) -> Result<Cipher> {
+        match id {
+            1 => Ok(Cipher::XChaCha20Poly1305),
+            2 => Ok(Cipher::Aes256Gcm),
+            _ => Err(CryptoError::UnknownCipher(id).into()),
+        }
+    }
 
-/// takes a string and turns it into vec of corresponding numbers
-fn string_to_numbers(s: &str) -> Result<Vec<u8>> {
-    let mut vals: Vec<u8> = Vec::new();
-    for c in s.chars() {
-        if let Some(idx) = SYMBOLS.iter().position(|x| *x == c) {
-            // NOTE: panics if more than 255 symbols in SYMBOLS
-            vals.push(idx.try_into().unwrap());
-        } else {
-            return Err(Box::new(CryptoError::UnknownChar(c)));
+    fn nonce_len(&self) -> usize {
+        match self {
+            Cipher::XChaCha20Poly1305 => 24,
+            Cipher::Aes256Gcm => 12,
         }
     }
-    Ok(vals)
+
+    fn encrypt(&self, key: &[u8; 32], nonce: &[u8], payload: Payload) -> Result<Vec<u8>> {
+        let res = match self {
+            Cipher::XChaCha20Poly1305 => {
+                XChaCha20Poly1305::new(key.into()).encrypt(nonce.into(), payload)
+            }
+            Cipher::Aes256Gcm => Aes256Gcm::new(key.into()).encrypt(nonce.into(), payload),
+        };
+        res.map_err(|_| CryptoError::EncryptionFailed.into())
+    }
+
+    fn decrypt(&self, key: &[u8; 32], nonce: &[u8], payload: Payload) -> Result<Vec<u8>> {
+        let res = match self {
+            Cipher::XChaCha20Poly1305 => {
+                XChaCha20Poly1305::new(key.into()).decrypt(nonce.into(), payload)
+            }
+            Cipher::Aes256Gcm => Aes256Gcm::new(key.into()).decrypt(nonce.into(), payload),
+        };
+        // the tag doesn't tell us whether the key or the data is wrong
+        res.map_err(|_| CryptoError::DecryptionFailed.into())
+    }
 }
 
-/// reverse of string_to_numbers
-fn numbers_to_string(vals: Vec<u8>) -> String {
-    let mut s: String = String::new();
-    for i in vals {
-        s.push(SYMBOLS[i as usize]);
+impl Display for Cipher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Cipher::XChaCha20Poly1305 => write!(f, "XChaCha20-Poly1305"),
+            Cipher::Aes256Gcm => write!(f, "AES-256-GCM"),
+        }
     }
-    s
 }
 
-/// encrypt msg using pass and return encrypted msg
-pub fn encrypt(msg: &str, pass: &str) -> Result<String> {
-    let msg_vals = string_to_numbers(msg)?;
-    let pass_vals = string_to_numbers(pass)?;
-    let mut encrypted_msg_vals = Vec::new();
+/// how the passphrase is turned into a 256 bit key
+///
+/// the parameters are stored in the envelope, so they can be raised without breaking old images
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kdf {
+    /// memory-hard Argon2id, memory cost is in KiB
+    Argon2id {
+        memory: u32,
+        iterations: u32,
+        parallelism: u32,
+    },
+}
 
-    // use pass_idx to repeatedly iterate over pass_vals
-    let mut pass_idx = 0;
-    for v in msg_vals {
-        // NOTE: if SYMBOLS has more than 255 chars this panics, change u8
-        encrypted_msg_vals.push((v + pass_vals[pass_idx]) % SYMBOLS.len() as u8);
-        // update pass_idx
-        pass_idx = (pass_idx + 1) % pass_vals.len();
+impl Default for Kdf {
+    fn default() -> Self {
+        // recommended by OWASP, takes a fraction of a second
+        Kdf::Argon2id {
+            memory: Params::DEFAULT_M_COST,
+            iterations: Params::DEFAULT_T_COST,
+            parallelism: Params::DEFAULT_P_COST,
+        }
     }
+}
+
+impl Kdf {
+    fn id(&self) -> u8 {
+        match self {
+            Kdf::Argon2id { .. } => 1,
+        }
+    }
+
+    fn params(&self) -> Vec<u8> {
+        match self {
+            Kdf::Argon2id {
+                memory,
+                iterations,
+                parallelism,
+            } => [memory, iterations, parallelism]
+                .iter()
+                .flat_map(|p| p.to_be_bytes())
+                .collect(),
+        }
+    }
+
+    /// reads kdf with id from the start of bytes and returns it with the number of bytes read
+    fn from_id_and_params(id: u8, bytes: &[u8]) -> Result<(Kdf, usize)> {
+        match id {
+            1 => {
+                let params = bytes.first_chunk::<12>().ok_or(CryptoError::BadEnvelope)?;
+                let param =
+                    |i: usize| <u32>::from_be_bytes(params[i * 4..i * 4 + 4].try_into().unwrap());
+                let kdf = Kdf::Argon2id {
+                    memory: param(0),
+                    iterations: param(1),
+                    parallelism: param(2),
+                };
+                Ok((kdf, params.len()))
+            }
+            _ => Err(CryptoError::UnknownKdf(id).into()),
+        }
+    }
+
+    /// turns the passphrase into a 256 bit key
+    pub fn derive_key(&self, pass: &str, salt: &[u8]) -> Result<[u8; 32]> {
+        match *self {
+            Kdf::Argon2id {
+                memory,
+                iterations,
+                parallelism,
+            } => {
+                if memory > MAX_ARGON2_MEMORY
+                    || iterations > MAX_ARGON2_ITERATIONS
+                    || parallelism > MAX_ARGON2_PARALLELISM
+                {
+                    return Err(CryptoError::BadKdfParams.into());
+                }
+                let params = Params::new(memory, iterations, parallelism, Some(32))
+                    .map_err(|_| CryptoError::BadKdfParams)?;
+                let mut key = [0; 32];
+                Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+                    .hash_password_into(pass.as_bytes(), salt, &mut key)
+                    .map_err(|_| CryptoError::BadKdfParams)?;
+                Ok(key)
+            }
+        }
+    }
+}
+
+/// number of bytes the envelope adds to the encrypted data
+pub fn overhead(cipher: Cipher, kdf: Kdf) -> usize {
+    MAGIC.len() + 3 + kdf.params().len() + SALT_LEN + cipher.nonce_len() + TAG_LEN
+}
+
+/// number of bytes the envelope adds to data encrypted to this many recipients
+pub fn recipients_overhead(cipher: Cipher, recipients: usize) -> usize {
+    MAGIC.len() + 4 + recipients * x25519::STANZA_LEN + SALT_LEN + cipher.nonce_len() + TAG_LEN
+}
+
+/// true if data looks like an envelope created by [`encrypt`] or [`encrypt_to`]
+pub fn is_envelope(data: &[u8]) -> bool {
+    data.starts_with(&MAGIC)
+}
+
+/// true if data is an envelope created by [`encrypt_to`], which needs an identity to decrypt
+pub fn is_encrypted_to_recipients(data: &[u8]) -> bool {
+    is_envelope(data) && data.get(MAGIC.len() + 2) == Some(&RECIPIENTS_ID)
+}
 
-    Ok(numbers_to_string(encrypted_msg_vals))
+/// encrypt data using a key derived from pass with kdf and return the envelope
+pub fn encrypt(data: &[u8], pass: &str, cipher: Cipher, kdf: Kdf) -> Result<Vec<u8>> {
+    let mut salt = [0; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let key = kdf.derive_key(pass, &salt)?;
+    seal(data, cipher, kdf.id(), &kdf.params(), &salt, &key)
 }
 
-pub fn decrypt(msg: &str, pass: &str) -> Result<String> {
-    let msg_vals = string_to_numbers(msg)?;
-    let pass_vals = string_to_numbers(pass)?;
-    let mut encrypted_msg_vals = Vec::new();
+/// encrypt data so that every one of recipients can decrypt it and return the envelope
+pub fn encrypt_to(data: &[u8], recipients: &[Recipient], cipher: Cipher) -> Result<Vec<u8>> {
+    let count = <u8>::try_from(recipients.len())
+        .ok()
+        .filter(|n| *n > 0)
+        .ok_or(CryptoError::BadRecipientCount(recipients.len()))?;
+    let mut file_key = [0; 32];
+    OsRng.fill_bytes(&mut file_key);
+    let mut params = vec![count];
+    params.extend(x25519::wrap(&file_key, recipients)?);
 
-    // use pass_idx to repeatedly iterate over pass_vals
-    let mut pass_idx = 0;
-    for v in msg_vals {
-        // NOTE: if SYMBOLS has more than 255 chars this panics, change u8
-        // not very pretty? but works
-        // basically see if subtraction would overflow, if yes subtract (u8::MAX - SYMBOLS.len()+1) from result
-        if let (n, true) = v.overflowing_sub(pass_vals[pass_idx]) {
-            encrypted_msg_vals.push(n - (u8::MAX - SYMBOLS.len() as u8 + 1));
-        } else {
-            encrypted_msg_vals.push((v - pass_vals[pass_idx]) % SYMBOLS.len() as u8);
+    let mut salt = [0; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let key = payload_key(&file_key, &salt);
+    seal(data, cipher, RECIPIENTS_ID, &params, &salt, &key)
+}
+
+/// key the data of a recipients envelope is encrypted with
+fn payload_key(file_key: &[u8; 32], salt: &[u8]) -> [u8; 32] {
+    let mut key = [0; 32];
+    Hkdf::<Sha256>::new(Some(salt), file_key)
+        .expand(PAYLOAD_INFO, &mut key)
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    key
+}
+
+/// build the envelope around data encrypted with key
+fn seal(
+    data: &[u8],
+    cipher: Cipher,
+    kdf_id: u8,
+    params: &[u8],
+    salt: &[u8],
+    key: &[u8; 32],
+) -> Result<Vec<u8>> {
+    let mut nonce = vec![0; cipher.nonce_len()];
+    OsRng.fill_bytes(&mut nonce);
+
+    // header is everything in front of the ciphertext, it is authenticated too
+    let mut envelope = MAGIC.to_vec();
+    envelope.extend_from_slice(&[VERSION, cipher.id(), kdf_id]);
+    envelope.extend_from_slice(params);
+    envelope.extend_from_slice(salt);
+    envelope.extend_from_slice(&nonce);
+
+    let ciphertext = cipher.encrypt(
+        key,
+        &nonce,
+        Payload {
+            msg: data,
+            aad: &envelope,
+        },
+    )?;
+    envelope.extend_from_slice(&ciphertext);
+
+    Ok(envelope)
+}
+
+/// where the key of an envelope comes from
+enum KeySource<'a> {
+    Passphrase(Kdf),
+    /// the wrapped file keys
+    Recipients(&'a [u8]),
+}
+
+/// the parts of an envelope
+struct Envelope<'a> {
+    cipher: Cipher,
+    key_source: KeySource<'a>,
+    salt: &'a [u8],
+    nonce: &'a [u8],
+    /// everything in front of the ciphertext
+    header: &'a [u8],
+    ciphertext: &'a [u8],
+}
+
+impl<'a> Envelope<'a> {
+    fn parse(envelope: &'a [u8]) -> Result<Envelope<'a>> {
+        if !is_envelope(envelope) {
+            return Err(CryptoError::BadEnvelope.into());
         }
-        // update pass_idx
-        pass_idx = (pass_idx + 1) % pass_vals.len();
+        let [version, cipher, kdf] = *envelope[MAGIC.len()..]
+            .first_chunk::<3>()
+            .ok_or(CryptoError::BadEnvelope)?;
+        if version != VERSION {
+            return Err(CryptoError::UnsupportedVersion(version).into());
+        }
+        let cipher = Cipher::from_id(cipher)?;
+        let params_start = MAGIC.len() + 3;
+        let params = &envelope[params_start..];
+        let (key_source, params_len) = match kdf {
+            RECIPIENTS_ID => {
+                let count = *params.first().ok_or(CryptoError::BadEnvelope)? as usize;
+                let len = 1 + count * x25519::STANZA_LEN;
+                if count == 0 || params.len() < len {
+                    return Err(CryptoError::BadEnvelope.into());
+                }
+                (KeySource::Recipients(&params[1..len]), len)
+            }
+            _ => {
+                let (kdf, len) = Kdf::from_id_and_params(kdf, params)?;
+                (KeySource::Passphrase(kdf), len)
+            }
+        };
+
+        let salt_start = params_start + params_len;
+        let nonce_start = salt_start + SALT_LEN;
+        let header_len = nonce_start + cipher.nonce_len();
+        if envelope.len() < header_len + TAG_LEN {
+            return Err(CryptoError::BadEnvelope.into());
+        }
+        let (header, ciphertext) = envelope.split_at(header_len);
+
+        Ok(Envelope {
+            cipher,
+            key_source,
+            salt: &header[salt_start..nonce_start],
+            nonce: &header[nonce_start..],
+            header,
+            ciphertext,
+        })
     }
 
-    Ok(numbers_to_string(encrypted_msg_vals))
+    fn open(&self, key: &[u8; 32]) -> Result<Vec<u8>> {
+        self.cipher.decrypt(
+            key,
+            self.nonce,
+            Payload {
+                msg: self.ciphertext,
+                aad: self.header,
+            },
+        )
+    }
+}
+
+/// decrypt an envelope created by [`encrypt`] using pass
+///
+/// fails with [`CryptoError::DecryptionFailed`] if pass is wrong or the envelope was changed
+pub fn decrypt(envelope: &[u8], pass: &str) -> Result<Vec<u8>> {
+    let envelope = Envelope::parse(envelope)?;
+    let KeySource::Passphrase(kdf) = envelope.key_source else {
+        return Err(CryptoError::IdentityRequired.into());
+    };
+    let key = kdf.derive_key(pass, envelope.salt)?;
+    envelope.open(&key)
+}
+
+/// decrypt an envelope created by [`encrypt_to`] using any of identities
+///
+/// fails with [`CryptoError::NotARecipient`] if the data wasn't encrypted to any of them
+pub fn decrypt_with(envelope: &[u8], identities: &[Identity]) -> Result<Vec<u8>> {
+    let envelope = Envelope::parse(envelope)?;
+    let KeySource::Recipients(stanzas) = envelope.key_source else {
+        return Err(CryptoError::PassphraseRequired.into());
+    };
+    let file_key = x25519::unwrap(stanzas, identities)?;
+    envelope.open(&payload_key(&file_key, envelope.salt))
 }
 
 #[derive(Debug)]
-enum CryptoError {
+#[non_exhaustive]
+pub enum CryptoError {
+    // char can't be handled by the Vigenère cipher
     UnknownChar(char),
+    // envelope is too short or doesn't start with MAGIC
+    BadEnvelope,
+    // envelope was created by a newer version of PNGecret
+    UnsupportedVersion(u8),
+    UnknownCipher(u8),
+    UnknownKdf(u8),
+    // kdf parameters are invalid or too expensive
+    BadKdfParams,
+    EncryptionFailed,
+    // wrong passphrase, or the envelope was tampered with
+    DecryptionFailed,
+    // data is encrypted, but no passphrase was given
+    PassphraseRequired,
+    // a passphrase was given, but the data isn't encrypted
+    NotEncrypted,
+    // the Vigenère cipher can't use an empty passphrase
+    EmptyPassphrase,
+    // data is encrypted to recipients, but no identity was given
+    IdentityRequired,
+    // none of the identities can decrypt the data
+    NotARecipient,
+    // key text is malformed, or the public key is unusable
+    BadKey,
+    // envelopes hold between 1 and 255 recipients
+    BadRecipientCount(usize),
+    // both a passphrase and recipients were given
+    PassphraseAndRecipients,
 }
 
 impl Display for CryptoError {
@@ -91,6 +432,47 @@ impl Display for CryptoError {
                     "Couldn't encrypt or decrypt the message, because {c} is unknown."
                 )
             }
+            CryptoError::BadEnvelope => write!(f, "Encrypted data is malformed"),
+            CryptoError::UnsupportedVersion(v) => {
+                write!(f, "Encrypted data has unsupported version {v}")
+            }
+            CryptoError::UnknownCipher(c) => write!(f, "Unknown cipher id {c}"),
+            CryptoError::UnknownKdf(k) => write!(f, "Unknown key derivation id {k}"),
+            CryptoError::BadKdfParams => {
+                write!(f, "Key derivation parameters are invalid or too expensive")
+            }
+            CryptoError::EncryptionFailed => write!(f, "Couldn't encrypt the message"),
+            CryptoError::DecryptionFailed => write!(
+                f,
+                "Couldn't decrypt the message: wrong passphrase or the data was tampered with"
+            ),
+            CryptoError::PassphraseRequired => {
+                write!(f, "The message is encrypted, a passphrase is required")
+            }
+            CryptoError::NotEncrypted => write!(
+                f,
+                "The message isn't encrypted (or was encrypted with the Vigenère cipher of older versions)"
+            ),
+            CryptoError::EmptyPassphrase => write!(f, "The passphrase can't be empty"),
+            CryptoError::IdentityRequired => write!(
+                f,
+                "The message is encrypted to recipients, an identity is required"
+            ),
+            CryptoError::NotARecipient => {
+                write!(
+                    f,
+                    "The message isn't encrypted to any of the given identities"
+                )
+            }
+            CryptoError::BadKey => write!(f, "Key is malformed or unusable"),
+            CryptoError::BadRecipientCount(n) => write!(
+                f,
+                "Messages can be encrypted to 1 to 255 recipients, not {n}"
+            ),
+            CryptoError::PassphraseAndRecipients => write!(
+                f,
+                "Messages can be encrypted with a passphrase or to recipients, not both"
+            ),
         }
     }
 }
@@ -101,42 +483,163 @@ impl Error for CryptoError {}
 mod tests {
     use super::*;
 
+    // cheap parameters, the tests would take ages otherwise
+    const TEST_KDF: Kdf = Kdf::Argon2id {
+        memory: 8,
+        iterations: 1,
+        parallelism: 1,
+    };
+
     #[test]
-    fn test_string_to_numbers() {
-        let actual = string_to_numbers("Hello World!").unwrap();
-        let expected = vec![33, 4, 11, 11, 14, 105, 48, 14, 17, 11, 3, 68];
+    fn test_encrypt_decrypt() {
+        for cipher in [Cipher::XChaCha20Poly1305, Cipher::Aes256Gcm] {
+            let envelope = encrypt(b"Hello World!", "password", cipher, TEST_KDF).unwrap();
+            assert!(is_envelope(&envelope));
+            assert_eq!(envelope.len(), 12 + overhead(cipher, TEST_KDF));
+            assert_eq!(decrypt(&envelope, "password").unwrap(), b"Hello World!");
+        }
+    }
 
-        assert_eq!(actual, expected);
+    #[test]
+    fn test_encrypt_is_randomized() {
+        let a = encrypt(b"Hello World!", "password", Cipher::default(), TEST_KDF).unwrap();
+        let b = encrypt(b"Hello World!", "password", Cipher::default(), TEST_KDF).unwrap();
+        assert_ne!(a, b);
     }
 
     #[test]
-    fn test_unknown_char() {
-        let result = string_to_numbers("é");
+    fn test_wrong_passphrase() {
+        let envelope = encrypt(b"Hello World!", "password", Cipher::default(), TEST_KDF).unwrap();
+        let res = decrypt(&envelope, "passw0rd");
+        assert!(matches!(
+            res,
+            Err(crate::Error::Crypto(CryptoError::DecryptionFailed))
+        ));
+    }
 
-        assert!(result.is_err());
+    #[test]
+    fn test_tampered_envelope() {
+        let envelope = encrypt(b"Hello World!", "password", Cipher::default(), TEST_KDF).unwrap();
+        // flipping a bit anywhere, header included, must be detected
+        for i in 0..envelope.len() {
+            let mut tampered = envelope.clone();
+            tampered[i] ^= 1;
+            assert!(decrypt(&tampered, "password").is_err());
+        }
     }
 
     #[test]
-    fn test_numbers_to_string() {
-        let actual = numbers_to_string(vec![33, 4, 11, 11, 14, 105, 48, 14, 17, 11, 3, 68]);
-        let expected = "Hello World!";
+    fn test_bad_envelope() {
+        assert!(matches!(
+            decrypt(b"Hello World!", "password"),
+            Err(crate::Error::Crypto(CryptoError::BadEnvelope))
+        ));
+        let envelope = encrypt(b"", "password", Cipher::default(), TEST_KDF).unwrap();
+        assert!(matches!(
+            decrypt(&envelope[..envelope.len() - 1], "password"),
+            Err(crate::Error::Crypto(CryptoError::BadEnvelope))
+        ));
+    }
 
-        assert_eq!(actual, expected);
+    #[test]
+    fn test_unsupported_version() {
+        let mut envelope =
+            encrypt(b"Hello World!", "password", Cipher::default(), TEST_KDF).unwrap();
+        envelope[MAGIC.len()] = 99;
+        assert!(matches!(
+            decrypt(&envelope, "password"),
+            Err(crate::Error::Crypto(CryptoError::UnsupportedVersion(99)))
+        ));
+    }
+
+    #[test]
+    fn test_kdf_params_stored() {
+        let kdf = Kdf::Argon2id {
+            memory: 16,
+            iterations: 3,
+            parallelism: 2,
+        };
+        let envelope = encrypt(b"Hello World!", "password", Cipher::default(), kdf).unwrap();
+        let (stored, len) = Kdf::from_id_and_params(envelope[6], &envelope[7..]).unwrap();
+        assert_eq!((stored, len), (kdf, 12));
+        assert_eq!(decrypt(&envelope, "password").unwrap(), b"Hello World!");
+    }
+
+    #[test]
+    fn test_kdf_params_too_expensive() {
+        let mut envelope =
+            encrypt(b"Hello World!", "password", Cipher::default(), TEST_KDF).unwrap();
+        // memory cost of 4 GiB
+        let mut memory = envelope.clone();
+        memory[7..11].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(matches!(
+            decrypt(&memory, "password"),
+            Err(crate::Error::Crypto(CryptoError::BadKdfParams))
+        ));
+
+        // 1000 lanes, with just enough memory for them
+        envelope[7..11].copy_from_slice(&8000u32.to_be_bytes());
+        envelope[15..19].copy_from_slice(&1000u32.to_be_bytes());
+        assert!(matches!(
+            decrypt(&envelope, "password"),
+            Err(crate::Error::Crypto(CryptoError::BadKdfParams))
+        ));
     }
 
     #[test]
-    fn test_encrypt() {
-        let actual = encrypt("Hello World!", "password").unwrap();
-        let expected = "WeDDKn9rGlv´";
+    fn test_encrypt_to_recipients() {
+        let alice = Identity::generate();
+        let bob = Identity::generate();
+        let recipients = [alice.recipient(), bob.recipient()];
+        let envelope = encrypt_to(b"Hello World!", &recipients, Cipher::Aes256Gcm).unwrap();
+        assert!(is_encrypted_to_recipients(&envelope));
+        assert_eq!(
+            envelope.len(),
+            12 + recipients_overhead(Cipher::Aes256Gcm, 2)
+        );
+        for identity in [alice, bob] {
+            assert_eq!(
+                decrypt_with(&envelope, &[identity]).unwrap(),
+                b"Hello World!"
+            );
+        }
 
-        assert_eq!(actual, expected);
+        // a passphrase doesn't help, and the other way around
+        assert!(matches!(
+            decrypt(&envelope, "password"),
+            Err(crate::Error::Crypto(CryptoError::IdentityRequired))
+        ));
+        let envelope = encrypt(b"Hello World!", "password", Cipher::default(), TEST_KDF).unwrap();
+        assert!(!is_encrypted_to_recipients(&envelope));
+        assert!(matches!(
+            decrypt_with(&envelope, &[Identity::generate()]),
+            Err(crate::Error::Crypto(CryptoError::PassphraseRequired))
+        ));
     }
 
     #[test]
-    fn test_decrypt() {
-        let actual = decrypt("WeDDKn9rGlv´", "password").unwrap();
-        let expected = "Hello World!";
+    fn test_tampered_recipients_envelope() {
+        let identity = Identity::generate();
+        let envelope =
+            encrypt_to(b"Hello World!", &[identity.recipient()], Cipher::default()).unwrap();
+        for i in 0..envelope.len() {
+            let mut tampered = envelope.clone();
+            tampered[i] ^= 1;
+            assert!(decrypt_with(&tampered, std::slice::from_ref(&identity)).is_err());
+        }
+        assert!(matches!(
+            encrypt_to(b"Hello World!", &[], Cipher::default()),
+            Err(crate::Error::Crypto(CryptoError::BadRecipientCount(0)))
+        ));
+    }
 
-        assert_eq!(actual, expected);
+    #[test]
+    fn test_argon2_key() {
+        let salt = [0; SALT_LEN];
+        let a = TEST_KDF.derive_key("password", &salt).unwrap();
+        let b = TEST_KDF.derive_key("password", &salt).unwrap();
+        let c = TEST_KDF.derive_key("passw0rd", &salt).unwrap();
+        assert_eq!(a, b);
+        assert_ne!(a, c);
     }
 }
@@ -0,0 +1,219 @@
+//! X25519 key pairs to encrypt hidden data to recipients instead of a passphrase,
+//! the same way age does it.
+//!
+//! Every envelope gets a random file key, which is wrapped once for every recipient:
+//! a new ephemeral key pair is agreed with the recipient's public key, and the shared
+//! secret is turned into the key that wraps the file key with HKDF-SHA256.
+//!
+//! Keys are written as text, a prefix followed by the base64 encoded key:
+//!
+//! ```text
+//! pngecret-public:3Dx5Zq4...    share it with the people who encrypt to you
+//! pngecret-secret:hV8Rr0c...    keep it in a key file, it decrypts everything sent to you
+//! ```
+
+use std::{fmt::Display, str::FromStr};
+
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use chacha20poly1305::ChaCha20Poly1305;
+use chacha20poly1305::aead::{Aead, KeyInit, OsRng};
+use hkdf::Hkdf;
+use sha2::Sha256;
+use x25519_dalek::{EphemeralSecret, PublicKey, StaticSecret};
+
+use crate::Result;
+use crate::crypt::CryptoError;
+
+const PUBLIC_PREFIX: &str = "pngecret-public:";
+const SECRET_PREFIX: &str = "pngecret-secret:";
+const WRAP_INFO: &[u8] = b"pngecret x25519";
+/// length of the wrapped file key of one recipient: ephemeral public key and the
+/// encrypted file key with its tag
+pub const STANZA_LEN: usize = 32 + 32 + 16;
+
+/// public key data can be encrypted to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Recipient(PublicKey);
+
+/// secret key that decrypts data encrypted to its [`Recipient`]
+#[derive(Clone)]
+pub struct Identity(StaticSecret);
+
+impl Identity {
+    /// new random key pair
+    pub fn generate() -> Identity {
+        Identity(StaticSecret::random_from_rng(OsRng))
+    }
+
+    /// public half of the key pair
+    pub fn recipient(&self) -> Recipient {
+        Recipient(PublicKey::from(&self.0))
+    }
+
+    /// every identity in a key file, one per line
+    ///
+    /// empty lines and comments starting with # are skipped
+    pub fn parse_file(content: &str) -> Result<Vec<Identity>> {
+        content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(Identity::from_str)
+            .collect()
+    }
+
+    /// file key from the stanza wrapped for this identity, None if it is for someone else
+    fn unwrap_key(&self, stanza: &[u8]) -> Option<[u8; 32]> {
+        let ephemeral = PublicKey::from(<[u8; 32]>::try_from(&stanza[..32]).ok()?);
+        let shared = self.0.diffie_hellman(&ephemeral);
+        if !shared.was_contributory() {
+            return None;
+        }
+        let key = wrap_key(shared.as_bytes(), &ephemeral, &self.recipient().0);
+        let file_key = ChaCha20Poly1305::new(&key.into())
+            .decrypt(&Default::default(), &stanza[32..])
+            .ok()?;
+        file_key.try_into().ok()
+    }
+}
+
+impl Recipient {
+    /// file key wrapped for this recipient, see [`STANZA_LEN`]
+    fn wrap_key(&self, file_key: &[u8; 32]) -> Result<Vec<u8>> {
+        let secret = EphemeralSecret::random_from_rng(OsRng);
+        let ephemeral = PublicKey::from(&secret);
+        let shared = secret.diffie_hellman(&self.0);
+        // low order public keys would give everyone the same shared secret
+        if !shared.was_contributory() {
+            return Err(CryptoError::BadKey.into());
+        }
+        let key = wrap_key(shared.as_bytes(), &ephemeral, &self.0);
+        // the key is only ever used once, so a zero nonce is fine
+        let wrapped = ChaCha20Poly1305::new(&key.into())
+            .encrypt(&Default::default(), &file_key[..])
+            .map_err(|_| CryptoError::EncryptionFailed)?;
+
+        let mut stanza = ephemeral.as_bytes().to_vec();
+        stanza.extend_from_slice(&wrapped);
+        Ok(stanza)
+    }
+}
+
+/// key that wraps the file key, bound to both public keys
+fn wrap_key(shared: &[u8], ephemeral: &PublicKey, recipient: &PublicKey) -> [u8; 32] {
+    let mut salt = ephemeral.as_bytes().to_vec();
+    salt.extend_from_slice(recipient.as_bytes());
+    let mut key = [0; 32];
+    Hkdf::<Sha256>::new(Some(&salt), shared)
+        .expand(WRAP_INFO, &mut key)
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    key
+}
+
+/// file key wrapped for every recipient, concatenated
+pub(super) fn wrap(file_key: &[u8; 32], recipients: &[Recipient]) -> Result<Vec<u8>> {
+    let mut stanzas = Vec::with_capacity(recipients.len() * STANZA_LEN);
+    for recipient in recipients {
+        stanzas.extend(recipient.wrap_key(file_key)?);
+    }
+    Ok(stanzas)
+}
+
+/// file key from the first stanza one of the identities can unwrap
+pub(super) fn unwrap(stanzas: &[u8], identities: &[Identity]) -> Result<[u8; 32]> {
+    if identities.is_empty() {
+        return Err(CryptoError::IdentityRequired.into());
+    }
+    stanzas
+        .chunks_exact(STANZA_LEN)
+        .find_map(|stanza| identities.iter().find_map(|i| i.unwrap_key(stanza)))
+        .ok_or(CryptoError::NotARecipient.into())
+}
+
+/// 32 key bytes after prefix in s
+fn parse_key(s: &str, prefix: &str) -> Result<[u8; 32]> {
+    s.trim()
+        .strip_prefix(prefix)
+        .and_then(|key| BASE64.decode(key).ok())
+        .and_then(|key| key.try_into().ok())
+        .ok_or(CryptoError::BadKey.into())
+}
+
+impl FromStr for Recipient {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(Recipient(PublicKey::from(parse_key(s, PUBLIC_PREFIX)?)))
+    }
+}
+
+impl FromStr for Identity {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(Identity(StaticSecret::from(parse_key(s, SECRET_PREFIX)?)))
+    }
+}
+
+impl Display for Recipient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{PUBLIC_PREFIX}{}", BASE64.encode(self.0.as_bytes()))
+    }
+}
+
+impl Display for Identity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{SECRET_PREFIX}{}", BASE64.encode(self.0.as_bytes()))
+    }
+}
+
+impl std::fmt::Debug for Identity {
+    /// only the public key, secrets don't belong in logs
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Identity").field(&self.recipient()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wrap_unwrap() {
+        let alice = Identity::generate();
+        let bob = Identity::generate();
+        let eve = Identity::generate();
+        let file_key = [7; 32];
+        let stanzas = wrap(&file_key, &[alice.recipient(), bob.recipient()]).unwrap();
+        assert_eq!(stanzas.len(), 2 * STANZA_LEN);
+
+        assert_eq!(unwrap(&stanzas, &[bob]).unwrap(), file_key);
+        assert_eq!(unwrap(&stanzas, &[eve.clone(), alice]).unwrap(), file_key);
+        assert!(matches!(
+            unwrap(&stanzas, &[eve]),
+            Err(crate::Error::Crypto(CryptoError::NotARecipient))
+        ));
+    }
+
+    #[test]
+    fn test_key_text() {
+        let identity = Identity::generate();
+        let recipient = identity.recipient();
+        assert!(recipient.to_string().starts_with(PUBLIC_PREFIX));
+        assert_eq!(
+            recipient.to_string().parse::<Recipient>().unwrap(),
+            recipient
+        );
+
+        let file = format!("# public key: {recipient}\n\n{identity}\n");
+        let parsed = Identity::parse_file(&file).unwrap();
+        assert_eq!(parsed.len(), 1);
+        assert_eq!(parsed[0].recipient(), recipient);
+
+        // a secret key is no public key and the other way around
+        assert!(identity.to_string().parse::<Recipient>().is_err());
+        assert!(recipient.to_string().parse::<Identity>().is_err());
+        let secret = &identity.to_string()[SECRET_PREFIX.len()..];
+        assert!(!format!("{identity:?}").contains(secret));
+    }
+}
@@ -0,0 +1,217 @@
+use std::{fmt::Display, io, string::FromUtf8Error};
+
+use crate::chunk::ChunkError;
+use crate::chunk_type::ChunkTypeError;
+use crate::crypt::CryptoError;
+use crate::ihdr::IhdrError;
+use crate::lsb::LsbError;
+use crate::palette::PaletteError;
+use crate::payload::PayloadError;
+use crate::pixels::PixelError;
+use crate::png::PngError;
+use crate::sequence::SequenceError;
+use crate::signature::SignatureError;
+use crate::text_chunk::TextChunkError;
+
+/// every error PNGecret can return
+///
+/// the module specific errors are wrapped as is, so callers can match on them
+/// (e.g. `Error::Chunk(ChunkError::BadChecksum { .. })`)
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// reading or writing failed
+    Io(io::Error),
+    /// chunk type is invalid
+    ChunkType(ChunkTypeError),
+    /// chunk is invalid
+    Chunk(ChunkError),
+    /// png is invalid or doesn't contain what was asked for
+    Png(PngError),
+    /// image header is invalid
+    Ihdr(IhdrError),
+    /// message couldn't be encrypted or decrypted
+    Crypto(CryptoError),
+    /// hidden payload is malformed
+    Payload(PayloadError),
+    /// image data can't be read
+    Pixel(PixelError),
+    /// data can't be hidden in or read from the pixels
+    Lsb(LsbError),
+    /// palette of an indexed image is missing or invalid
+    Palette(PaletteError),
+    /// pieces of hidden data split over multiple chunks don't fit together
+    Sequence(SequenceError),
+    /// signature is missing, invalid or from an untrusted key
+    Signature(SignatureError),
+    /// tEXt, zTXt or iTXt chunk is invalid
+    TextChunk(TextChunkError),
+    /// chunk data isn't valid UTF-8
+    Utf8(FromUtf8Error),
+    /// chunk at `index` (starting at byte `offset` of the file) couldn't be parsed,
+    /// the reason is in `source`
+    InvalidChunk {
+        index: usize,
+        offset: usize,
+        source: Box<Error>,
+    },
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{e}"),
+            Error::ChunkType(e) => write!(f, "{e}"),
+            Error::Chunk(e) => write!(f, "{e}"),
+            Error::Png(e) => write!(f, "{e}"),
+            Error::Ihdr(e) => write!(f, "{e}"),
+            Error::Crypto(e) => write!(f, "{e}"),
+            Error::Payload(e) => write!(f, "{e}"),
+            Error::Pixel(e) => write!(f, "{e}"),
+            Error::Lsb(e) => write!(f, "{e}"),
+            Error::Palette(e) => write!(f, "{e}"),
+            Error::Sequence(e) => write!(f, "{e}"),
+            Error::Signature(e) => write!(f, "{e}"),
+            Error::TextChunk(e) => write!(f, "{e}"),
+            Error::Utf8(e) => write!(f, "Data is not valid UTF-8: {e}"),
+            Error::InvalidChunk { index, offset, .. } => {
+                write!(f, "Chunk {index} at byte offset {offset} is invalid")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            // wrapped errors are shown directly, so skip them in the chain
+            Error::Io(e) => e.source(),
+            Error::Utf8(e) => e.source(),
+            Error::ChunkType(_)
+            | Error::Chunk(_)
+            | Error::Png(_)
+            | Error::Ihdr(_)
+            | Error::Crypto(_)
+            | Error::Payload(_)
+            | Error::Pixel(_)
+            | Error::Lsb(_)
+            | Error::Palette(_)
+            | Error::Sequence(_)
+            | Error::Signature(_)
+            | Error::TextChunk(_) => None,
+            Error::InvalidChunk { source, .. } => Some(source.as_ref()),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<ChunkTypeError> for Error {
+    fn from(e: ChunkTypeError) -> Self {
+        Error::ChunkType(e)
+    }
+}
+
+impl From<ChunkError> for Error {
+    fn from(e: ChunkError) -> Self {
+        Error::Chunk(e)
+    }
+}
+
+impl From<PngError> for Error {
+    fn from(e: PngError) -> Self {
+        Error::Png(e)
+    }
+}
+
+impl From<IhdrError> for Error {
+    fn from(e: IhdrError) -> Self {
+        Error::Ihdr(e)
+    }
+}
+
+impl From<CryptoError> for Error {
+    fn from(e: CryptoError) -> Self {
+        Error::Crypto(e)
+    }
+}
+
+impl From<PayloadError> for Error {
+    fn from(e: PayloadError) -> Self {
+        Error::Payload(e)
+    }
+}
+
+impl From<PixelError> for Error {
+    fn from(e: PixelError) -> Self {
+        Error::Pixel(e)
+    }
+}
+
+impl From<LsbError> for Error {
+    fn from(e: LsbError) -> Self {
+        Error::Lsb(e)
+    }
+}
+
+impl From<PaletteError> for Error {
+    fn from(e: PaletteError) -> Self {
+        Error::Palette(e)
+    }
+}
+
+impl From<SequenceError> for Error {
+    fn from(e: SequenceError) -> Self {
+        Error::Sequence(e)
+    }
+}
+
+impl From<SignatureError> for Error {
+    fn from(e: SignatureError) -> Self {
+        Error::Signature(e)
+    }
+}
+
+impl From<TextChunkError> for Error {
+    fn from(e: TextChunkError) -> Self {
+        Error::TextChunk(e)
+    }
+}
+
+impl From<FromUtf8Error> for Error {
+    fn from(e: FromUtf8Error) -> Self {
+        Error::Utf8(e)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error as _;
+
+    use super::*;
+
+    #[test]
+    fn test_source_chain() {
+        let err = Error::InvalidChunk {
+            index: 2,
+            offset: 33,
+            source: Box::new(Error::Chunk(ChunkError::TooShort(3))),
+        };
+        assert_eq!(err.to_string(), "Chunk 2 at byte offset 33 is invalid");
+        let source = err.source().unwrap();
+        assert_eq!(source.to_string(), ChunkError::TooShort(3).to_string());
+        assert!(source.source().is_none());
+    }
+
+    #[test]
+    fn test_from_io() {
+        let err: Error = io::Error::new(io::ErrorKind::NotFound, "gone").into();
+        assert!(matches!(err, Error::Io(_)));
+    }
+}
@@ -0,0 +1,321 @@
+//! The image header, the IHDR chunk every png starts with (section 11.2.2 of the PNG spec).
+
+use std::{error::Error, fmt::Display, str::FromStr};
+
+use crate::Result;
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+
+/// width and height can't be bigger than this
+pub const MAX_DIMENSION: u32 = (1 << 31) - 1;
+
+/// how the samples of a pixel are interpreted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorType {
+    Grayscale = 0,
+    Rgb = 2,
+    /// every pixel is an index into the PLTE chunk
+    Indexed = 3,
+    GrayscaleAlpha = 4,
+    Rgba = 6,
+}
+
+impl ColorType {
+    /// number of samples per pixel
+    pub fn channels(&self) -> usize {
+        match self {
+            ColorType::Grayscale | ColorType::Indexed => 1,
+            ColorType::GrayscaleAlpha => 2,
+            ColorType::Rgb => 3,
+            ColorType::Rgba => 4,
+        }
+    }
+
+    /// bit depths the spec allows for this color type
+    pub fn bit_depths(&self) -> &'static [u8] {
+        match self {
+            ColorType::Grayscale => &[1, 2, 4, 8, 16],
+            ColorType::Indexed => &[1, 2, 4, 8],
+            ColorType::Rgb | ColorType::GrayscaleAlpha | ColorType::Rgba => &[8, 16],
+        }
+    }
+
+    pub fn has_alpha(&self) -> bool {
+        matches!(self, ColorType::GrayscaleAlpha | ColorType::Rgba)
+    }
+}
+
+impl TryFrom<u8> for ColorType {
+    type Error = crate::Error;
+
+    fn try_from(value: u8) -> Result<Self> {
+        match value {
+            0 => Ok(ColorType::Grayscale),
+            2 => Ok(ColorType::Rgb),
+            3 => Ok(ColorType::Indexed),
+            4 => Ok(ColorType::GrayscaleAlpha),
+            6 => Ok(ColorType::Rgba),
+            _ => Err(IhdrError::UnknownColorType(value).into()),
+        }
+    }
+}
+
+impl Display for ColorType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ColorType::Grayscale => "grayscale",
+            ColorType::Rgb => "RGB",
+            ColorType::Indexed => "indexed",
+            ColorType::GrayscaleAlpha => "grayscale with alpha",
+            ColorType::Rgba => "RGBA",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// parsed and validated IHDR chunk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ihdr {
+    width: u32,
+    height: u32,
+    bit_depth: u8,
+    color_type: ColorType,
+    // compression and filter method can only be 0, but they are checked anyway
+    compression_method: u8,
+    filter_method: u8,
+    interlaced: bool,
+}
+
+impl Ihdr {
+    pub fn new(
+        width: u32,
+        height: u32,
+        bit_depth: u8,
+        color_type: ColorType,
+        interlaced: bool,
+    ) -> Result<Ihdr> {
+        for dimension in [width, height] {
+            if dimension == 0 || dimension > MAX_DIMENSION {
+                return Err(IhdrError::BadDimension(dimension).into());
+            }
+        }
+        if !color_type.bit_depths().contains(&bit_depth) {
+            return Err(IhdrError::BadBitDepth {
+                color_type,
+                bit_depth,
+            }
+            .into());
+        }
+        Ok(Ihdr {
+            width,
+            height,
+            bit_depth,
+            color_type,
+            compression_method: 0,
+            filter_method: 0,
+            interlaced,
+        })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// bits per sample (or per palette index)
+    pub fn bit_depth(&self) -> u8 {
+        self.bit_depth
+    }
+
+    pub fn color_type(&self) -> ColorType {
+        self.color_type
+    }
+
+    pub fn compression_method(&self) -> u8 {
+        self.compression_method
+    }
+
+    pub fn filter_method(&self) -> u8 {
+        self.filter_method
+    }
+
+    /// true if the image uses Adam7 interlacing
+    pub fn is_interlaced(&self) -> bool {
+        self.interlaced
+    }
+
+    /// bits per pixel, all samples together
+    pub fn bits_per_pixel(&self) -> usize {
+        self.color_type.channels() * self.bit_depth as usize
+    }
+
+    pub fn to_chunk(&self) -> Chunk {
+        let mut data = self.width.to_be_bytes().to_vec();
+        data.extend_from_slice(&self.height.to_be_bytes());
+        data.extend_from_slice(&[
+            self.bit_depth,
+            self.color_type as u8,
+            self.compression_method,
+            self.filter_method,
+            self.interlaced as u8,
+        ]);
+        Chunk::new(ChunkType::from_str("IHDR").unwrap(), data)
+    }
+}
+
+impl TryFrom<&Chunk> for Ihdr {
+    type Error = crate::Error;
+
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        if &chunk.chunk_type().bytes() != b"IHDR" {
+            return Err(IhdrError::NotIhdr.into());
+        }
+        let Ok::<&[u8; 13], _>(data) = chunk.data().try_into() else {
+            return Err(IhdrError::BadLength(chunk.data().len()).into());
+        };
+
+        let dimension = |i: usize| <u32>::from_be_bytes(data[i..i + 4].try_into().unwrap());
+        let color_type = ColorType::try_from(data[9])?;
+        let mut ihdr = Ihdr::new(dimension(0), dimension(4), data[8], color_type, false)?;
+        ihdr.compression_method = match data[10] {
+            0 => 0,
+            m => return Err(IhdrError::UnknownCompressionMethod(m).into()),
+        };
+        ihdr.filter_method = match data[11] {
+            0 => 0,
+            m => return Err(IhdrError::UnknownFilterMethod(m).into()),
+        };
+        ihdr.interlaced = match data[12] {
+            0 => false,
+            1 => true,
+            m => return Err(IhdrError::UnknownInterlaceMethod(m).into()),
+        };
+        Ok(ihdr)
+    }
+}
+
+impl Display for Ihdr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}x{}, {} bit {}, {}",
+            self.width,
+            self.height,
+            self.bit_depth,
+            self.color_type,
+            if self.interlaced {
+                "Adam7 interlaced"
+            } else {
+                "not interlaced"
+            }
+        )
+    }
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum IhdrError {
+    // chunk isn't an IHDR chunk
+    NotIhdr,
+    // IHDR data has to be exactly 13 bytes long
+    BadLength(usize),
+    // width or height is 0 or too big
+    BadDimension(u32),
+    UnknownColorType(u8),
+    // bit depth isn't allowed for this color type
+    BadBitDepth {
+        color_type: ColorType,
+        bit_depth: u8,
+    },
+    UnknownCompressionMethod(u8),
+    UnknownFilterMethod(u8),
+    UnknownInterlaceMethod(u8),
+}
+
+impl Display for IhdrError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IhdrError::NotIhdr => write!(f, "Chunk is not an IHDR chunk"),
+            IhdrError::BadLength(l) => {
+                write!(f, "IHDR chunk has {l} bytes of data, but it needs 13")
+            }
+            IhdrError::BadDimension(d) => write!(
+                f,
+                "Image dimension {d} is invalid, it has to be between 1 and {MAX_DIMENSION}"
+            ),
+            IhdrError::UnknownColorType(c) => write!(f, "Unknown color type {c}"),
+            IhdrError::BadBitDepth {
+                color_type,
+                bit_depth,
+            } => write!(
+                f,
+                "Bit depth {bit_depth} is not allowed for {color_type} images"
+            ),
+            IhdrError::UnknownCompressionMethod(m) => {
+                write!(f, "Unknown compression method {m}")
+            }
+            IhdrError::UnknownFilterMethod(m) => write!(f, "Unknown filter method {m}"),
+            IhdrError::UnknownInterlaceMethod(m) => write!(f, "Unknown interlace method {m}"),
+        }
+    }
+}
+
+impl Error for IhdrError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ihdr_chunk(data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str("IHDR").unwrap(), data.to_vec())
+    }
+
+    #[test]
+    fn test_parse() {
+        let chunk = ihdr_chunk(&[0, 0, 0, 50, 0, 0, 0, 40, 8, 6, 0, 0, 0]);
+        let ihdr = Ihdr::try_from(&chunk).unwrap();
+        assert_eq!(ihdr.width(), 50);
+        assert_eq!(ihdr.height(), 40);
+        assert_eq!(ihdr.bit_depth(), 8);
+        assert_eq!(ihdr.color_type(), ColorType::Rgba);
+        assert!(!ihdr.is_interlaced());
+        assert_eq!(ihdr.bits_per_pixel(), 32);
+        assert_eq!(ihdr.to_chunk().as_bytes(), chunk.as_bytes());
+    }
+
+    #[test]
+    fn test_display() {
+        let ihdr = Ihdr::new(640, 480, 16, ColorType::Rgb, true).unwrap();
+        assert_eq!(ihdr.to_string(), "640x480, 16 bit RGB, Adam7 interlaced");
+    }
+
+    #[test]
+    fn test_bad_bit_depth() {
+        assert!(Ihdr::new(1, 1, 4, ColorType::Grayscale, false).is_ok());
+        assert!(matches!(
+            Ihdr::new(1, 1, 4, ColorType::Rgb, false),
+            Err(crate::Error::Ihdr(IhdrError::BadBitDepth {
+                bit_depth: 4,
+                ..
+            }))
+        ));
+        assert!(Ihdr::new(1, 1, 16, ColorType::Indexed, false).is_err());
+    }
+
+    #[test]
+    fn test_invalid_fields() {
+        let valid = [0, 0, 0, 1, 0, 0, 0, 1, 8, 0, 0, 0, 0];
+        for (i, value) in [(3, 0), (9, 1), (10, 1), (11, 1), (12, 2)] {
+            let mut data = valid;
+            data[i] = value;
+            assert!(Ihdr::try_from(&ihdr_chunk(&data)).is_err(), "byte {i}");
+        }
+        assert!(matches!(
+            Ihdr::try_from(&ihdr_chunk(&valid[..12])),
+            Err(crate::Error::Ihdr(IhdrError::BadLength(12)))
+        ));
+    }
+}
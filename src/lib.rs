@@ -0,0 +1,43 @@
+//! Hide (and optionally encrypt) secret messages in PNG files.
+//!
+//! The [`Png`], [`Chunk`] and [`ChunkType`] types can be used to parse and edit PNG files,
+//! while [`encode`], [`decode`] and [`remove`] work on the secret messages hidden in them.
+//! Messages are arbitrary bytes, [`decode_text`] additionally checks they are UTF-8 text.
+//! [`embed_payload`] and [`extract_payload`] hide messages in the pixels themselves instead
+//! (or in the palette indices of indexed images).
+
+pub mod alpha;
+pub mod analyze;
+pub mod capacity;
+pub mod chunk;
+pub mod chunk_type;
+pub mod crypt;
+pub mod error;
+pub mod ihdr;
+pub mod lsb;
+pub mod palette;
+pub mod payload;
+pub mod pixels;
+pub mod png;
+pub mod provenance;
+pub mod secret;
+pub mod sequence;
+pub mod signature;
+pub mod text_chunk;
+pub mod validate;
+
+#[cfg(test)]
+pub(crate) mod test_util;
+
+pub use chunk::Chunk;
+pub use chunk_type::ChunkType;
+pub use error::{Error, Result};
+pub use ihdr::{ColorType, Ihdr};
+pub use lsb::LsbOptions;
+pub use payload::Payload;
+pub use png::{Placement, Png};
+pub use secret::{
+    DecodeOptions, EncodeOptions, decode, decode_payload, decode_text, embed_payload, encode,
+    encode_payload, extract_payload, remove, remove_all, verify,
+};
+pub use text_chunk::TextChunk;
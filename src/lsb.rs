@@ -0,0 +1,719 @@
+//! Hiding data in the least significant bits of the pixel samples.
+//!
+//! The data is prefixed with a small header and written bit by bit (most significant bit
+//! of every byte first) into the lowest bits of the chosen channels, sample after sample:
+//!
+//! ```text
+//! magic     4 bytes   0x89 "PGL"
+//! version   1 byte    currently 1
+//! length    4 bytes   big endian length of data
+//! data
+//! ```
+//!
+//! Only 8 and 16 bit images without a palette are supported. In 16 bit images only the
+//! low byte of every sample is changed, apart from the carry of LSB matching.
+//!
+//! Without a [`StegoKey`] the samples are used in order from the top-left pixel, which is
+//! the first place steganalysis looks. With a key the samples are shuffled by ChaCha20
+//! seeded with it, so the data is scattered over the whole image and can only be found
+//! again with the same key. Keys derived from a passphrase are salted with the image
+//! header, which embedding never changes, so passphrases have to be guessed for every
+//! image on its own.
+//!
+//! Changes in smooth areas are the easiest to detect. Adaptive embedding uses the samples
+//! that differ most from their neighbors first, so data that doesn't fill the image
+//! ends up in busy areas. Only the bits above the ones that change are compared, so the
+//! same order is found again when extracting.
+//!
+//! LSB matching changes a sample by +1 or -1 at random instead of setting its low bit,
+//! which doesn't leave the pairs of values 2k and 2k+1 the chi-square attack looks for.
+//! It only works with 1 bit per sample. Together with adaptive embedding it never
+//! changes the bits that are compared, so there half of the changes are still plain
+//! replacements.
+
+use std::{error::Error, fmt::Display, str::FromStr};
+
+use chacha20poly1305::aead::OsRng;
+use rand_chacha::ChaCha20Rng;
+use rand_chacha::rand_core::{RngCore, SeedableRng};
+
+use crate::Result;
+use crate::crypt::Kdf;
+use crate::ihdr::{ColorType, Ihdr};
+use crate::pixels::Pixels;
+
+/// first bytes hidden in the pixels
+pub const MAGIC: [u8; 4] = [0x89, b'P', b'G', b'L'];
+const VERSION: u8 = 1;
+/// length of the header in front of the hidden data
+pub const HEADER_LEN: usize = 9;
+/// more bits per sample would visibly change the image
+pub const MAX_BITS: u8 = 4;
+/// start of the salt of the key derivation, the key has to be found again without
+/// anything stored, so the rest of it is the image header
+const KEY_SALT: &[u8] = b"pngecret lsb slots";
+
+/// channels data is hidden in
+///
+/// in grayscale images any of red, green and blue selects the gray channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Channels {
+    pub red: bool,
+    pub green: bool,
+    pub blue: bool,
+    pub alpha: bool,
+}
+
+impl Default for Channels {
+    /// the color channels, changing alpha can show in transparent areas
+    fn default() -> Self {
+        Channels {
+            red: true,
+            green: true,
+            blue: true,
+            alpha: false,
+        }
+    }
+}
+
+impl FromStr for Channels {
+    type Err = crate::Error;
+
+    /// parse channels like "rgb" or "ga", one letter per channel
+    fn from_str(s: &str) -> Result<Self> {
+        let mut channels = Channels::NONE;
+        for c in s.chars() {
+            match c.to_ascii_lowercase() {
+                'r' => channels.red = true,
+                'g' => channels.green = true,
+                'b' => channels.blue = true,
+                'a' => channels.alpha = true,
+                _ => return Err(LsbError::UnknownChannel(c).into()),
+            }
+        }
+        if channels == Channels::NONE {
+            return Err(LsbError::NoChannels.into());
+        }
+        Ok(channels)
+    }
+}
+
+impl Channels {
+    const NONE: Channels = Channels {
+        red: false,
+        green: false,
+        blue: false,
+        alpha: false,
+    };
+}
+
+impl Display for Channels {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (selected, c) in [
+            (self.red, 'r'),
+            (self.green, 'g'),
+            (self.blue, 'b'),
+            (self.alpha, 'a'),
+        ] {
+            if selected {
+                write!(f, "{c}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// seed of the order the samples are used in
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct StegoKey([u8; 32]);
+
+impl StegoKey {
+    /// key derived from a passphrase with kdf for the image with this header
+    ///
+    /// the data found with the key starts with a known magic, so a fast hash would let
+    /// passphrases be guessed around the encryption, that's why this is Argon2id too.
+    /// If the passphrase encrypts the data as well, use the kdf of its envelope, so the
+    /// key isn't cheaper to guess than the encryption
+    pub fn derive(passphrase: &str, ihdr: &Ihdr, kdf: Kdf) -> Result<StegoKey> {
+        let mut salt = KEY_SALT.to_vec();
+        salt.extend_from_slice(ihdr.to_chunk().data());
+        Ok(StegoKey(kdf.derive_key(passphrase, &salt)?))
+    }
+}
+
+impl From<[u8; 32]> for StegoKey {
+    fn from(seed: [u8; 32]) -> Self {
+        StegoKey(seed)
+    }
+}
+
+impl std::fmt::Debug for StegoKey {
+    /// secrets don't belong in logs
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("StegoKey(..)")
+    }
+}
+
+/// where in the pixels data is hidden
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LsbOptions {
+    pub channels: Channels,
+    /// number of low bits used in every sample, 1 to [`MAX_BITS`]
+    pub bits: u8,
+    /// shuffles the samples, None uses them in order
+    pub key: Option<StegoKey>,
+    /// hide the data in fully transparent pixels if there are enough of them, see
+    /// [`crate::alpha`] (the other options except key are only used if there aren't)
+    pub transparent: bool,
+    /// use the samples in busy areas first, has to be the same when extracting
+    pub adaptive: bool,
+    /// change samples by ±1 instead of replacing their low bit, only works with 1 bit
+    pub matching: bool,
+}
+
+impl Default for LsbOptions {
+    fn default() -> Self {
+        LsbOptions {
+            channels: Channels::default(),
+            bits: 1,
+            key: None,
+            transparent: false,
+            adaptive: false,
+            matching: false,
+        }
+    }
+}
+
+/// index of the byte of every sample data can be hidden in, in the order they are used
+fn slots(pixels: &Pixels, options: &LsbOptions) -> Result<Vec<usize>> {
+    if !(1..=MAX_BITS).contains(&options.bits) {
+        return Err(LsbError::BadBits(options.bits).into());
+    }
+    let ihdr = pixels.ihdr();
+    if ihdr.bit_depth() < 8 || ihdr.color_type() == ColorType::Indexed {
+        return Err(LsbError::UnsupportedImage {
+            color_type: ihdr.color_type(),
+            bit_depth: ihdr.bit_depth(),
+        }
+        .into());
+    }
+
+    let Channels {
+        red,
+        green,
+        blue,
+        alpha,
+    } = options.channels;
+    let gray = red || green || blue;
+    let selected: &[bool] = match ihdr.color_type() {
+        ColorType::Grayscale | ColorType::Indexed => &[gray],
+        ColorType::Rgb => &[red, green, blue],
+        ColorType::GrayscaleAlpha => &[gray, alpha],
+        ColorType::Rgba => &[red, green, blue, alpha],
+    };
+    if !selected.contains(&true) {
+        return Err(LsbError::NoChannels.into());
+    }
+
+    // the low byte comes last in 16 bit samples
+    let sample_len = ihdr.bit_depth() as usize / 8;
+    let samples = pixels.data().len() / sample_len;
+    let mut slots: Vec<usize> = (0..samples)
+        .filter(|i| selected[i % selected.len()])
+        .map(|i| i * sample_len + sample_len - 1)
+        .collect();
+    if let Some(key) = options.key {
+        shuffle(&mut slots, key);
+    }
+    if options.adaptive {
+        // stable, so samples with the same score stay shuffled
+        let texture = texture(pixels, options.bits);
+        slots.sort_by_key(|slot| std::cmp::Reverse(texture[slot / sample_len]));
+    }
+    Ok(slots)
+}
+
+/// how different every sample is from the same channel of the pixels next to it
+///
+/// only the bits above the lowest bits + 1 are compared, embedding never changes them
+fn texture(pixels: &Pixels, bits: u8) -> Vec<u32> {
+    let ihdr = pixels.ihdr();
+    let sample_len = ihdr.bit_depth() as usize / 8;
+    let channels = ihdr.color_type().channels();
+    let values: Vec<u32> = pixels
+        .data()
+        .chunks_exact(sample_len)
+        .map(|s| s.iter().fold(0, |acc, b| acc << 8 | *b as u32) >> (bits + 1))
+        .collect();
+
+    let mut scores = vec![0; values.len()];
+    let mut start = 0;
+    for (width, height) in pixels.passes() {
+        let (width, height) = (width as usize, height as usize);
+        let row = width * channels;
+        for y in 0..height {
+            for x in 0..width {
+                for c in 0..channels {
+                    let i = start + y * row + x * channels + c;
+                    let neighbors = [
+                        (x > 0).then(|| i - channels),
+                        (x + 1 < width).then(|| i + channels),
+                        (y > 0).then(|| i - row),
+                        (y + 1 < height).then(|| i + row),
+                    ];
+                    scores[i] = neighbors
+                        .into_iter()
+                        .flatten()
+                        .map(|n| values[i].abs_diff(values[n]))
+                        .sum();
+                }
+            }
+        }
+        start += height * row;
+    }
+    scores
+}
+
+/// Fisher-Yates shuffle seeded with key
+pub(crate) fn shuffle<T>(slots: &mut [T], StegoKey(seed): StegoKey) {
+    let mut rng = ChaCha20Rng::from_seed(seed);
+    for i in (1..slots.len()).rev() {
+        // reject the top of the range that doesn't fit evenly, it would favor low indices
+        let n = i as u64 + 1;
+        let limit = u64::MAX / n * n;
+        let j = loop {
+            let x = rng.next_u64();
+            if x < limit {
+                break x % n;
+            }
+        };
+        slots.swap(i, j as usize);
+    }
+}
+
+/// number of bytes that can be hidden in pixels
+pub fn capacity(pixels: &Pixels, options: &LsbOptions) -> Result<usize> {
+    let bits = slots(pixels, options)?.len() * options.bits as usize;
+    Ok((bits / 8).saturating_sub(HEADER_LEN))
+}
+
+/// hide data in the low bits of pixels
+pub fn embed(pixels: &mut Pixels, data: &[u8], options: &LsbOptions) -> Result<()> {
+    if options.matching && options.bits != 1 {
+        return Err(LsbError::MatchingBits(options.bits).into());
+    }
+    let slots = slots(pixels, options)?;
+    let bits = options.bits as usize;
+    let sample_len = pixels.ihdr().bit_depth() as usize / 8;
+    let mut seed = [0; 32];
+    OsRng.fill_bytes(&mut seed);
+    let mut rng = ChaCha20Rng::from_seed(seed);
+    let samples = pixels.data_mut();
+    for (k, bit) in to_bits(data, slots.len() * bits)?.into_iter().enumerate() {
+        let slot = slots[k / bits];
+        if !options.matching {
+            let shift = bits - 1 - k % bits;
+            samples[slot] = samples[slot] & !(1 << shift) | bit << shift;
+            continue;
+        }
+        if samples[slot] & 1 == bit {
+            continue;
+        }
+        let sample = &mut samples[slot + 1 - sample_len..=slot];
+        let low = sample[sample_len - 1];
+        let up = if sample.iter().all(|b| *b == 0) {
+            true
+        } else if sample.iter().all(|b| *b == u8::MAX) {
+            false
+        } else {
+            // adaptive embedding stays within the same multiple of 4, see texture
+            match (options.adaptive, low % 4) {
+                (true, 0) => true,
+                (true, 3) => false,
+                _ => rng.next_u32() & 1 == 0,
+            }
+        };
+        step(sample, up);
+    }
+    Ok(())
+}
+
+/// add or subtract 1 from a big endian sample that doesn't overflow
+fn step(sample: &mut [u8], up: bool) {
+    for byte in sample.iter_mut().rev() {
+        let (value, carry) = if up {
+            byte.overflowing_add(1)
+        } else {
+            byte.overflowing_sub(1)
+        };
+        *byte = value;
+        if !carry {
+            break;
+        }
+    }
+}
+
+/// read the data hidden in pixels, None if there is none
+pub fn extract(pixels: &Pixels, options: &LsbOptions) -> Result<Option<Vec<u8>>> {
+    let slots = slots(pixels, options)?;
+    let bits = options.bits as usize;
+    let samples = pixels.data();
+    from_bits(slots.len() * bits, |k| {
+        let shift = bits - 1 - k % bits;
+        (samples[slots[k / bits]] >> shift) & 1
+    })
+}
+
+/// header and data as single bits, most significant bit of every byte first
+///
+/// fails if they don't fit into the available number of bits
+pub(crate) fn to_bits(data: &[u8], available: usize) -> Result<Vec<u8>> {
+    let capacity = (available / 8).saturating_sub(HEADER_LEN);
+    let length = <u32>::try_from(data.len())
+        .ok()
+        .filter(|_| data.len() <= capacity)
+        .ok_or(LsbError::TooLarge {
+            needed: data.len(),
+            capacity,
+        })?;
+
+    let mut stream = MAGIC.to_vec();
+    stream.push(VERSION);
+    stream.extend_from_slice(&length.to_be_bytes());
+    stream.extend_from_slice(data);
+    Ok(stream
+        .iter()
+        .flat_map(|b| (0..8).rev().map(move |i| (b >> i) & 1))
+        .collect())
+}
+
+/// data written by [`to_bits`], read_bit gives the bit at an index below available
+///
+/// None if the bits don't start with the header
+pub(crate) fn from_bits(
+    available: usize,
+    read_bit: impl Fn(usize) -> u8,
+) -> Result<Option<Vec<u8>>> {
+    let read = |start: usize, len: usize| -> Vec<u8> {
+        (start..start + len)
+            .map(|byte| (0..8).fold(0, |acc, i| acc << 1 | read_bit(byte * 8 + i)))
+            .collect()
+    };
+
+    let available = available / 8;
+    if available < HEADER_LEN {
+        return Ok(None);
+    }
+    let header = read(0, HEADER_LEN);
+    if header[..4] != MAGIC {
+        return Ok(None);
+    }
+    if header[4] != VERSION {
+        return Err(LsbError::UnsupportedVersion(header[4]).into());
+    }
+    let length = <u32>::from_be_bytes(header[5..9].try_into().unwrap()) as usize;
+    if length > available - HEADER_LEN {
+        return Err(LsbError::Truncated.into());
+    }
+
+    Ok(Some(read(HEADER_LEN, length)))
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum LsbError {
+    // palette images and bit depths below 8 can't hold data in their samples
+    UnsupportedImage {
+        color_type: ColorType,
+        bit_depth: u8,
+    },
+    // none of the chosen channels exist in the image
+    NoChannels,
+    UnknownChannel(char),
+    // number of bits per sample is 0 or above MAX_BITS
+    BadBits(u8),
+    // LSB matching with more than 1 bit per sample
+    MatchingBits(u8),
+    TooLarge {
+        needed: usize,
+        capacity: usize,
+    },
+    UnsupportedVersion(u8),
+    // header says there is more data than the image can hold
+    Truncated,
+}
+
+impl Display for LsbError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LsbError::UnsupportedImage {
+                color_type,
+                bit_depth,
+            } => write!(
+                f,
+                "Can't hide data in the pixels of {bit_depth} bit {color_type} images"
+            ),
+            LsbError::NoChannels => write!(f, "None of the chosen channels are in the image"),
+            LsbError::UnknownChannel(c) => {
+                write!(f, "Unknown channel {c:?}, use r, g, b and a")
+            }
+            LsbError::BadBits(b) => write!(
+                f,
+                "Can't use {b} bits per sample, it has to be between 1 and {MAX_BITS}"
+            ),
+            LsbError::MatchingBits(b) => write!(
+                f,
+                "LSB matching only works with 1 bit per sample, not {b} bits"
+            ),
+            LsbError::TooLarge { needed, capacity } => write!(
+                f,
+                "Data is {needed} bytes long, but the pixels can only hold {capacity} bytes"
+            ),
+            LsbError::UnsupportedVersion(v) => {
+                write!(f, "Data in the pixels has unsupported version {v}")
+            }
+            LsbError::Truncated => write!(f, "Data in the pixels is truncated"),
+        }
+    }
+}
+
+impl Error for LsbError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    fn dice() -> Pixels {
+        Pixels::from_png(&test_util::dice()).unwrap()
+    }
+
+    #[test]
+    fn test_embed_extract() {
+        for bits in 1..=MAX_BITS {
+            let options = LsbOptions {
+                bits,
+                ..Default::default()
+            };
+            let mut pixels = dice();
+            let original = pixels.clone();
+            embed(&mut pixels, b"Hello World!", &options).unwrap();
+            assert_eq!(
+                extract(&pixels, &options).unwrap().as_deref(),
+                Some(&b"Hello World!"[..])
+            );
+
+            // only the low bits of the color channels changed
+            let mask = !((1u8 << bits) - 1);
+            for (i, (a, b)) in original.data().iter().zip(pixels.data()).enumerate() {
+                if i % 4 == 3 {
+                    assert_eq!(a, b);
+                } else {
+                    assert_eq!(a & mask, b & mask);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_capacity() {
+        // 50x50 pixels with 3 color channels
+        let pixels = dice();
+        assert_eq!(
+            capacity(&pixels, &LsbOptions::default()).unwrap(),
+            50 * 50 * 3 / 8 - HEADER_LEN
+        );
+        let options = LsbOptions {
+            channels: "a".parse().unwrap(),
+            bits: 2,
+            ..Default::default()
+        };
+        assert_eq!(
+            capacity(&pixels, &options).unwrap(),
+            50 * 50 * 2 / 8 - HEADER_LEN
+        );
+    }
+
+    #[test]
+    fn test_derive_per_image() {
+        let ihdr = *dice().ihdr();
+        let other = Ihdr::new(50, 51, 8, ColorType::Rgba, false).unwrap();
+        let kdf = Kdf::Argon2id {
+            memory: 16,
+            iterations: 1,
+            parallelism: 1,
+        };
+        let key = StegoKey::derive("passphrase", &ihdr, kdf).unwrap();
+        assert_eq!(StegoKey::derive("passphrase", &ihdr, kdf).unwrap(), key);
+        assert_ne!(StegoKey::derive("passphrase", &other, kdf).unwrap(), key);
+        let costlier = Kdf::Argon2id {
+            memory: 32,
+            iterations: 1,
+            parallelism: 1,
+        };
+        assert_ne!(
+            StegoKey::derive("passphrase", &ihdr, costlier).unwrap(),
+            key
+        );
+    }
+
+    #[test]
+    fn test_keyed_slots() {
+        let keyed = |seed| LsbOptions {
+            key: Some(StegoKey::from([seed; 32])),
+            ..Default::default()
+        };
+        let mut pixels = dice();
+        let original = pixels.clone();
+        embed(&mut pixels, b"Hello World!", &keyed(1)).unwrap();
+        assert_eq!(
+            extract(&pixels, &keyed(1)).unwrap().as_deref(),
+            Some(&b"Hello World!"[..])
+        );
+        // without the key the data is not where it is looked for
+        assert!(extract(&pixels, &LsbOptions::default()).unwrap().is_none());
+        assert!(extract(&pixels, &keyed(2)).unwrap().is_none());
+
+        // 168 bits are scattered over the whole image, not packed at the start
+        let changed: Vec<usize> = original
+            .data()
+            .iter()
+            .zip(pixels.data())
+            .enumerate()
+            .filter_map(|(i, (a, b))| (a != b).then_some(i))
+            .collect();
+        assert!(*changed.last().unwrap() > pixels.data().len() / 2);
+        // every sample is used once, capacity is the same
+        assert_eq!(
+            capacity(&pixels, &keyed(1)).unwrap(),
+            capacity(&pixels, &LsbOptions::default()).unwrap()
+        );
+        let mut slots = slots(&pixels, &keyed(1)).unwrap();
+        slots.sort();
+        assert_eq!(
+            slots,
+            super::slots(&pixels, &LsbOptions::default()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_adaptive() {
+        let adaptive = LsbOptions {
+            adaptive: true,
+            key: Some(StegoKey::from([4; 32])),
+            ..Default::default()
+        };
+        let mut pixels = dice();
+        let original = pixels.clone();
+        embed(&mut pixels, b"Hello World!", &adaptive).unwrap();
+        assert_eq!(
+            extract(&pixels, &adaptive).unwrap().as_deref(),
+            Some(&b"Hello World!"[..])
+        );
+        // embedding doesn't change the order
+        assert_eq!(
+            slots(&pixels, &adaptive).unwrap(),
+            slots(&original, &adaptive).unwrap()
+        );
+
+        // the changed samples are in busier areas than the average sample
+        let texture = texture(&original, 1);
+        let mean = |scores: &mut dyn Iterator<Item = u32>| {
+            let scores: Vec<u32> = scores.collect();
+            scores.iter().sum::<u32>() as f64 / scores.len() as f64
+        };
+        let changed = mean(
+            &mut (0..texture.len())
+                .filter(|i| original.data()[*i] != pixels.data()[*i])
+                .map(|i| texture[i]),
+        );
+        assert!(changed > 2.0 * mean(&mut texture.iter().copied()));
+    }
+
+    #[test]
+    fn test_matching() {
+        let matching = LsbOptions {
+            matching: true,
+            adaptive: true,
+            ..Default::default()
+        };
+        let mut pixels = dice();
+        let original = pixels.clone();
+        let data = [0xa5; 200];
+        embed(&mut pixels, &data, &matching).unwrap();
+        assert_eq!(extract(&pixels, &matching).unwrap().unwrap(), data);
+
+        // every change is ±1, but not every change just flips the low bit
+        let changes: Vec<(u8, u8)> = original
+            .data()
+            .iter()
+            .zip(pixels.data())
+            .filter(|(a, b)| a != b)
+            .map(|(a, b)| (*a, *b))
+            .collect();
+        assert!(changes.iter().all(|(a, b)| a.abs_diff(*b) == 1));
+        assert!(changes.iter().any(|(a, b)| a ^ b != 1));
+
+        // without adaptive embedding every value can go either way
+        let matching = LsbOptions {
+            adaptive: false,
+            ..matching
+        };
+        let mut pixels = dice();
+        embed(&mut pixels, &[0x5a; 900], &matching).unwrap();
+        let mut directions = [(false, false); 4];
+        for (a, b) in original.data().iter().zip(pixels.data()) {
+            let (up, down) = &mut directions[*a as usize % 4];
+            *up |= b > a;
+            *down |= b < a;
+        }
+        assert_eq!(directions, [(true, true); 4]);
+
+        let two_bits = LsbOptions {
+            bits: 2,
+            ..matching
+        };
+        assert!(matches!(
+            embed(&mut pixels, &data, &two_bits),
+            Err(crate::Error::Lsb(LsbError::MatchingBits(2)))
+        ));
+    }
+
+    #[test]
+    fn test_step() {
+        let mut sample = [0x12, 0xff];
+        step(&mut sample, true);
+        assert_eq!(sample, [0x13, 0x00]);
+        step(&mut sample, false);
+        assert_eq!(sample, [0x12, 0xff]);
+        let mut sample = [7];
+        step(&mut sample, false);
+        assert_eq!(sample, [6]);
+    }
+
+    #[test]
+    fn test_too_large() {
+        let mut pixels = dice();
+        let data = vec![0; capacity(&pixels, &LsbOptions::default()).unwrap() + 1];
+        assert!(matches!(
+            embed(&mut pixels, &data, &LsbOptions::default()),
+            Err(crate::Error::Lsb(LsbError::TooLarge { .. }))
+        ));
+    }
+
+    #[test]
+    fn test_nothing_hidden() {
+        assert!(extract(&dice(), &LsbOptions::default()).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_parse_channels() {
+        let channels: Channels = "RGBA".parse().unwrap();
+        assert_eq!(channels.to_string(), "rgba");
+        assert!("".parse::<Channels>().is_err());
+        assert!(matches!(
+            "rgx".parse::<Channels>(),
+            Err(crate::Error::Lsb(LsbError::UnknownChannel('x')))
+        ));
+    }
+}
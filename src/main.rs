@@ -1,26 +1,46 @@
+use std::error::Error;
+use std::process::ExitCode;
+
 use args::Cli;
 use clap::Parser;
 use command::*;
+use pngecret::Result;
 
 mod args;
-mod chunk;
-mod chunk_type;
 mod command;
-mod crypt;
-mod png;
-
-pub type Error = Box<dyn std::error::Error>;
-pub type Result<T> = std::result::Result<T, Error>;
 
-fn main() -> Result<()> {
+fn main() -> ExitCode {
     let cli = Cli::parse();
 
+    if let Err(e) = run(cli) {
+        // print the error and everything that caused it
+        eprintln!("Error: {e}");
+        let mut source = e.source();
+        while let Some(s) = source {
+            eprintln!("Caused by: {s}");
+            source = s.source();
+        }
+        return ExitCode::FAILURE;
+    }
+
+    ExitCode::SUCCESS
+}
+
+fn run(cli: Cli) -> Result<()> {
     if let Some(c) = cli.command {
         match c {
             args::PngecretArgs::Encode(encode_args) => encode(encode_args)?,
             args::PngecretArgs::Decode(decode_args) => decode(decode_args)?,
             args::PngecretArgs::Remove(remove_args) => remove(remove_args)?,
             args::PngecretArgs::Print(print_args) => print(print_args)?,
+            args::PngecretArgs::Embed(embed_args) => embed(embed_args)?,
+            args::PngecretArgs::Extract(extract_args) => extract(extract_args)?,
+            args::PngecretArgs::Validate(validate_args) => validate(validate_args)?,
+            args::PngecretArgs::Capacity(capacity_args) => capacity(capacity_args)?,
+            args::PngecretArgs::Analyze(analyze_args) => analyze(analyze_args)?,
+            args::PngecretArgs::Keygen(keygen_args) => keygen(keygen_args)?,
+            args::PngecretArgs::Sign(sign_args) => sign(sign_args)?,
+            args::PngecretArgs::Verify(verify_args) => verify(verify_args)?,
         }
     }
 
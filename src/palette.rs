@@ -0,0 +1,451 @@
+//! Hiding data in the palette indices of indexed images, the way EzStego does it.
+//!
+//! Flipping the low bit of a palette index can swap a color for a completely different
+//! one. Instead, the palette entries are paired up by how alike they look, closest
+//! colors first, and every pixel with a paired color carries one bit: 0 for the entry
+//! with the lower index, 1 for the other one. Hiding a bit at most swaps a pixel to the
+//! other color of its pair. Colors without a look-alike are left alone.
+//!
+//! The pairs only depend on PLTE and tRNS, so extracting finds the same pairs again. If
+//! the pairs can't hold the data and the bit depth leaves room in the palette, near
+//! duplicates of the unpaired colors are added to it first.
+//!
+//! The data has the same header as in [`crate::lsb`], and with a [`StegoKey`] the
+//! pixels are used in a shuffled order.
+
+use std::{error::Error, fmt::Display, str::FromStr};
+
+use crate::Result;
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::ihdr::ColorType;
+use crate::lsb::{self, StegoKey};
+use crate::pixels::{PixelError, Pixels};
+use crate::png::Png;
+
+/// colors further apart than this (about 4 levels in every channel) are never paired
+pub const MAX_DISTANCE: u32 = 128;
+
+/// colors of an indexed image, with the alpha values from tRNS
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Palette {
+    entries: Vec<[u8; 4]>,
+    // tRNS can be shorter than PLTE, and has to stay absent if it was
+    trns_len: Option<usize>,
+}
+
+impl Palette {
+    /// read PLTE and tRNS of an indexed png
+    pub fn from_png(png: &Png) -> Result<Palette> {
+        let ihdr = png.ihdr()?;
+        if ihdr.color_type() != ColorType::Indexed {
+            return Err(PaletteError::NotIndexed(ihdr.color_type()).into());
+        }
+        let plte = png
+            .first_chunk_by_type("PLTE")
+            .ok_or(PaletteError::MissingPalette)?
+            .data();
+        let max = 1 << ihdr.bit_depth();
+        if plte.is_empty() || plte.len() % 3 != 0 || plte.len() / 3 > max {
+            return Err(PaletteError::BadPalette(plte.len()).into());
+        }
+        let trns = png.first_chunk_by_type("tRNS").map(|c| c.data());
+        if trns.is_some_and(|t| t.len() > plte.len() / 3) {
+            return Err(PaletteError::BadTransparency.into());
+        }
+
+        let alpha = |i: usize| trns.and_then(|t| t.get(i)).copied().unwrap_or(255);
+        Ok(Palette {
+            entries: plte
+                .chunks_exact(3)
+                .enumerate()
+                .map(|(i, c)| [c[0], c[1], c[2], alpha(i)])
+                .collect(),
+            trns_len: trns.map(<[u8]>::len),
+        })
+    }
+
+    /// red, green, blue and alpha of every entry
+    pub fn entries(&self) -> &[[u8; 4]] {
+        &self.entries
+    }
+
+    /// the entry every entry is paired with, if any
+    ///
+    /// closest colors are paired first, ties go to the lower indices
+    pub fn pairs(&self) -> Vec<Option<usize>> {
+        let n = self.entries.len();
+        let mut candidates: Vec<(u32, usize, usize)> = (0..n)
+            .flat_map(|i| (i + 1..n).map(move |j| (i, j)))
+            .map(|(i, j)| (distance(self.entries[i], self.entries[j]), i, j))
+            .filter(|(d, _, _)| *d <= MAX_DISTANCE)
+            .collect();
+        candidates.sort_unstable();
+
+        let mut pairs = vec![None; n];
+        for (_, i, j) in candidates {
+            if pairs[i].is_none() && pairs[j].is_none() {
+                pairs[i] = Some(j);
+                pairs[j] = Some(i);
+            }
+        }
+        pairs
+    }
+
+    /// palette with a near duplicate of every unpaired color in used, as long as there
+    /// is room for max entries
+    ///
+    /// used counts the pixels of every entry, the most used colors get a duplicate first
+    fn extended(&self, used: &[usize], max: usize) -> Palette {
+        let pairs = self.pairs();
+        let mut unpaired: Vec<usize> = (0..self.entries.len())
+            .filter(|&i| pairs[i].is_none() && used[i] > 0)
+            .collect();
+        unpaired.sort_by_key(|&i| std::cmp::Reverse(used[i]));
+
+        let mut extended = self.clone();
+        for i in unpaired {
+            if extended.entries.len() >= max {
+                break;
+            }
+            let color = self.entries[i];
+            // one level up or down in one channel, unless that color already exists
+            let duplicate = [2, 1, 0]
+                .into_iter()
+                .flat_map(|c| {
+                    [1, -1].map(|step| {
+                        let mut d = color;
+                        d[c] = color[c].checked_add_signed(step)?;
+                        Some(d)
+                    })
+                })
+                .flatten()
+                .find(|d| !extended.entries.contains(d));
+            if let Some(duplicate) = duplicate {
+                extended.entries.push(duplicate);
+            }
+        }
+        if extended.entries[self.entries.len()..]
+            .iter()
+            .any(|e| e[3] < 255)
+        {
+            extended.trns_len = Some(extended.entries.len());
+        }
+        extended
+    }
+
+    /// write the palette to the PLTE and tRNS chunks of png
+    fn write_to(&self, png: &mut Png) -> Result<()> {
+        let plte = self.entries.iter().flat_map(|e| &e[..3]).copied().collect();
+        replace(png, "PLTE", plte)?;
+        if let Some(len) = self.trns_len {
+            let trns = self.entries[..len].iter().map(|e| e[3]).collect();
+            replace(png, "tRNS", trns)?;
+        }
+        Ok(())
+    }
+}
+
+/// how different two colors look, 0 if both are invisible
+///
+/// the "redmean" approximation of perceived distance, with alpha weighted like green
+pub(crate) fn distance(a: [u8; 4], b: [u8; 4]) -> u32 {
+    if a[3] == 0 && b[3] == 0 {
+        return 0;
+    }
+    let [dr, dg, db, da] = [0, 1, 2, 3].map(|i| a[i].abs_diff(b[i]) as u32);
+    let red_mean = (a[0] as u32 + b[0] as u32) / 2;
+    (((512 + red_mean) * dr * dr) >> 8)
+        + 4 * dg * dg
+        + (((767 - red_mean) * db * db) >> 8)
+        + 4 * da * da
+}
+
+/// replace the data of the first chunk of type chunk_type, or add it after PLTE
+fn replace(png: &mut Png, chunk_type: &str, data: Vec<u8>) -> Result<()> {
+    let chunk = Chunk::try_new(ChunkType::from_str(chunk_type)?, data)?;
+    let position = |t: &str| {
+        png.chunks()
+            .iter()
+            .position(|c| c.chunk_type().bytes() == t.as_bytes())
+    };
+    match position(chunk_type) {
+        Some(index) => {
+            png.replace_chunk(index, chunk)?;
+        }
+        None => {
+            let plte = position("PLTE").ok_or(PaletteError::MissingPalette)?;
+            png.insert_chunk(chunk, crate::Placement::Index(plte + 1))?;
+        }
+    }
+    Ok(())
+}
+
+/// palette index of every pixel, in the order they are stored
+///
+/// one byte per pixel, since the bit depth is at most 8
+fn indices(pixels: &Pixels) -> Result<Vec<u8>> {
+    let ihdr = pixels.ihdr();
+    // carriers are numbered with u32
+    if ihdr.width() as u64 * ihdr.height() as u64 > u32::MAX as u64 {
+        return Err(PixelError::TooLarge.into());
+    }
+    let depth = ihdr.bit_depth() as usize;
+    let mask = ((1 << depth) - 1) as u8;
+    let data = pixels.data();
+    Ok(pixels
+        .pixel_offsets()
+        .map(|offset| (data[offset / 8] >> (8 - depth - offset % 8)) & mask)
+        .collect())
+}
+
+/// write indices back into the pixels, the reverse of indices
+fn set_indices(pixels: &mut Pixels, indices: &[u8]) {
+    let depth = pixels.ihdr().bit_depth() as usize;
+    for (offset, &index) in pixels.pixel_offsets().zip(indices) {
+        let shift = 8 - depth - offset % 8;
+        let mask = (((1 << depth) - 1) << shift) as u8;
+        let byte = &mut pixels.data_mut()[offset / 8];
+        *byte = *byte & !mask | ((index as usize) << shift) as u8 & mask;
+    }
+}
+
+/// pixels whose color is paired, as their number in indices, in the order they are used
+fn carriers(indices: &[u8], pairs: &[Option<usize>], key: Option<StegoKey>) -> Vec<u32> {
+    // indices checked that there are at most u32::MAX pixels
+    let mut carriers: Vec<u32> = (0..indices.len() as u32)
+        .filter(|&pixel| {
+            let index = indices[pixel as usize] as usize;
+            pairs.get(index).is_some_and(Option::is_some)
+        })
+        .collect();
+    if let Some(key) = key {
+        lsb::shuffle(&mut carriers, key);
+    }
+    carriers
+}
+
+/// number of pixels of every palette entry
+fn usage(palette: &Palette, indices: &[u8]) -> Vec<usize> {
+    let mut used = vec![0; palette.entries.len()];
+    for &index in indices {
+        if let Some(count) = used.get_mut(index as usize) {
+            *count += 1;
+        }
+    }
+    used
+}
+
+/// number of bytes the pixels hold with palette, used counts the pixels of every entry
+fn palette_capacity(palette: &Palette, used: &[usize]) -> usize {
+    let pairs = palette.pairs();
+    let bits: usize = (0..used.len())
+        .filter(|&i| pairs[i].is_some())
+        .map(|i| used[i])
+        .sum();
+    (bits / 8).saturating_sub(lsb::HEADER_LEN)
+}
+
+/// the palette pixels carry most bits with: palette itself if it's enough for needed
+/// bytes, else extended by near duplicates
+fn best_palette(png: &Png, indices: &[u8], needed: usize) -> Result<Palette> {
+    let palette = Palette::from_png(png)?;
+    let used = usage(&palette, indices);
+    if palette_capacity(&palette, &used) >= needed {
+        return Ok(palette);
+    }
+    let extended = palette.extended(&used, 1 << png.ihdr()?.bit_depth());
+    Ok(
+        if palette_capacity(&extended, &used) > palette_capacity(&palette, &used) {
+            extended
+        } else {
+            palette
+        },
+    )
+}
+
+/// number of bytes that can be hidden in the palette indices of png, with an extended
+/// palette if that holds more
+pub fn capacity(png: &Png) -> Result<usize> {
+    let indices = indices(&Pixels::from_png(png)?)?;
+    let palette = best_palette(png, &indices, usize::MAX)?;
+    Ok(palette_capacity(&palette, &usage(&palette, &indices)))
+}
+
+/// hide data in the palette indices of png, which has to be an indexed image
+///
+/// the palette is only changed if data doesn't fit otherwise
+pub fn embed(png: &mut Png, data: &[u8], key: Option<StegoKey>) -> Result<()> {
+    let mut pixels = Pixels::from_png(png)?;
+    let mut indices = indices(&pixels)?;
+    let palette = best_palette(png, &indices, data.len())?;
+    let pairs = palette.pairs();
+    let carriers = carriers(&indices, &pairs, key);
+    let bits = lsb::to_bits(data, carriers.len())?;
+
+    for (pixel, bit) in carriers.into_iter().zip(bits) {
+        // every index is in a pair, the lower index of the two means 0
+        let index = &mut indices[pixel as usize];
+        let partner = pairs[*index as usize].unwrap();
+        let is_one = *index as usize > partner;
+        if is_one != (bit == 1) {
+            *index = partner as u8;
+        }
+    }
+
+    if palette != Palette::from_png(png)? {
+        palette.write_to(png)?;
+    }
+    set_indices(&mut pixels, &indices);
+    pixels.write_to(png)
+}
+
+/// read the data hidden in the palette indices of png, None if there is none
+pub fn extract(png: &Png, key: Option<StegoKey>) -> Result<Option<Vec<u8>>> {
+    let indices = indices(&Pixels::from_png(png)?)?;
+    let pairs = Palette::from_png(png)?.pairs();
+    let carriers = carriers(&indices, &pairs, key);
+    lsb::from_bits(carriers.len(), |k| {
+        let index = indices[carriers[k] as usize] as usize;
+        (index > pairs[index].unwrap()) as u8
+    })
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum PaletteError {
+    // only indexed images have pixels that are palette indices
+    NotIndexed(ColorType),
+    MissingPalette,
+    // PLTE length isn't a multiple of 3, or has more entries than the bit depth allows
+    BadPalette(usize),
+    // tRNS has more entries than PLTE
+    BadTransparency,
+}
+
+impl Display for PaletteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PaletteError::NotIndexed(ct) => {
+                write!(f, "Only indexed images have a palette, not {ct} images")
+            }
+            PaletteError::MissingPalette => write!(f, "Indexed image has no PLTE chunk"),
+            PaletteError::BadPalette(len) => write!(f, "PLTE chunk of {len} bytes is invalid"),
+            PaletteError::BadTransparency => {
+                write!(f, "tRNS chunk has more entries than the palette")
+            }
+        }
+    }
+}
+
+impl Error for PaletteError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ihdr::Ihdr;
+    use crate::test_util::{dice, dice_indexed};
+    use flate2::{Compression, write::ZlibEncoder};
+    use std::io::Write;
+
+    /// 2 bit indexed png with the given palette, 8 pixels per row
+    fn two_bit(palette: &[[u8; 3]], rows: &[[u8; 2]]) -> Png {
+        let ihdr = Ihdr::new(8, rows.len() as u32, 2, ColorType::Indexed, false).unwrap();
+        let raw: Vec<u8> = rows.iter().flat_map(|r| [0, r[0], r[1]]).collect();
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&raw).unwrap();
+        let chunk = |t: &str, data: Vec<u8>| Chunk::new(ChunkType::from_str(t).unwrap(), data);
+        Png::from_chunks(vec![
+            ihdr.to_chunk(),
+            chunk("PLTE", palette.concat()),
+            chunk("IDAT", encoder.finish().unwrap()),
+            chunk("IEND", vec![]),
+        ])
+    }
+
+    #[test]
+    fn test_pairs() {
+        let png = two_bit(
+            &[[0, 0, 0], [200, 10, 10], [2, 1, 0], [255, 255, 255]],
+            &[[0; 2]],
+        );
+        let pairs = Palette::from_png(&png).unwrap().pairs();
+        // only black and almost black look alike
+        assert_eq!(pairs, [Some(2), None, Some(0), None]);
+    }
+
+    #[test]
+    fn test_embed_extract() {
+        let mut png = dice_indexed();
+        let original = Pixels::from_png(&png).unwrap();
+        let palette = Palette::from_png(&png).unwrap();
+        let pairs = palette.pairs();
+        embed(&mut png, b"Hello World!", None).unwrap();
+        assert_eq!(
+            extract(&png, None).unwrap().as_deref(),
+            Some(&b"Hello World!"[..])
+        );
+
+        // the palette didn't change, pixels only moved to the other color of their pair
+        assert_eq!(Palette::from_png(&png).unwrap(), palette);
+        let pixels = Pixels::from_png(&png).unwrap();
+        for (a, b) in original.data().iter().zip(pixels.data()) {
+            let (a, b) = (*a as usize, *b as usize);
+            assert!(a == b || pairs[a] == Some(b));
+            assert!(distance(palette.entries()[a], palette.entries()[b]) <= MAX_DISTANCE);
+        }
+    }
+
+    #[test]
+    fn test_keyed() {
+        let key = StegoKey::from([3; 32]);
+        let mut png = dice_indexed();
+        embed(&mut png, b"Hello World!", Some(key)).unwrap();
+        assert_eq!(
+            extract(&png, Some(key)).unwrap().as_deref(),
+            Some(&b"Hello World!"[..])
+        );
+        assert!(extract(&png, None).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_extended_palette() {
+        // black and white only pair up with near duplicates
+        let mut png = two_bit(
+            &[[0, 0, 0], [255, 255, 255]],
+            &[[0b00010001, 0b00000100]; 12],
+        );
+        assert!(extract(&png, None).unwrap().is_none());
+        assert_eq!(capacity(&png).unwrap(), 8 * 12 / 8 - lsb::HEADER_LEN);
+
+        embed(&mut png, b"Hi!", None).unwrap();
+        let palette = Palette::from_png(&png).unwrap();
+        assert_eq!(palette.entries().len(), 4);
+        assert_eq!(palette.pairs(), [Some(2), Some(3), Some(0), Some(1)]);
+        assert_eq!(extract(&png, None).unwrap().as_deref(), Some(&b"Hi!"[..]));
+
+        let png = Png::try_from(png.as_bytes().as_slice()).unwrap();
+        assert_eq!(extract(&png, None).unwrap().as_deref(), Some(&b"Hi!"[..]));
+    }
+
+    #[test]
+    fn test_too_large() {
+        let mut png = dice_indexed();
+        let data = vec![0; capacity(&png).unwrap() + 1];
+        assert!(matches!(
+            embed(&mut png, &data, None),
+            Err(crate::Error::Lsb(lsb::LsbError::TooLarge { .. }))
+        ));
+    }
+
+    #[test]
+    fn test_not_indexed() {
+        let png = dice();
+        assert!(matches!(
+            Palette::from_png(&png),
+            Err(crate::Error::Palette(PaletteError::NotIndexed(
+                ColorType::Rgba
+            )))
+        ));
+    }
+}
@@ -0,0 +1,368 @@
+//! What is actually hidden: the message bytes plus optional metadata.
+//!
+//! Plain messages are stored as they are, so they can still be read with any PNG tool.
+//! As soon as there is metadata (or the message could be mistaken for one of our binary
+//! formats) they are wrapped in a small container:
+//!
+//! ```text
+//! magic     4 bytes   0x89 "PGP"
+//! version   1 byte    currently 1
+//! flags     1 byte    bit 0 = file name present, bit 1 = data is zlib compressed
+//! name      2 byte big endian length + UTF-8 name (only if bit 0 is set)
+//! size      8 bytes   big endian length of data (before compression)
+//! data
+//! ```
+//!
+//! Compression happens before encryption, since encrypted data doesn't compress.
+
+use std::{
+    error::Error,
+    fmt::Display,
+    io::{Read, Write},
+    path::Path,
+};
+
+use flate2::{Compression, read::ZlibDecoder, write::ZlibEncoder};
+
+use crate::Result;
+
+/// first bytes of every payload container
+pub const MAGIC: [u8; 4] = [0x89, b'P', b'G', b'P'];
+const VERSION: u8 = 1;
+const FLAG_FILE_NAME: u8 = 1;
+const FLAG_COMPRESSED: u8 = 1 << 1;
+/// compressed payloads are never inflated to more than this, so a tiny image can't
+/// decompress to gigabytes of data
+pub const MAX_DECOMPRESSED_SIZE: u64 = 1 << 30;
+
+/// hidden data and what is known about it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Payload {
+    data: Vec<u8>,
+    file_name: Option<String>,
+}
+
+impl Payload {
+    /// payload that is just a message
+    pub fn message(data: Vec<u8>) -> Payload {
+        Payload {
+            data,
+            file_name: None,
+        }
+    }
+
+    /// payload that is a file with the given name
+    ///
+    /// only the last component of name is kept, directories are never stored
+    pub fn file(name: &str, data: Vec<u8>) -> Result<Payload> {
+        let file_name = sanitize_file_name(name)?;
+        Ok(Payload {
+            data,
+            file_name: Some(file_name),
+        })
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+
+    /// original name of the hidden file, if it was one
+    pub fn file_name(&self) -> Option<&str> {
+        self.file_name.as_deref()
+    }
+
+    fn needs_container(&self) -> bool {
+        // every binary format of PNGecret starts with 0x89, a message that does too
+        // would be misread on decode
+        self.file_name.is_some() || self.data.first() == Some(&0x89)
+    }
+
+    pub fn as_bytes(&self) -> Vec<u8> {
+        if !self.needs_container() {
+            return self.data.clone();
+        }
+        self.as_container_bytes()
+    }
+
+    /// like [`Payload::as_bytes`], but always wrapped in a container
+    pub fn as_container_bytes(&self) -> Vec<u8> {
+        self.container(0, &self.data)
+    }
+
+    /// like [`Payload::as_bytes`], but the data is compressed with zlib at level (0-9)
+    ///
+    /// if compression doesn't make the data smaller, it is stored uncompressed
+    pub fn as_compressed_bytes(&self, level: u32) -> Vec<u8> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::new(level.min(9)));
+        // writing into a vec can't fail
+        encoder.write_all(&self.data).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let bytes = self.container(FLAG_COMPRESSED, &compressed);
+        let uncompressed = self.as_bytes();
+        if bytes.len() < uncompressed.len() {
+            bytes
+        } else {
+            uncompressed
+        }
+    }
+
+    fn container(&self, mut flags: u8, data: &[u8]) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        if self.file_name.is_some() {
+            flags |= FLAG_FILE_NAME;
+        }
+        bytes.push(flags);
+        if let Some(name) = &self.file_name {
+            // name is at most 255 bytes, see sanitize_file_name
+            bytes.extend_from_slice(&(name.len() as u16).to_be_bytes());
+            bytes.extend_from_slice(name.as_bytes());
+        }
+        bytes.extend_from_slice(&(self.data.len() as u64).to_be_bytes());
+        bytes.extend_from_slice(data);
+        bytes
+    }
+}
+
+impl TryFrom<&[u8]> for Payload {
+    type Error = crate::Error;
+
+    fn try_from(bytes: &[u8]) -> Result<Self> {
+        if !bytes.starts_with(&MAGIC) {
+            return Ok(Payload::message(bytes.to_vec()));
+        }
+
+        let mut reader = Reader {
+            bytes,
+            pos: MAGIC.len(),
+        };
+        let version = reader.take(1)?[0];
+        if version != VERSION {
+            return Err(PayloadError::UnsupportedVersion(version).into());
+        }
+        let flags = reader.take(1)?[0];
+
+        let file_name = if flags & FLAG_FILE_NAME != 0 {
+            let len = <u16>::from_be_bytes(reader.take(2)?.try_into().unwrap());
+            let name = std::str::from_utf8(reader.take(len as usize)?)
+                .map_err(|_| PayloadError::BadFileName)?;
+            Some(sanitize_file_name(name)?)
+        } else {
+            None
+        };
+
+        let size = <u64>::from_be_bytes(reader.take(8)?.try_into().unwrap());
+        let data = if flags & FLAG_COMPRESSED != 0 {
+            if size > MAX_DECOMPRESSED_SIZE {
+                return Err(PayloadError::TooLarge(size).into());
+            }
+            // never inflate more than one byte past the announced size
+            let mut data = Vec::new();
+            ZlibDecoder::new(reader.rest())
+                .take(size + 1)
+                .read_to_end(&mut data)
+                .map_err(|_| PayloadError::BadCompression)?;
+            data
+        } else {
+            reader.rest().to_vec()
+        };
+        if size != data.len() as u64 {
+            return Err(PayloadError::SizeMismatch {
+                expected: size,
+                actual: data.len() as u64,
+            }
+            .into());
+        }
+
+        Ok(Payload { data, file_name })
+    }
+}
+
+// small helper to read a payload container field by field
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        let res = self
+            .bytes
+            .get(self.pos..self.pos + n)
+            .ok_or(PayloadError::Truncated)?;
+        self.pos += n;
+        Ok(res)
+    }
+
+    fn rest(&self) -> &'a [u8] {
+        &self.bytes[self.pos..]
+    }
+}
+
+/// strips everything but the last path component, so extracting can't write outside of
+/// the target directory
+fn sanitize_file_name(name: &str) -> Result<String> {
+    // treat windows separators as separators too, no matter where we run
+    let name = name.rsplit(['/', '\\']).next().unwrap_or_default();
+    match Path::new(name).file_name().and_then(|n| n.to_str()) {
+        Some(n) if n.len() <= 255 => Ok(n.to_string()),
+        _ => Err(PayloadError::BadFileName.into()),
+    }
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum PayloadError {
+    // container ends before all fields were read
+    Truncated,
+    // container was created by a newer version of PNGecret
+    UnsupportedVersion(u8),
+    // file name is empty, too long, not UTF-8 or not a file name at all (like "..")
+    BadFileName,
+    // size field doesn't match the amount of data
+    SizeMismatch { expected: u64, actual: u64 },
+    // compressed data is corrupt
+    BadCompression,
+    // compressed data would inflate to more than MAX_DECOMPRESSED_SIZE
+    TooLarge(u64),
+}
+
+impl Display for PayloadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PayloadError::Truncated => write!(f, "Hidden payload is truncated"),
+            PayloadError::UnsupportedVersion(v) => {
+                write!(f, "Hidden payload has unsupported version {v}")
+            }
+            PayloadError::BadFileName => write!(f, "Hidden file has an invalid name"),
+            PayloadError::SizeMismatch { expected, actual } => write!(
+                f,
+                "Hidden payload should be {expected} bytes long, but is {actual} bytes long"
+            ),
+            PayloadError::BadCompression => write!(f, "Hidden payload can't be decompressed"),
+            PayloadError::TooLarge(size) => write!(
+                f,
+                "Hidden payload would decompress to {size} bytes, which is more than the limit of {MAX_DECOMPRESSED_SIZE}"
+            ),
+        }
+    }
+}
+
+impl Error for PayloadError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_is_stored_as_is() {
+        let payload = Payload::message(b"Hello World!".to_vec());
+        assert_eq!(payload.as_bytes(), b"Hello World!");
+        assert_eq!(Payload::try_from(&b"Hello World!"[..]).unwrap(), payload);
+    }
+
+    #[test]
+    fn test_message_starting_with_0x89() {
+        let payload = Payload::message(MAGIC.to_vec());
+        let bytes = payload.as_bytes();
+        assert_ne!(bytes, MAGIC);
+        assert_eq!(Payload::try_from(bytes.as_slice()).unwrap(), payload);
+    }
+
+    #[test]
+    fn test_file_roundtrip() {
+        let payload = Payload::file("secret.pdf", vec![0, 1, 2, 3]).unwrap();
+        let bytes = payload.as_bytes();
+        assert!(bytes.starts_with(&MAGIC));
+
+        let parsed = Payload::try_from(bytes.as_slice()).unwrap();
+        assert_eq!(parsed.file_name(), Some("secret.pdf"));
+        assert_eq!(parsed.data(), [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_file_name_is_sanitized() {
+        let payload = Payload::file("../../etc/passwd", vec![]).unwrap();
+        assert_eq!(payload.file_name(), Some("passwd"));
+        let payload = Payload::file("C:\\Users\\me\\key.pem", vec![]).unwrap();
+        assert_eq!(payload.file_name(), Some("key.pem"));
+        assert!(Payload::file("..", vec![]).is_err());
+        assert!(Payload::file("dir/", vec![]).is_err());
+    }
+
+    #[test]
+    fn test_truncated_container() {
+        let bytes = Payload::file("secret.pdf", vec![0, 1, 2, 3])
+            .unwrap()
+            .as_bytes();
+        assert!(matches!(
+            Payload::try_from(&bytes[..bytes.len() - 1]),
+            Err(crate::Error::Payload(PayloadError::SizeMismatch {
+                expected: 4,
+                actual: 3
+            }))
+        ));
+        assert!(matches!(
+            Payload::try_from(&bytes[..8]),
+            Err(crate::Error::Payload(PayloadError::Truncated))
+        ));
+    }
+
+    #[test]
+    fn test_compressed_roundtrip() {
+        let payload =
+            Payload::file("notes.txt", "all work and no play ".repeat(100).into()).unwrap();
+        let bytes = payload.as_compressed_bytes(9);
+        assert!(bytes.len() < payload.as_bytes().len());
+        assert_eq!(Payload::try_from(bytes.as_slice()).unwrap(), payload);
+
+        let payload = Payload::message("all work and no play ".repeat(100).into());
+        let bytes = payload.as_compressed_bytes(6);
+        assert!(bytes.starts_with(&MAGIC));
+        assert_eq!(Payload::try_from(bytes.as_slice()).unwrap(), payload);
+    }
+
+    #[test]
+    fn test_incompressible_stays_uncompressed() {
+        let payload = Payload::message(b"short".to_vec());
+        assert_eq!(payload.as_compressed_bytes(9), b"short");
+    }
+
+    #[test]
+    fn test_decompression_bomb() {
+        // 10 MB of zeros compress to a few KB
+        let payload = Payload::message(vec![0; 10_000_000]);
+        let mut bytes = payload.as_compressed_bytes(9);
+        assert!(bytes.len() < 20_000);
+
+        // claim it is only 1000 bytes long, decoding must stop right after that
+        bytes[6..14].copy_from_slice(&1000u64.to_be_bytes());
+        assert!(matches!(
+            Payload::try_from(bytes.as_slice()),
+            Err(crate::Error::Payload(PayloadError::SizeMismatch {
+                expected: 1000,
+                actual: 1001
+            }))
+        ));
+
+        bytes[6..14].copy_from_slice(&u64::MAX.to_be_bytes());
+        assert!(matches!(
+            Payload::try_from(bytes.as_slice()),
+            Err(crate::Error::Payload(PayloadError::TooLarge(_)))
+        ));
+    }
+
+    #[test]
+    fn test_corrupt_compressed_data() {
+        let payload = Payload::message("all work and no play ".repeat(100).into());
+        let mut bytes = payload.as_compressed_bytes(6);
+        let len = bytes.len();
+        bytes.truncate(len - 10);
+        assert!(Payload::try_from(bytes.as_slice()).is_err());
+    }
+}
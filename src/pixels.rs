@@ -0,0 +1,467 @@
+//! The raw image data: all IDAT chunks concatenated, inflated and unfiltered.
+//!
+//! Every scanline starts with a filter type byte (section 9 of the PNG spec), which is
+//! removed here, so [`Pixels::data`] holds the actual samples. Interlaced images are
+//! stored pass after pass, as they are in the file. When the data is written back,
+//! every scanline is filtered with the same filter type it had before.
+
+use std::{
+    error::Error,
+    fmt::Display,
+    io::{Read, Write},
+    str::FromStr,
+};
+
+use flate2::{Compression, read::ZlibDecoder, write::ZlibEncoder};
+
+use crate::Result;
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::ihdr::{ColorType, Ihdr};
+use crate::payload::MAX_DECOMPRESSED_SIZE;
+use crate::png::Png;
+
+// starting column, starting row, column step and row step of the 7 Adam7 passes
+const ADAM7: [(u32, u32, u32, u32); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+/// unfiltered samples of a png
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pixels {
+    ihdr: Ihdr,
+    // filter type of every scanline, so the data can be filtered the same way again
+    filters: Vec<u8>,
+    data: Vec<u8>,
+}
+
+impl Pixels {
+    /// read the image data of png
+    pub fn from_png(png: &Png) -> Result<Pixels> {
+        let mut pixels = Pixels {
+            ihdr: png.ihdr()?,
+            filters: Vec::new(),
+            data: Vec::new(),
+        };
+        let expected: u64 = pixels
+            .scanlines()
+            .map(|(rows, len)| rows as u64 * (len as u64 + 1))
+            .sum();
+        if expected > MAX_DECOMPRESSED_SIZE {
+            return Err(PixelError::TooLarge.into());
+        }
+
+        // inflate the concatenated IDAT data, but never more than the image can hold
+        let compressed: Vec<u8> = png
+            .chunks()
+            .iter()
+            .filter(|c| &c.chunk_type().bytes() == b"IDAT")
+            .flat_map(|c| c.data())
+            .copied()
+            .collect();
+        if compressed.is_empty() {
+            return Err(PixelError::MissingImageData.into());
+        }
+        let mut filtered = Vec::new();
+        ZlibDecoder::new(compressed.as_slice())
+            .take(expected + 1)
+            .read_to_end(&mut filtered)
+            .map_err(|_| PixelError::BadCompression)?;
+        if filtered.len() as u64 != expected {
+            return Err(PixelError::SizeMismatch {
+                expected,
+                actual: filtered.len() as u64,
+            }
+            .into());
+        }
+
+        // undo the filters, scanline by scanline
+        let bpp = pixels.filter_stride();
+        let mut filtered = filtered.as_slice();
+        for (rows, len) in pixels.scanlines().collect::<Vec<_>>() {
+            let mut prior = vec![0; len];
+            for _ in 0..rows {
+                let (line, rest) = filtered.split_at(len + 1);
+                filtered = rest;
+                let filter = line[0];
+                let mut row = line[1..].to_vec();
+                unfilter(filter, &mut row, &prior, bpp)?;
+                pixels.filters.push(filter);
+                pixels.data.extend_from_slice(&row);
+                prior = row;
+            }
+        }
+
+        Ok(pixels)
+    }
+
+    /// filter and deflate the data and write it to the IDAT chunks of png
+    ///
+    /// the data is spread over as many IDAT chunks as there were before, so the list of
+    /// chunks doesn't change
+    pub fn write_to(&self, png: &mut Png) -> Result<()> {
+        let bpp = self.filter_stride();
+        let mut filtered = Vec::with_capacity(self.data.len() + self.filters.len());
+        let mut data = self.data.as_slice();
+        let mut filters = self.filters.iter();
+        for (rows, len) in self.scanlines() {
+            let mut prior: &[u8] = &vec![0; len];
+            for _ in 0..rows {
+                let (row, rest) = data.split_at(len);
+                data = rest;
+                // filters were checked when the data was read
+                let filter = *filters.next().unwrap();
+                filtered.push(filter);
+                filtered.extend(filter_row(filter, row, prior, bpp));
+                prior = row;
+            }
+        }
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+        // writing into a vec can't fail
+        encoder.write_all(&filtered).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let idat_indices: Vec<usize> = png
+            .chunks()
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| &c.chunk_type().bytes() == b"IDAT")
+            .map(|(i, _)| i)
+            .collect();
+        let piece_len = compressed.len().div_ceil(idat_indices.len()).max(1);
+        let mut pieces = compressed.chunks(piece_len);
+        for (n, index) in idat_indices.iter().enumerate() {
+            // the last chunk gets whatever is left, in case it didn't divide evenly
+            let piece = if n + 1 == idat_indices.len() {
+                pieces.by_ref().flatten().copied().collect()
+            } else {
+                pieces.next().unwrap_or_default().to_vec()
+            };
+            let chunk = Chunk::try_new(ChunkType::from_str("IDAT")?, piece)?;
+            png.replace_chunk(*index, chunk)?;
+        }
+
+        Ok(())
+    }
+
+    /// header of the image the pixels are from
+    pub fn ihdr(&self) -> &Ihdr {
+        &self.ihdr
+    }
+
+    /// unfiltered scanlines, without filter type bytes
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    /// offset in bits of every pixel in data, in the order they are stored
+    ///
+    /// pixels below 8 bits don't simply follow each other, every scanline is padded to
+    /// whole bytes. The offsets are computed on the fly, there can be billions of them
+    pub fn pixel_offsets(&self) -> impl Iterator<Item = usize> + use<> {
+        let bits = self.ihdr.bits_per_pixel();
+        let mut rows = Vec::new();
+        let mut start = 0;
+        for (width, height) in self.passes() {
+            let len = (width as usize * bits).div_ceil(8);
+            for _ in 0..height {
+                rows.push((start, width as usize));
+                start += len;
+            }
+        }
+        rows.into_iter()
+            .flat_map(move |(start, width)| (0..width).map(move |x| start * 8 + x * bits))
+    }
+
+    /// width and height of every pass that isn't empty, the whole image if it isn't
+    /// interlaced
+    pub fn passes(&self) -> impl Iterator<Item = (u32, u32)> + '_ {
+        let passes = if self.ihdr.is_interlaced() {
+            ADAM7.to_vec()
+        } else {
+            vec![(0, 0, 1, 1)]
+        };
+        passes
+            .into_iter()
+            .map(|(x, y, dx, dy)| {
+                (
+                    self.ihdr.width().saturating_sub(x).div_ceil(dx),
+                    self.ihdr.height().saturating_sub(y).div_ceil(dy),
+                )
+            })
+            // empty passes have no scanlines at all, not even filter bytes
+            .filter(|(w, h)| *w > 0 && *h > 0)
+    }
+
+    /// number of scanlines and their length in bytes, for every pass
+    fn scanlines(&self) -> impl Iterator<Item = (u32, usize)> + '_ {
+        self.passes()
+            .map(|(w, h)| (h, (w as usize * self.ihdr.bits_per_pixel()).div_ceil(8)))
+    }
+
+    // distance to the byte of the previous pixel used by the filters, at least 1
+    fn filter_stride(&self) -> usize {
+        self.ihdr.bits_per_pixel().div_ceil(8)
+    }
+
+    /// how much changed differs from these pixels, which have to be of the same image
+    ///
+    /// samples below 8 bits are compared byte by byte, which is what indexed images
+    /// need anyway: there the values are palette indices, so only changes are counted
+    pub fn distortion(&self, changed: &Pixels) -> Distortion {
+        let depth = self.ihdr.bit_depth();
+        let sample_len = (depth as usize / 8).max(1);
+        let value = |s: &[u8]| s.iter().fold(0, |acc, b| acc << 8 | *b as u64) as f64;
+        let (mut count, mut squares) = (0, 0.0);
+        let pairs = self
+            .data
+            .chunks_exact(sample_len)
+            .zip(changed.data.chunks_exact(sample_len));
+        let samples = pairs.len();
+        for (a, b) in pairs {
+            if a != b {
+                count += 1;
+                squares += (value(a) - value(b)).powi(2);
+            }
+        }
+
+        let indexed = self.ihdr.color_type() == ColorType::Indexed;
+        Distortion {
+            changed: count,
+            samples,
+            mse: (!indexed && depth >= 8).then(|| squares / samples.max(1) as f64),
+            max: ((1u64 << depth.max(8)) - 1) as f64,
+        }
+    }
+}
+
+/// difference between the pixels before and after hiding data in them
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Distortion {
+    /// number of samples that changed
+    pub changed: usize,
+    pub samples: usize,
+    /// mean squared error over all samples, None if the values aren't intensities
+    pub mse: Option<f64>,
+    // highest value a sample can have
+    max: f64,
+}
+
+impl Distortion {
+    /// peak signal to noise ratio in dB, None if nothing changed
+    pub fn psnr(&self) -> Option<f64> {
+        let mse = self.mse.filter(|m| *m > 0.0)?;
+        Some(10.0 * (self.max * self.max / mse).log10())
+    }
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = (
+        (p - a as i16).abs(),
+        (p - b as i16).abs(),
+        (p - c as i16).abs(),
+    );
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+// value the filter predicts for row[i], from the bytes left, above and above left of it
+fn predict(filter: u8, row: &[u8], prior: &[u8], i: usize, bpp: usize) -> u8 {
+    let left = if i >= bpp { row[i - bpp] } else { 0 };
+    let up = prior[i];
+    let up_left = if i >= bpp { prior[i - bpp] } else { 0 };
+    match filter {
+        1 => left,
+        2 => up,
+        3 => ((left as u16 + up as u16) / 2) as u8,
+        4 => paeth(left, up, up_left),
+        _ => 0,
+    }
+}
+
+/// undo filter on row in place, prior is the unfiltered row above it
+fn unfilter(filter: u8, row: &mut [u8], prior: &[u8], bpp: usize) -> Result<()> {
+    if filter > 4 {
+        return Err(PixelError::UnknownFilter(filter).into());
+    }
+    for i in 0..row.len() {
+        row[i] = row[i].wrapping_add(predict(filter, row, prior, i, bpp));
+    }
+    Ok(())
+}
+
+/// apply filter to the unfiltered row, prior is the unfiltered row above it
+fn filter_row(filter: u8, row: &[u8], prior: &[u8], bpp: usize) -> Vec<u8> {
+    (0..row.len())
+        .map(|i| row[i].wrapping_sub(predict(filter, row, prior, i, bpp)))
+        .collect()
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum PixelError {
+    MissingImageData,
+    // image data can't be inflated
+    BadCompression,
+    UnknownFilter(u8),
+    // inflated image data doesn't have the size IHDR says it should have
+    SizeMismatch { expected: u64, actual: u64 },
+    // image is too big to be held in memory
+    TooLarge,
+}
+
+impl Display for PixelError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PixelError::MissingImageData => write!(f, "PNG has no IDAT chunk"),
+            PixelError::BadCompression => write!(f, "Image data can't be decompressed"),
+            PixelError::UnknownFilter(t) => write!(f, "Unknown scanline filter type {t}"),
+            PixelError::SizeMismatch { expected, actual } => write!(
+                f,
+                "Image data should be {expected} bytes long, but it is {actual} bytes"
+            ),
+            PixelError::TooLarge => write!(f, "Image is too large"),
+        }
+    }
+}
+
+impl Error for PixelError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// png with a single IDAT chunk holding the given scanlines (filter bytes included)
+    fn testing_png(width: u32, height: u32, color_type: u8, interlace: u8, raw: &[u8]) -> Png {
+        let mut ihdr = width.to_be_bytes().to_vec();
+        ihdr.extend_from_slice(&height.to_be_bytes());
+        ihdr.extend_from_slice(&[8, color_type, 0, 0, interlace]);
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(raw).unwrap();
+        Png::from_chunks(vec![
+            Chunk::new(ChunkType::from_str("IHDR").unwrap(), ihdr),
+            Chunk::new(
+                ChunkType::from_str("IDAT").unwrap(),
+                encoder.finish().unwrap(),
+            ),
+            Chunk::new(ChunkType::from_str("IEND").unwrap(), vec![]),
+        ])
+    }
+
+    #[test]
+    fn test_unfilter() {
+        // 2x2 grayscale: Sub filter on the first row, Up filter on the second
+        let png = testing_png(2, 2, 0, 0, &[1, 10, 5, 2, 1, 1]);
+        let pixels = Pixels::from_png(&png).unwrap();
+        assert_eq!(pixels.data(), [10, 15, 11, 16]);
+    }
+
+    #[test]
+    fn test_all_filters_roundtrip() {
+        // 3x5 RGB, every scanline with a different filter type
+        let mut raw = Vec::new();
+        for filter in 0..5u8 {
+            raw.push(filter);
+            raw.extend((0..9).map(|i| filter.wrapping_mul(37).wrapping_add(i * 29)));
+        }
+        let mut png = testing_png(3, 5, 2, 0, &raw);
+        let mut pixels = Pixels::from_png(&png).unwrap();
+        pixels.data_mut()[7] ^= 1;
+        pixels.write_to(&mut png).unwrap();
+
+        let written = Pixels::from_png(&png).unwrap();
+        assert_eq!(written, pixels);
+        assert_eq!(written.filters, [0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_interlaced() {
+        // 3x3 grayscale has passes of 1x1, 1x1, 2x1, 1x2 and 3x1 pixels
+        let raw = [0, 1, 0, 2, 0, 3, 4, 0, 5, 0, 6, 0, 7, 8, 9];
+        let png = testing_png(3, 3, 0, 1, &raw);
+        let pixels = Pixels::from_png(&png).unwrap();
+        assert_eq!(pixels.data(), [1, 2, 3, 4, 5, 6, 7, 8, 9]);
+    }
+
+    #[test]
+    fn test_wrong_size() {
+        let png = testing_png(2, 2, 0, 0, &[0, 1, 2, 0, 3]);
+        assert!(matches!(
+            Pixels::from_png(&png),
+            Err(crate::Error::Pixel(PixelError::SizeMismatch {
+                expected: 6,
+                actual: 5
+            }))
+        ));
+    }
+
+    #[test]
+    fn test_unknown_filter() {
+        let png = testing_png(1, 1, 0, 0, &[5, 1]);
+        assert!(matches!(
+            Pixels::from_png(&png),
+            Err(crate::Error::Pixel(PixelError::UnknownFilter(5)))
+        ));
+    }
+
+    #[test]
+    fn test_keeps_chunk_list() {
+        let mut png = testing_png(2, 2, 0, 0, &[0, 1, 2, 0, 3, 4]);
+        // split the image data over two IDAT chunks
+        let idat = png.chunks()[1].data().to_vec();
+        let (a, b) = idat.split_at(idat.len() / 2);
+        png.replace_chunk(
+            1,
+            Chunk::new(ChunkType::from_str("IDAT").unwrap(), a.to_vec()),
+        )
+        .unwrap();
+        png.insert_chunk(
+            Chunk::new(ChunkType::from_str("IDAT").unwrap(), b.to_vec()),
+            crate::Placement::Index(2),
+        )
+        .unwrap();
+
+        let pixels = Pixels::from_png(&png).unwrap();
+        pixels.write_to(&mut png).unwrap();
+        let types: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|c| c.chunk_type().to_string())
+            .collect();
+        assert_eq!(types, ["IHDR", "IDAT", "IDAT", "IEND"]);
+        assert_eq!(Pixels::from_png(&png).unwrap(), pixels);
+    }
+
+    #[test]
+    fn test_distortion() {
+        let png = testing_png(2, 2, 0, 0, &[0, 10, 20, 0, 30, 40]);
+        let pixels = Pixels::from_png(&png).unwrap();
+        assert_eq!(pixels.distortion(&pixels).psnr(), None);
+
+        let mut changed = pixels.clone();
+        changed.data_mut()[0] = 11;
+        changed.data_mut()[3] = 38;
+        let distortion = pixels.distortion(&changed);
+        assert_eq!((distortion.changed, distortion.samples), (2, 4));
+        assert_eq!(distortion.mse, Some(5.0 / 4.0));
+        let psnr = 10.0 * (255.0f64 * 255.0 / 1.25).log10();
+        assert!((distortion.psnr().unwrap() - psnr).abs() < 1e-9);
+    }
+}
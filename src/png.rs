@@ -1,14 +1,33 @@
-use std::{
-    error::Error,
-    fmt::Display,
-    io::{BufReader, Read},
-};
+use std::{error::Error, fmt::Display};
 
 use crate::chunk::Chunk;
+use crate::ihdr::Ihdr;
+use crate::validate::{self, Violation};
+
+/// where to put a new chunk in the png
+///
+/// the spec says IHDR has to be first and IEND last, so anything added after IEND
+/// is ignored (or rejected) by strict decoders
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Placement {
+    /// right before IEND, the safest choice for ancillary chunks
+    #[default]
+    BeforeIend,
+    /// right after IHDR
+    AfterIhdr,
+    /// right before the first IDAT chunk
+    BeforeFirstIdat,
+    /// right after the last IDAT chunk
+    AfterLastIdat,
+    /// at this index in the chunk list (the chunk that was there moves back)
+    Index(usize),
+}
 
 pub struct Png {
     header: [u8; 8],
     chunks: Vec<Chunk>,
+    // whatever comes after IEND that isn't a chunk, kept so the file is written back as is
+    trailing_data: Vec<u8>,
 }
 
 #[allow(dead_code)]
@@ -19,24 +38,111 @@ impl Png {
         Self {
             header: Png::STANDARD_HEADER,
             chunks,
+            trailing_data: Vec::new(),
         }
     }
 
+    /// put chunk at the very end, after IEND if there is one
+    #[deprecated(note = "use `insert_chunk` with `Placement::BeforeIend` instead")]
     pub fn append_chunk(&mut self, chunk: Chunk) {
         self.chunks.push(chunk);
     }
 
-    pub fn remove_first_chunk(&mut self, chunk_type: &str) -> crate::Result<Chunk> {
-        if let Some(chunk) = self.chunk_by_type(chunk_type) {
-            let chunk_idx = self
+    /// insert chunk at the given placement and return the index it ended up at
+    pub fn insert_chunk(&mut self, chunk: Chunk, placement: Placement) -> crate::Result<usize> {
+        let idx = self.placement_index(placement)?;
+        self.chunks.insert(idx, chunk);
+        Ok(idx)
+    }
+
+    /// index a chunk inserted with the given placement would get
+    pub fn placement_index(&self, placement: Placement) -> crate::Result<usize> {
+        let position_of = |ctype: &[u8; 4]| {
+            self.chunks
+                .iter()
+                .position(|c| &c.chunk_type().bytes() == ctype)
+        };
+        let idx = match placement {
+            // if there is no IEND (broken png) just put it at the end
+            Placement::BeforeIend => position_of(b"IEND").unwrap_or(self.chunks.len()),
+            Placement::AfterIhdr => position_of(b"IHDR")
+                .map(|i| i + 1)
+                .ok_or_else(|| PngError::ChunkNotFound("IHDR".to_string()))?,
+            Placement::BeforeFirstIdat => {
+                position_of(b"IDAT").ok_or_else(|| PngError::ChunkNotFound("IDAT".to_string()))?
+            }
+            Placement::AfterLastIdat => self
                 .chunks
                 .iter()
-                .position(|c| c.chunk_type() == chunk.chunk_type())
-                .unwrap();
-            Ok(self.chunks.remove(chunk_idx))
-        } else {
-            Err(Box::new(PngError::ChunkNotFound(chunk_type.to_string())))
+                .rposition(|c| &c.chunk_type().bytes() == b"IDAT")
+                .map(|i| i + 1)
+                .ok_or_else(|| PngError::ChunkNotFound("IDAT".to_string()))?,
+            Placement::Index(i) => {
+                if i > self.chunks.len() {
+                    return Err(PngError::IndexOutOfBounds {
+                        index: i,
+                        len: self.chunks.len(),
+                    }
+                    .into());
+                }
+                i
+            }
+        };
+        Ok(idx)
+    }
+
+    /// remove the first chunk of type chunk_type and return it
+    pub fn remove_first_chunk(&mut self, chunk_type: &str) -> crate::Result<Chunk> {
+        let index = self
+            .chunks
+            .iter()
+            .position(|c| c.chunk_type().bytes() == chunk_type.as_bytes())
+            .ok_or_else(|| PngError::ChunkNotFound(chunk_type.to_string()))?;
+        Ok(self.chunks.remove(index))
+    }
+
+    /// remove the chunk at index and return it
+    pub fn remove_chunk_at(&mut self, index: usize) -> crate::Result<Chunk> {
+        if index >= self.chunks.len() {
+            return Err(PngError::IndexOutOfBounds {
+                index,
+                len: self.chunks.len(),
+            }
+            .into());
+        }
+        Ok(self.chunks.remove(index))
+    }
+
+    /// remove every chunk of type chunk_type and return them in order
+    pub fn remove_all_by_type(&mut self, chunk_type: &str) -> crate::Result<Vec<Chunk>> {
+        let (removed, kept) = std::mem::take(&mut self.chunks)
+            .into_iter()
+            .partition(|c| c.chunk_type().bytes() == chunk_type.as_bytes());
+        self.chunks = kept;
+        if removed.is_empty() {
+            return Err(PngError::ChunkNotFound(chunk_type.to_string()).into());
         }
+        Ok(removed)
+    }
+
+    /// put chunk at index in place of the chunk that is there, which is returned
+    pub fn replace_chunk(&mut self, index: usize, chunk: Chunk) -> crate::Result<Chunk> {
+        let len = self.chunks.len();
+        let old = self
+            .chunks
+            .get_mut(index)
+            .ok_or(PngError::IndexOutOfBounds { index, len })?;
+        Ok(std::mem::replace(old, chunk))
+    }
+
+    /// image header parsed from the first chunk, which has to be IHDR
+    pub fn ihdr(&self) -> crate::Result<Ihdr> {
+        let first = self
+            .chunks
+            .first()
+            .filter(|c| &c.chunk_type().bytes() == b"IHDR")
+            .ok_or_else(|| PngError::ChunkNotFound("IHDR".to_string()))?;
+        Ihdr::try_from(first)
     }
 
     pub fn header(&self) -> &[u8; 8] {
@@ -47,16 +153,38 @@ impl Png {
         &self.chunks
     }
 
+    /// every chunk of type chunk_type, in order
+    pub fn chunks_by_type<'a>(
+        &'a self,
+        chunk_type: &str,
+    ) -> impl Iterator<Item = &'a Chunk> + use<'a> {
+        let chunk_type = chunk_type.as_bytes().to_vec();
+        self.chunks
+            .iter()
+            .filter(move |c| c.chunk_type().bytes() == *chunk_type)
+    }
+
+    pub fn first_chunk_by_type(&self, chunk_type: &str) -> Option<&Chunk> {
+        self.chunks_by_type(chunk_type).next()
+    }
+
+    pub fn last_chunk_by_type(&self, chunk_type: &str) -> Option<&Chunk> {
+        self.chunks_by_type(chunk_type).last()
+    }
+
+    /// the first chunk of type chunk_type, the one [`Png::remove_first_chunk`] removes
     pub fn chunk_by_type(&self, chunk_type: &str) -> Option<&Chunk> {
-        let chunk_type_bytes = chunk_type.as_bytes();
+        self.first_chunk_by_type(chunk_type)
+    }
 
-        let mut res: Option<&Chunk> = None;
-        for c in &self.chunks {
-            if c.chunk_type().bytes() == chunk_type_bytes {
-                res = Some(c);
-            }
-        }
-        res
+    /// bytes after IEND that aren't a chunk (empty in a valid png)
+    pub fn trailing_data(&self) -> &[u8] {
+        &self.trailing_data
+    }
+
+    /// check the png against the chunk rules of the spec, see [`crate::validate`]
+    pub fn validate(&self) -> Vec<Violation> {
+        validate::validate(self)
     }
 
     pub fn as_bytes(&self) -> Vec<u8> {
@@ -64,6 +192,7 @@ impl Png {
         self.header
             .iter()
             .chain(chunks_as_bytes.iter())
+            .chain(self.trailing_data.iter())
             .copied()
             .collect()
     }
@@ -73,32 +202,56 @@ impl TryFrom<&[u8]> for Png {
     type Error = crate::Error;
 
     fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
-        let mut reader = BufReader::new(bytes);
-
-        let mut header_buf = [0; 8];
-        reader.read_exact(&mut header_buf)?;
-        if header_buf != Png::STANDARD_HEADER {
-            return Err(Box::new(PngError::BadHeader));
+        if !bytes.starts_with(&Png::STANDARD_HEADER) {
+            return Err(PngError::BadHeader.into());
         }
 
         let mut chunks: Vec<Chunk> = Vec::new();
-        // length of entire chunk is 4 (length) + 4 (type) + length (data) + 4 (crc)
-        let mut length_buf = [0; 4];
-        while reader.read_exact(&mut length_buf).is_ok() {
-            // read 4 + length + 4 more bytes to read rest of chunk
-            let length_of_chunk = 4 + <u32>::from_be_bytes(length_buf) + 4;
-            let mut rest_buf = vec![0; <usize>::try_from(length_of_chunk)?];
-            reader.read_exact(&mut rest_buf)?;
-
-            // add length and rest, then create Chunk and add it to list
-            let chunk_bytes: Vec<u8> = length_buf.iter().chain(rest_buf.iter()).copied().collect();
-            let chunk = Chunk::try_from(chunk_bytes.as_slice())?;
-            chunks.push(chunk);
+        let mut trailing_data = Vec::new();
+        let mut offset = Png::STANDARD_HEADER.len();
+        while offset < bytes.len() {
+            let index = chunks.len();
+            let rest = &bytes[offset..];
+
+            // length of entire chunk is 4 (length) + 4 (type) + length (data) + 4 (crc)
+            let chunk = rest
+                .first_chunk::<4>()
+                .and_then(|length_buf| {
+                    // a crafted length can overflow usize on 32 bit targets
+                    (<u32>::from_be_bytes(*length_buf) as usize).checked_add(12)
+                })
+                .filter(|length_of_chunk| *length_of_chunk <= rest.len())
+                .ok_or_else(|| PngError::Truncated { index, offset }.into())
+                .and_then(|length_of_chunk| {
+                    // create Chunk from the bytes
+                    let chunk = Chunk::try_from(&rest[..length_of_chunk]).map_err(|e| {
+                        crate::Error::InvalidChunk {
+                            index,
+                            offset,
+                            source: Box::new(e),
+                        }
+                    })?;
+                    Ok((chunk, length_of_chunk))
+                });
+
+            match chunk {
+                Ok((chunk, length_of_chunk)) => {
+                    chunks.push(chunk);
+                    offset += length_of_chunk;
+                }
+                // anything after IEND that isn't a chunk is kept as it is
+                Err(_) if chunks.iter().any(|c| &c.chunk_type().bytes() == b"IEND") => {
+                    trailing_data = rest.to_vec();
+                    break;
+                }
+                Err(e) => return Err(e),
+            }
         }
 
         Ok(Png {
             header: Png::STANDARD_HEADER,
             chunks,
+            trailing_data,
         })
     }
 }
@@ -114,9 +267,18 @@ impl Display for Png {
 }
 
 #[derive(Debug)]
-enum PngError {
+#[non_exhaustive]
+pub enum PngError {
+    // file doesn't start with the png signature
     BadHeader,
+    // no chunk of this type
     ChunkNotFound(String),
+    // chunk index is bigger than the number of chunks
+    IndexOutOfBounds { index: usize, len: usize },
+    // file ends in the middle of the chunk at index, which starts at byte offset
+    Truncated { index: usize, offset: usize },
+    // png breaks this many rules of the spec (errors only), see Png::validate
+    Invalid(usize),
 }
 
 impl Display for PngError {
@@ -124,6 +286,17 @@ impl Display for PngError {
         match self {
             PngError::BadHeader => write!(f, "This PNG has a faulty header"),
             PngError::ChunkNotFound(ct) => write!(f, "No chunk with type {ct} in PNG"),
+            PngError::IndexOutOfBounds { index, len } => {
+                write!(
+                    f,
+                    "Index {index} is out of bounds for PNG with {len} chunks"
+                )
+            }
+            PngError::Truncated { index, offset } => write!(
+                f,
+                "PNG ends in the middle of chunk {index} starting at byte offset {offset}"
+            ),
+            PngError::Invalid(n) => write!(f, "PNG breaks the spec in {n} places"),
         }
     }
 }
@@ -133,7 +306,7 @@ impl Error for PngError {}
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::chunk::Chunk;
+    use crate::chunk::{Chunk, ChunkError};
     use crate::chunk_type::ChunkType;
     use std::convert::TryFrom;
 
@@ -225,6 +398,58 @@ mod tests {
         assert!(png.is_err());
     }
 
+    #[test]
+    fn test_invalid_chunk_position() {
+        let mut bytes = PNG_FILE.to_vec();
+        // break the crc of the sRGB chunk, which is the second chunk at offset 33
+        bytes[45] ^= 1;
+
+        let png = Png::try_from(bytes.as_ref());
+
+        match png {
+            Err(crate::Error::InvalidChunk {
+                index,
+                offset,
+                source,
+            }) => {
+                assert_eq!((index, offset), (1, 33));
+                assert!(matches!(
+                    *source,
+                    crate::Error::Chunk(ChunkError::BadChecksum { .. })
+                ));
+            }
+            _ => panic!("expected an invalid chunk error"),
+        }
+    }
+
+    #[test]
+    fn test_truncated_png() {
+        let png = Png::try_from(&PNG_FILE[..PNG_FILE.len() - 2]);
+
+        assert!(matches!(
+            png,
+            Err(crate::Error::Png(PngError::Truncated {
+                index: 6,
+                offset: 4791
+            }))
+        ));
+    }
+
+    #[test]
+    fn test_huge_chunk_length() {
+        let mut bytes = Png::STANDARD_HEADER.to_vec();
+        bytes.extend_from_slice(&u32::MAX.to_be_bytes());
+        bytes.extend_from_slice(b"IHDR");
+
+        assert!(matches!(
+            Png::try_from(bytes.as_slice()),
+            Err(crate::Error::Png(PngError::Truncated {
+                index: 0,
+                offset: 8
+            }))
+        ));
+    }
+
     #[test]
     fn test_list_chunks() {
         let png = testing_png();
@@ -241,6 +466,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(deprecated)]
     fn test_append_chunk() {
         let mut png = testing_png();
         png.append_chunk(chunk_from_strings("TeSt", "Message").unwrap());
@@ -252,12 +478,161 @@ mod tests {
     #[test]
     fn test_remove_first_chunk() {
         let mut png = testing_png();
-        png.append_chunk(chunk_from_strings("TeSt", "Message").unwrap());
+        png.insert_chunk(
+            chunk_from_strings("TeSt", "Message").unwrap(),
+            Placement::default(),
+        )
+        .unwrap();
         png.remove_first_chunk("TeSt").unwrap();
         let chunk = png.chunk_by_type("TeSt");
         assert!(chunk.is_none());
     }
 
+    #[test]
+    fn test_first_and_last_chunk_by_type() {
+        let mut png = testing_png();
+        png.insert_chunk(
+            chunk_from_strings("TeSt", "first").unwrap(),
+            Placement::default(),
+        )
+        .unwrap();
+        png.insert_chunk(
+            chunk_from_strings("TeSt", "second").unwrap(),
+            Placement::default(),
+        )
+        .unwrap();
+        let data = |c: Option<&Chunk>| c.unwrap().data_as_string().unwrap();
+        assert_eq!(data(png.first_chunk_by_type("TeSt")), "first");
+        assert_eq!(data(png.chunk_by_type("TeSt")), "first");
+        assert_eq!(data(png.last_chunk_by_type("TeSt")), "second");
+        assert_eq!(png.chunks_by_type("TeSt").count(), 2);
+
+        // the chunk that is found is the one that is removed
+        let removed = png.remove_first_chunk("TeSt").unwrap();
+        assert_eq!(removed.data_as_string().unwrap(), "first");
+        assert_eq!(data(png.chunk_by_type("TeSt")), "second");
+    }
+
+    #[test]
+    fn test_remove_chunk_at() {
+        let mut png = testing_png();
+        let removed = png.remove_chunk_at(1).unwrap();
+        assert_eq!(&removed.chunk_type().to_string(), "miDl");
+        assert_eq!(png.chunks().len(), 2);
+        assert!(matches!(
+            png.remove_chunk_at(2),
+            Err(crate::Error::Png(PngError::IndexOutOfBounds {
+                index: 2,
+                len: 2
+            }))
+        ));
+    }
+
+    #[test]
+    fn test_remove_all_by_type() {
+        let mut png = testing_png();
+        png.insert_chunk(
+            chunk_from_strings("TeSt", "first").unwrap(),
+            Placement::default(),
+        )
+        .unwrap();
+        png.insert_chunk(
+            chunk_from_strings("FrSt", "again").unwrap(),
+            Placement::default(),
+        )
+        .unwrap();
+        png.insert_chunk(
+            chunk_from_strings("TeSt", "second").unwrap(),
+            Placement::default(),
+        )
+        .unwrap();
+        let removed = png.remove_all_by_type("TeSt").unwrap();
+        assert_eq!(removed.len(), 2);
+        assert_eq!(chunk_types(&png), ["FrSt", "miDl", "LASt", "FrSt"]);
+        assert!(png.remove_all_by_type("TeSt").is_err());
+    }
+
+    fn chunk_types(png: &Png) -> Vec<String> {
+        png.chunks()
+            .iter()
+            .map(|c| c.chunk_type().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_insert_chunk_before_iend() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let idx = png
+            .insert_chunk(
+                chunk_from_strings("TeSt", "Message").unwrap(),
+                Placement::default(),
+            )
+            .unwrap();
+        let types = chunk_types(&png);
+        assert_eq!(idx, types.len() - 2);
+        assert_eq!(types[types.len() - 2], "TeSt");
+        assert_eq!(types[types.len() - 1], "IEND");
+    }
+
+    #[test]
+    fn test_insert_chunk_after_ihdr() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let idx = png
+            .insert_chunk(
+                chunk_from_strings("TeSt", "Message").unwrap(),
+                Placement::AfterIhdr,
+            )
+            .unwrap();
+        assert_eq!(idx, 1);
+        assert_eq!(chunk_types(&png)[..2], ["IHDR", "TeSt"]);
+    }
+
+    #[test]
+    fn test_insert_chunk_around_idat() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.insert_chunk(
+            chunk_from_strings("BeFr", "before").unwrap(),
+            Placement::BeforeFirstIdat,
+        )
+        .unwrap();
+        png.insert_chunk(
+            chunk_from_strings("AfTr", "after").unwrap(),
+            Placement::AfterLastIdat,
+        )
+        .unwrap();
+        let types = chunk_types(&png);
+        let idat = types.iter().position(|t| t == "IDAT").unwrap();
+        assert_eq!(types[idat - 1], "BeFr");
+        assert_eq!(types[idat + 1], "AfTr");
+    }
+
+    #[test]
+    fn test_insert_chunk_without_idat() {
+        let mut png = testing_png();
+        let res = png.insert_chunk(
+            chunk_from_strings("TeSt", "Message").unwrap(),
+            Placement::BeforeFirstIdat,
+        );
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_insert_chunk_at_index() {
+        let mut png = testing_png();
+        png.insert_chunk(
+            chunk_from_strings("TeSt", "Message").unwrap(),
+            Placement::Index(1),
+        )
+        .unwrap();
+        assert_eq!(chunk_types(&png), ["FrSt", "TeSt", "miDl", "LASt"]);
+
+        let res = png.insert_chunk(
+            chunk_from_strings("TeSt", "Message").unwrap(),
+            Placement::Index(10),
+        );
+        assert!(res.is_err());
+    }
+
     #[test]
     fn test_png_from_image_file() {
         let png = Png::try_from(&PNG_FILE[..]);
@@ -272,6 +647,24 @@ mod tests {
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn test_ihdr() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let ihdr = png.ihdr().unwrap();
+        assert_eq!((ihdr.width(), ihdr.height()), (50, 50));
+        assert_eq!(ihdr.color_type(), crate::ColorType::Rgba);
+        assert!(
+            png.to_string()
+                .contains("IHDR\t50x50, 8 bit RGBA, not interlaced")
+        );
+
+        let png = testing_png();
+        assert!(matches!(
+            png.ihdr(),
+            Err(crate::Error::Png(PngError::ChunkNotFound(_)))
+        ));
+    }
+
     #[test]
     fn test_png_trait_impls() {
         let chunk_bytes: Vec<u8> = testing_chunks()
@@ -0,0 +1,158 @@
+//! Signing the image itself, to prove later that it wasn't changed since it was published.
+//!
+//! The signature (see [`crate::signature`]) covers the decoded pixels and the critical
+//! chunks and is stored in a `pgSG` chunk without a target. Since the pixels are hashed
+//! after decompressing and unfiltering them, recompressing the image only changes the
+//! critical chunks, while editing it changes the pixels.
+
+use crate::Result;
+use crate::png::{Placement, Png};
+use crate::signature::{self, Digest, Signature, SigningKey, VerifyingKey};
+
+/// what [`verify`] found out about a signed image
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    /// who signed the image
+    pub signer: VerifyingKey,
+    /// the decoded pixels (or the image header) changed
+    pub pixels_changed: bool,
+    /// some critical chunk changed, e.g. because the image was recompressed
+    pub critical_chunks_changed: bool,
+}
+
+/// sign the pixels and critical chunks of png with key
+///
+/// an existing signature of the image is replaced, signatures of hidden data are kept
+pub fn sign(png: &mut Png, key: &SigningKey) -> Result<()> {
+    let digests = vec![
+        (Digest::Pixels, signature::pixels_digest(png)?),
+        (
+            Digest::CriticalChunks,
+            signature::critical_chunks_digest(png),
+        ),
+    ];
+    let chunk = Signature::sign(key, "", digests)?.to_chunk();
+
+    match existing(png) {
+        Some(index) => {
+            png.replace_chunk(index, chunk)?;
+        }
+        None => {
+            png.insert_chunk(chunk, Placement::default())?;
+        }
+    }
+    Ok(())
+}
+
+/// check the signature of the image and report what changed since it was signed
+///
+/// only keys in trusted are accepted, there has to be at least one
+pub fn verify(png: &Png, trusted: &[VerifyingKey]) -> Result<Report> {
+    let signature = Signature::find(png, "").ok_or(signature::SignatureError::Missing)?;
+    let changed = signature.changed(trusted, |kind| match kind {
+        Digest::Pixels => signature::pixels_digest(png),
+        Digest::CriticalChunks => Ok(signature::critical_chunks_digest(png)),
+        // an image signature never signs hidden data
+        Digest::HiddenData => Ok([0; 32]),
+    })?;
+
+    Ok(Report {
+        signer: signature.signer(),
+        pixels_changed: changed.contains(&Digest::Pixels),
+        critical_chunks_changed: changed.contains(&Digest::CriticalChunks),
+    })
+}
+
+/// index of the chunk holding the signature of the image
+fn existing(png: &Png) -> Option<usize> {
+    png.chunks().iter().rposition(|chunk| {
+        chunk.chunk_type().to_string() == signature::CHUNK_TYPE
+            && Signature::try_from(chunk).is_ok_and(|s| s.target().is_empty())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+    use crate::pixels::Pixels;
+    use crate::test_util::{dice, dice_indexed};
+
+    #[test]
+    fn test_sign_verify() {
+        let key = SigningKey::generate();
+        let mut png = dice();
+        sign(&mut png, &key).unwrap();
+        // signing again replaces the signature
+        sign(&mut png, &key).unwrap();
+        assert_eq!(png.chunks().len(), dice().chunks().len() + 1);
+
+        let report = verify(&png, &[key.verifying_key()]).unwrap();
+        assert_eq!(report.signer, key.verifying_key());
+        assert!(!report.pixels_changed && !report.critical_chunks_changed);
+        assert!(verify(&dice(), &[key.verifying_key()]).is_err());
+        assert!(matches!(
+            verify(&png, &[]),
+            Err(crate::Error::Signature(
+                signature::SignatureError::NoTrustedKeys
+            ))
+        ));
+
+        // junk in a signature chunk is skipped, not replaced or failed on
+        let junk = Chunk::new(
+            ChunkType::from_str(signature::CHUNK_TYPE).unwrap(),
+            vec![1; 9],
+        );
+        png.insert_chunk(junk, Placement::default()).unwrap();
+        sign(&mut png, &key).unwrap();
+        assert_eq!(png.chunks().len(), dice().chunks().len() + 2);
+        assert!(verify(&png, &[key.verifying_key()]).is_ok());
+    }
+
+    #[test]
+    fn test_palette_changed() {
+        let key = SigningKey::generate();
+        let trusted = [key.verifying_key()];
+
+        // the indices stay the same, but the colors they stand for don't
+        for chunk_type in ["PLTE", "tRNS"] {
+            let mut png = dice_indexed();
+            sign(&mut png, &key).unwrap();
+            let index = png
+                .chunks()
+                .iter()
+                .position(|c| c.chunk_type().to_string() == chunk_type)
+                .unwrap();
+            let mut data = png.chunks()[index].data().to_vec();
+            data[0] ^= 0xff;
+            let chunk = Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data);
+            png.replace_chunk(index, chunk).unwrap();
+            assert!(
+                verify(&png, &trusted).unwrap().pixels_changed,
+                "{chunk_type}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_recompressed_and_edited() {
+        let key = SigningKey::generate();
+        let mut png = dice();
+        sign(&mut png, &key).unwrap();
+
+        // rewriting the same pixels changes the compressed data, not the pixels
+        let pixels = Pixels::from_png(&png).unwrap();
+        pixels.write_to(&mut png).unwrap();
+        let trusted = [key.verifying_key()];
+        let report = verify(&png, &trusted).unwrap();
+        assert!(!report.pixels_changed && report.critical_chunks_changed);
+
+        let mut pixels = Pixels::from_png(&png).unwrap();
+        pixels.data_mut()[0] ^= 1;
+        pixels.write_to(&mut png).unwrap();
+        assert!(verify(&png, &trusted).unwrap().pixels_changed);
+    }
+}
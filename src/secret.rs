@@ -0,0 +1,991 @@
+use std::str::FromStr;
+
+use crate::Result;
+use crate::alpha;
+use crate::capacity::Method;
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::crypt::x25519::{Identity, Recipient};
+use crate::crypt::{self, Cipher, CryptoError, Kdf, vigenere};
+use crate::ihdr::ColorType;
+use crate::lsb::{self, LsbOptions};
+use crate::palette;
+use crate::payload::Payload;
+use crate::pixels::Pixels;
+use crate::png::{Placement, Png, PngError};
+use crate::sequence;
+use crate::signature::{self, Digest, Signature, SigningKey, VerifyingKey};
+use crate::text_chunk::{TextChunk, TextChunkError, TextKind, from_latin1, to_latin1};
+
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+
+/// keyword used for tEXt, zTXt and iTXt chunks if none is given
+pub const DEFAULT_KEYWORD: &str = "Comment";
+
+/// how a message is hidden by [`encode`]
+#[derive(Debug, Clone, Default)]
+pub struct EncodeOptions {
+    /// encrypt the message using this passphrase
+    pub passphrase: Option<String>,
+    /// encrypt the message to these public keys instead of a passphrase
+    pub recipients: Vec<Recipient>,
+    /// cipher used if a passphrase or recipients are given
+    pub cipher: Cipher,
+    /// how the key is derived from the passphrase
+    pub kdf: Kdf,
+    /// compress the payload with zlib at this level (0-9) before encrypting it
+    pub compression_level: Option<u32>,
+    /// split the data over multiple chunks with at most this many bytes each
+    ///
+    /// data that doesn't fit into a single chunk is always split
+    pub max_chunk_size: Option<usize>,
+    /// keyword of tEXt, zTXt and iTXt chunks, [`DEFAULT_KEYWORD`] if None
+    pub keyword: Option<String>,
+    /// sign the hidden data with this key, in a separate chunk (see [`crate::signature`])
+    pub signing_key: Option<SigningKey>,
+    /// sign the critical chunks as well, so changes to the image itself are detected
+    pub sign_critical_chunks: bool,
+}
+
+/// how a message is found and read by [`decode`]
+#[derive(Debug, Clone, Default)]
+pub struct DecodeOptions {
+    /// decrypt the message using this passphrase
+    pub passphrase: Option<String>,
+    /// decrypt messages encrypted to recipients using any of these
+    pub identities: Vec<Identity>,
+    /// decrypt messages encrypted by older versions of PNGecret with the Vigenère cipher
+    ///
+    /// without it, a passphrase for a message that isn't in an envelope is an error
+    pub legacy: bool,
+    /// keyword of tEXt, zTXt and iTXt chunks, [`DEFAULT_KEYWORD`] if None
+    pub keyword: Option<String>,
+    /// which of the chunks of the type (counting from 0, only the ones with the keyword
+    /// for text chunks) holds the message, the last one if None
+    pub index: Option<usize>,
+    /// only accept hidden data with a valid signature by one of these keys
+    ///
+    /// signatures aren't checked if this is empty
+    pub trusted_keys: Vec<VerifyingKey>,
+}
+
+/// hide msg (any bytes, text or not) in a new chunk of type chunk_type
+///
+/// the chunk is inserted before IEND, so the png stays valid
+pub fn encode(png: &mut Png, chunk_type: &str, msg: &[u8], options: &EncodeOptions) -> Result<()> {
+    encode_payload(png, chunk_type, &Payload::message(msg.to_vec()), options)
+}
+
+/// hide payload (a message or a whole file) in a new chunk of type chunk_type
+pub fn encode_payload(
+    png: &mut Png,
+    chunk_type: &str,
+    payload: &Payload,
+    options: &EncodeOptions,
+) -> Result<()> {
+    let chunk_type = ChunkType::from_str(chunk_type)?;
+    let text_kind = TextKind::from_chunk_type(&chunk_type.to_string());
+
+    let data = seal(payload, options, text_kind)?;
+    let data_digest = signature::digest(&data);
+
+    // split data if it is too big for a single chunk
+    let max_len = options
+        .max_chunk_size
+        .unwrap_or(Chunk::MAX_LENGTH)
+        .min(Chunk::MAX_LENGTH);
+    let keyword = options.keyword.as_deref().unwrap_or(DEFAULT_KEYWORD);
+    let mut piece_len = max_len;
+    let chunks = loop {
+        let pieces = if data.len() > piece_len {
+            sequence::split(&data, piece_len)?
+        } else {
+            vec![data.clone()]
+        };
+        let chunks = pieces
+            .iter()
+            .map(|piece| data_chunk(&chunk_type, text_kind, keyword, piece))
+            .collect::<Result<Vec<Chunk>>>()?;
+        let longest = chunks
+            .iter()
+            .map(|c| c.length() as usize)
+            .max()
+            .unwrap_or(0);
+        if longest <= max_len {
+            break chunks;
+        }
+        // text chunks add a header and base64 encode split data, so the pieces
+        // shrink until the chunks fit
+        piece_len = piece_len
+            .min(data.len())
+            .checked_sub(longest - max_len)
+            .filter(|l| *l > sequence::HEADER_LEN)
+            .ok_or(sequence::SequenceError::MaxLengthTooSmall(max_len))?;
+    };
+
+    // insert them before IEND, in order
+    for chunk in chunks {
+        png.insert_chunk(chunk, Placement::default())?;
+    }
+
+    // the signature goes after the data, once every chunk is in place
+    if let Some(key) = &options.signing_key {
+        let mut digests = vec![(Digest::HiddenData, data_digest)];
+        if options.sign_critical_chunks {
+            digests.push((
+                Digest::CriticalChunks,
+                signature::critical_chunks_digest(png),
+            ));
+        }
+        let target = signature_target(&chunk_type.to_string(), keyword);
+        let signature = Signature::sign(key, &target, digests)?;
+        png.insert_chunk(signature.to_chunk(), Placement::default())?;
+    }
+
+    Ok(())
+}
+
+/// chunk of type chunk_type holding data, as text if it is a text chunk
+fn data_chunk(
+    chunk_type: &ChunkType,
+    text_kind: Option<TextKind>,
+    keyword: &str,
+    data: &[u8],
+) -> Result<Chunk> {
+    let Some(kind) = text_kind else {
+        return Chunk::try_new(chunk_type.clone(), data.to_vec());
+    };
+    // seal puts everything else in a container, which starts with 0x89 like pieces do
+    let text = data_to_text(kind, data).ok_or(TextChunkError::CannotStore)?;
+    let text_chunk = match kind {
+        TextKind::Text => TextChunk::plain(keyword, &text)?,
+        TextKind::CompressedText => TextChunk::compressed(keyword, &text)?,
+        TextKind::InternationalText => TextChunk::international(keyword, "", "", false, &text)?,
+    };
+    text_chunk.to_chunk()
+}
+
+/// where data is hidden, as stored in its signature
+fn signature_target(chunk_type: &str, keyword: &str) -> String {
+    match TextKind::from_chunk_type(chunk_type) {
+        Some(_) => format!("{chunk_type}\0{keyword}"),
+        None => chunk_type.to_string(),
+    }
+}
+
+/// check the signature of data hidden in chunk_type and return who signed it
+fn check_signature(
+    png: &Png,
+    chunk_type: &str,
+    keyword: &str,
+    data: &[u8],
+    trusted: &[VerifyingKey],
+) -> Result<VerifyingKey> {
+    let signatures = Signature::find_all(png, &signature_target(chunk_type, keyword));
+    // messages hidden at the same target all have their own signature, take the one
+    // signed for this data, and the last one to report what changed if none is
+    let hidden = (Digest::HiddenData, signature::digest(data));
+    let signature = signatures
+        .iter()
+        .find(|s| s.digests().contains(&hidden))
+        .or(signatures.first())
+        .ok_or(signature::SignatureError::Missing)?;
+    signature.verify(trusted, |kind| match kind {
+        Digest::HiddenData => Ok(signature::digest(data)),
+        Digest::CriticalChunks => Ok(signature::critical_chunks_digest(png)),
+        Digest::Pixels => signature::pixels_digest(png),
+    })?;
+    Ok(signature.signer())
+}
+
+/// number of bytes added to payload before it is hidden: the container around it and
+/// the encryption envelope
+///
+/// compression isn't taken into account, since it depends on the data
+pub fn overhead(payload: &Payload, options: &EncodeOptions) -> usize {
+    let container = payload.as_bytes().len() - payload.data().len();
+    let envelope = match (&options.passphrase, options.recipients.len()) {
+        (Some(_), _) => crypt::overhead(options.cipher, options.kdf),
+        (None, 0) => 0,
+        (None, n) => crypt::recipients_overhead(options.cipher, n),
+    };
+    container + envelope
+}
+
+/// payload as it is hidden: optionally compressed, then optionally encrypted,
+/// file name included
+///
+/// messages that can't be stored in a text chunk of text_kind as is are put in a container
+fn seal(
+    payload: &Payload,
+    options: &EncodeOptions,
+    text_kind: Option<TextKind>,
+) -> Result<Vec<u8>> {
+    let mut data = match options.compression_level {
+        Some(level) => payload.as_compressed_bytes(level),
+        None => payload.as_bytes(),
+    };
+    if let Some(kind) = text_kind
+        && data_to_text(kind, &data).is_none()
+    {
+        data = payload.as_container_bytes();
+    }
+    match (&options.passphrase, options.recipients.as_slice()) {
+        (Some(_), [_, ..]) => Err(CryptoError::PassphraseAndRecipients.into()),
+        (Some(pass), []) => crypt::encrypt(&data, pass, options.cipher, options.kdf),
+        (None, []) => Ok(data),
+        (None, recipients) => crypt::encrypt_to(&data, recipients, options.cipher),
+    }
+}
+
+/// reverse of seal
+///
+/// messages encrypted by older versions of PNGecret are decrypted with the Vigenère cipher
+/// if options.legacy is set
+fn open(data: Vec<u8>, options: &DecodeOptions) -> Result<Payload> {
+    if crypt::is_encrypted_to_recipients(&data) {
+        let data = crypt::decrypt_with(&data, &options.identities)?;
+        return Payload::try_from(data.as_slice());
+    }
+    if crypt::is_envelope(&data) {
+        let pass = options
+            .passphrase
+            .as_deref()
+            .ok_or(CryptoError::PassphraseRequired)?;
+        let data = crypt::decrypt(&data, pass)?;
+        return Payload::try_from(data.as_slice());
+    }
+
+    let Some(pass) = options.passphrase.as_deref() else {
+        return Payload::try_from(data.as_slice());
+    };
+    // binary data with a passphrase is an envelope whose magic was changed
+    if data.first() == Some(&0x89) {
+        return Err(CryptoError::BadEnvelope.into());
+    }
+    // the Vigenère cipher only ever produced text in its alphabet
+    let text = String::from_utf8(data)
+        .ok()
+        .filter(|text| vigenere::is_ciphertext(text))
+        .ok_or(CryptoError::DecryptionFailed)?;
+    if !options.legacy {
+        return Err(CryptoError::NotEncrypted.into());
+    }
+    Ok(Payload::message(
+        vigenere::decrypt(&text, pass)?.into_bytes(),
+    ))
+}
+
+/// text that stores data in a text chunk of kind, None if data can't be stored in it
+///
+/// text is stored as is, binary data (everything starting with 0x89) as base64
+fn data_to_text(kind: TextKind, data: &[u8]) -> Option<String> {
+    let text = if data.first() == Some(&0x89) {
+        BASE64.encode(data)
+    } else if kind.is_latin1() {
+        // every byte but 0 is a Latin-1 char, so any message round trips exactly
+        if data.contains(&0) {
+            return None;
+        }
+        from_latin1(data)
+    } else {
+        String::from_utf8(data.to_vec()).ok()?
+    };
+
+    // text that looks like base64 encoded binary data would be misread
+    (text_to_data(kind, &text) == data).then_some(text)
+}
+
+/// reverse of data_to_text
+fn text_to_data(kind: TextKind, text: &str) -> Vec<u8> {
+    match BASE64.decode(text) {
+        Ok(data) if data.starts_with(&[0x89, b'P', b'G']) => data,
+        // text came from a text chunk of this kind, so it is Latin-1 if it has to be
+        _ if kind.is_latin1() => to_latin1(text).unwrap_or_default(),
+        _ => text.as_bytes().to_vec(),
+    }
+}
+
+/// data hidden in the chunks of type chunk_type, joined together if it was split
+fn hidden_data(png: &Png, chunk_type: &str, options: &DecodeOptions) -> Result<Option<Vec<u8>>> {
+    Ok(locate(png, chunk_type, options)?.map(|(_, data)| data))
+}
+
+/// indices of the chunks holding one hidden message and the data in them
+///
+/// for text chunks only the ones with the keyword of options are used. options.index
+/// picks one of those chunks (the last one if None), if it is a piece of split data,
+/// all pieces of the same data are used
+fn locate(
+    png: &Png,
+    chunk_type: &str,
+    options: &DecodeOptions,
+) -> Result<Option<(Vec<usize>, Vec<u8>)>> {
+    let keyword = options.keyword.as_deref().unwrap_or(DEFAULT_KEYWORD);
+    let chunks = png
+        .chunks()
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| c.chunk_type().bytes() == chunk_type.as_bytes());
+    let found: Vec<(usize, Vec<u8>)> = match TextKind::from_chunk_type(chunk_type) {
+        Some(kind) => chunks
+            .filter_map(|(i, c)| TextChunk::try_from(c).ok().map(|t| (i, t)))
+            .filter(|(_, t)| t.keyword() == keyword)
+            .map(|(i, t)| (i, text_to_data(kind, t.text())))
+            .collect(),
+        None => chunks.map(|(i, c)| (i, c.data().to_vec())).collect(),
+    };
+
+    let Some(last) = found.len().checked_sub(1) else {
+        return Ok(None);
+    };
+    let index = options.index.unwrap_or(last);
+    let (chunk_index, data) = found.get(index).ok_or(PngError::IndexOutOfBounds {
+        index,
+        len: found.len(),
+    })?;
+    if !sequence::is_piece(data) {
+        return Ok(Some((vec![*chunk_index], data.clone())));
+    }
+
+    // only join the pieces of the chosen data, in case there are multiple
+    let id = sequence::piece_id(data)?;
+    let (indices, pieces): (Vec<usize>, Vec<&[u8]>) = found
+        .iter()
+        .filter(|(_, d)| sequence::is_piece(d) && sequence::piece_id(d).is_ok_and(|i| i == id))
+        .map(|(i, d)| (*i, d.as_slice()))
+        .unzip();
+    Ok(Some((indices, sequence::join(pieces)?)))
+}
+
+/// read the message hidden in the chunk of type chunk_type, decrypting it if options hold a key
+///
+/// if a file is hidden, this returns its content
+///
+/// returns None if there is no chunk of this type
+pub fn decode(png: &Png, chunk_type: &str, options: &DecodeOptions) -> Result<Option<Vec<u8>>> {
+    Ok(decode_payload(png, chunk_type, options)?.map(Payload::into_data))
+}
+
+/// read the payload hidden in the chunk of type chunk_type, decrypting it if options hold a key
+///
+/// messages encrypted by older versions of PNGecret are decrypted with the Vigenère cipher
+/// if options.legacy is set
+///
+/// returns None if there is no chunk of this type
+pub fn decode_payload(
+    png: &Png,
+    chunk_type: &str,
+    options: &DecodeOptions,
+) -> Result<Option<Payload>> {
+    let Some(data) = hidden_data(png, chunk_type, options)? else {
+        return Ok(None);
+    };
+    if !options.trusted_keys.is_empty() {
+        let keyword = options.keyword.as_deref().unwrap_or(DEFAULT_KEYWORD);
+        check_signature(png, chunk_type, keyword, &data, &options.trusted_keys)?;
+    }
+    open(data, options).map(Some)
+}
+
+/// check the signature of the data hidden in the chunk of type chunk_type and return
+/// the key that signed it
+///
+/// only keys in the trusted keys of options are accepted, there has to be at least one
+pub fn verify(png: &Png, chunk_type: &str, options: &DecodeOptions) -> Result<VerifyingKey> {
+    let keyword = options.keyword.as_deref().unwrap_or(DEFAULT_KEYWORD);
+    let data = hidden_data(png, chunk_type, options)?
+        .ok_or_else(|| PngError::ChunkNotFound(chunk_type.to_string()))?;
+    check_signature(png, chunk_type, keyword, &data, &options.trusted_keys)
+}
+
+/// like [`decode`], but the message has to be valid UTF-8 text
+pub fn decode_text(png: &Png, chunk_type: &str, options: &DecodeOptions) -> Result<Option<String>> {
+    match decode(png, chunk_type, options)? {
+        Some(msg) => Ok(Some(String::from_utf8(msg)?)),
+        None => Ok(None),
+    }
+}
+
+/// hide payload in the low bits of the pixels, without adding any chunks, and return
+/// how it was hidden
+///
+/// the image data is rewritten, but the list of chunks stays the same. Indexed images
+/// hide it in their palette indices instead (see [`palette`]), where only the key of
+/// lsb_options is used
+pub fn embed_payload(
+    png: &mut Png,
+    payload: &Payload,
+    options: &EncodeOptions,
+    lsb_options: &LsbOptions,
+) -> Result<Method> {
+    let data = seal(payload, options, None)?;
+    if png.ihdr()?.color_type() == ColorType::Indexed {
+        palette::embed(png, &data, lsb_options.key)?;
+        return Ok(Method::Palette);
+    }
+    let mut pixels = Pixels::from_png(png)?;
+    // too few transparent pixels fall back to the low bits
+    let method = if lsb_options.transparent && data.len() <= alpha::capacity(&pixels) {
+        alpha::embed(&mut pixels, &data, lsb_options.key)?;
+        Method::Alpha
+    } else {
+        lsb::embed(&mut pixels, &data, lsb_options)?;
+        Method::Lsb {
+            bits: lsb_options.bits,
+        }
+    };
+    pixels.write_to(png)?;
+    Ok(method)
+}
+
+/// read the payload hidden in the low bits of the pixels by [`embed_payload`]
+///
+/// data hidden in fully transparent pixels is found whether lsb_options.transparent is
+/// set or not, returns None if nothing is hidden in the pixels
+pub fn extract_payload(
+    png: &Png,
+    options: &DecodeOptions,
+    lsb_options: &LsbOptions,
+) -> Result<Option<Payload>> {
+    let data = if png.ihdr()?.color_type() == ColorType::Indexed {
+        palette::extract(png, lsb_options.key)?
+    } else {
+        let pixels = Pixels::from_png(png)?;
+        match alpha::extract(&pixels, lsb_options.key)? {
+            Some(data) => Some(data),
+            None => lsb::extract(&pixels, lsb_options)?,
+        }
+    };
+    match data {
+        Some(data) => open(data, options).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// remove the chunks holding the message [`decode`] would read with the same options
+/// and return them
+///
+/// all pieces of a split message are removed
+pub fn remove(png: &mut Png, chunk_type: &str, options: &DecodeOptions) -> Result<Vec<Chunk>> {
+    let (indices, _) = locate(png, chunk_type, options)?
+        .ok_or_else(|| PngError::ChunkNotFound(chunk_type.to_string()))?;
+    remove_chunks(png, indices)
+}
+
+/// remove every chunk of type chunk_type that could hold a message and return them
+///
+/// for text chunks only the ones with the keyword of options are removed
+pub fn remove_all(png: &mut Png, chunk_type: &str, options: &DecodeOptions) -> Result<Vec<Chunk>> {
+    if TextKind::from_chunk_type(chunk_type).is_none() {
+        return png.remove_all_by_type(chunk_type);
+    }
+    let keyword = options.keyword.as_deref().unwrap_or(DEFAULT_KEYWORD);
+    let indices: Vec<usize> = png
+        .chunks()
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| c.chunk_type().bytes() == chunk_type.as_bytes())
+        .filter(|(_, c)| TextChunk::try_from(*c).is_ok_and(|t| t.keyword() == keyword))
+        .map(|(i, _)| i)
+        .collect();
+    if indices.is_empty() {
+        return Err(PngError::ChunkNotFound(chunk_type.to_string()).into());
+    }
+    remove_chunks(png, indices)
+}
+
+/// remove the chunks at indices (in ascending order) and return them
+fn remove_chunks(png: &mut Png, indices: Vec<usize>) -> Result<Vec<Chunk>> {
+    // back to front, so the other indices stay valid
+    let mut removed = Vec::new();
+    for index in indices.into_iter().rev() {
+        removed.push(png.remove_chunk_at(index)?);
+    }
+    removed.reverse();
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::dice;
+
+    fn testing_png() -> Png {
+        let chunks = vec![
+            Chunk::new(ChunkType::from_str("IHDR").unwrap(), vec![0; 13]),
+            Chunk::new(ChunkType::from_str("IEND").unwrap(), vec![]),
+        ];
+        Png::from_chunks(chunks)
+    }
+
+    fn encrypted() -> EncodeOptions {
+        EncodeOptions {
+            passphrase: Some("password".to_string()),
+            kdf: Kdf::Argon2id {
+                memory: 8,
+                iterations: 1,
+                parallelism: 1,
+            },
+            ..Default::default()
+        }
+    }
+
+    fn passphrase(passphrase: &str) -> DecodeOptions {
+        DecodeOptions {
+            passphrase: Some(passphrase.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        let mut png = testing_png();
+        encode(&mut png, "TeSt", b"Hello World!", &EncodeOptions::default()).unwrap();
+        let msg = decode_text(&png, "TeSt", &DecodeOptions::default()).unwrap();
+        assert_eq!(msg.as_deref(), Some("Hello World!"));
+    }
+
+    #[test]
+    fn test_encode_decode_encrypted() {
+        let mut png = testing_png();
+        encode(&mut png, "TeSt", b"Hello World!", &encrypted()).unwrap();
+        assert!(crypt::is_envelope(
+            png.chunk_by_type("TeSt").unwrap().data()
+        ));
+        let msg = decode_text(&png, "TeSt", &passphrase("password")).unwrap();
+        assert_eq!(msg.as_deref(), Some("Hello World!"));
+    }
+
+    #[test]
+    fn test_encode_decode_recipients() {
+        let alice = Identity::generate();
+        let bob = Identity::generate();
+        let options = EncodeOptions {
+            recipients: vec![alice.recipient(), bob.recipient()],
+            ..Default::default()
+        };
+        let mut png = testing_png();
+        encode(&mut png, "TeSt", b"Hello World!", &options).unwrap();
+
+        let identity = |identity: Identity| DecodeOptions {
+            identities: vec![identity],
+            ..Default::default()
+        };
+        for i in [alice, bob] {
+            let msg = decode_text(&png, "TeSt", &identity(i)).unwrap();
+            assert_eq!(msg.as_deref(), Some("Hello World!"));
+        }
+        assert!(matches!(
+            decode(&png, "TeSt", &passphrase("password")),
+            Err(crate::Error::Crypto(CryptoError::IdentityRequired))
+        ));
+        assert!(matches!(
+            decode(&png, "TeSt", &identity(Identity::generate())),
+            Err(crate::Error::Crypto(CryptoError::NotARecipient))
+        ));
+
+        // a passphrase and recipients at once is ambiguous
+        let options = EncodeOptions {
+            recipients: vec![Identity::generate().recipient()],
+            ..encrypted()
+        };
+        assert!(encode(&mut png, "TeSt", b"Hello World!", &options).is_err());
+    }
+
+    #[test]
+    fn test_sign_verify() {
+        let key = SigningKey::generate();
+        let options = EncodeOptions {
+            signing_key: Some(key.clone()),
+            sign_critical_chunks: true,
+            ..encrypted()
+        };
+        let mut png = testing_png();
+        encode(&mut png, "TeSt", b"Hello World!", &options).unwrap();
+        encode(&mut png, "teXt", b"unsigned", &EncodeOptions::default()).unwrap();
+
+        let trusted = DecodeOptions {
+            trusted_keys: vec![key.verifying_key()],
+            ..passphrase("password")
+        };
+        assert_eq!(verify(&png, "TeSt", &trusted).unwrap(), key.verifying_key());
+        let msg = decode_text(&png, "TeSt", &trusted).unwrap();
+        assert_eq!(msg.as_deref(), Some("Hello World!"));
+        assert!(matches!(
+            decode(&png, "teXt", &trusted),
+            Err(crate::Error::Signature(signature::SignatureError::Missing))
+        ));
+        let untrusted = DecodeOptions {
+            trusted_keys: vec![SigningKey::generate().verifying_key()],
+            ..passphrase("password")
+        };
+        assert!(matches!(
+            decode(&png, "TeSt", &untrusted),
+            Err(crate::Error::Signature(
+                signature::SignatureError::UntrustedKey(_)
+            ))
+        ));
+
+        // changing the image header is caught as well
+        png.replace_chunk(
+            0,
+            Chunk::new(ChunkType::from_str("IHDR").unwrap(), vec![1; 13]),
+        )
+        .unwrap();
+        assert!(matches!(
+            verify(&png, "TeSt", &trusted),
+            Err(crate::Error::Signature(signature::SignatureError::Changed(d)))
+                if d == [Digest::CriticalChunks]
+        ));
+    }
+
+    #[test]
+    fn test_encode_decode_binary() {
+        let data: Vec<u8> = (0..=255).collect();
+        let mut png = testing_png();
+        encode(&mut png, "TeSt", &data, &encrypted()).unwrap();
+        encode(&mut png, "PlAn", &data, &EncodeOptions::default()).unwrap();
+        assert_eq!(
+            decode(&png, "TeSt", &passphrase("password")).unwrap(),
+            Some(data.clone())
+        );
+        assert_eq!(
+            decode(&png, "PlAn", &DecodeOptions::default()).unwrap(),
+            Some(data)
+        );
+        assert!(matches!(
+            decode_text(&png, "PlAn", &DecodeOptions::default()),
+            Err(crate::Error::Utf8(_))
+        ));
+    }
+
+    #[test]
+    fn test_encode_decode_unicode() {
+        let msg = "héllo wörld, こんにちは 🦀";
+        let mut png = testing_png();
+        encode(&mut png, "TeSt", msg.as_bytes(), &encrypted()).unwrap();
+        let decoded = decode_text(&png, "TeSt", &passphrase("password")).unwrap();
+        assert_eq!(decoded.as_deref(), Some(msg));
+    }
+
+    #[test]
+    fn test_encode_decode_file() {
+        let mut png = testing_png();
+        let file = Payload::file("notes.txt", b"Hello World!".to_vec()).unwrap();
+        encode_payload(&mut png, "TeSt", &file, &encrypted()).unwrap();
+        encode_payload(&mut png, "PlAn", &file, &EncodeOptions::default()).unwrap();
+
+        let decoded = decode_payload(&png, "TeSt", &passphrase("password")).unwrap();
+        assert_eq!(decoded.as_ref(), Some(&file));
+        let decoded = decode_payload(&png, "PlAn", &DecodeOptions::default()).unwrap();
+        assert_eq!(decoded.as_ref(), Some(&file));
+        // decode only returns the content
+        let decoded = decode(&png, "PlAn", &DecodeOptions::default()).unwrap();
+        assert_eq!(decoded.as_deref(), Some(&b"Hello World!"[..]));
+    }
+
+    #[test]
+    fn test_encode_decode_split() {
+        let data: Vec<u8> = (0..1000).map(|i| (i % 256) as u8).collect();
+        let mut png = testing_png();
+        let options = EncodeOptions {
+            max_chunk_size: Some(100),
+            ..encrypted()
+        };
+        encode(&mut png, "TeSt", &data, &options).unwrap();
+
+        let chunks: Vec<&Chunk> = png
+            .chunks()
+            .iter()
+            .filter(|c| c.chunk_type().to_string() == "TeSt")
+            .collect();
+        assert!(chunks.len() > 10);
+        assert!(chunks.iter().all(|c| c.length() <= 100));
+        assert_eq!(
+            png.chunks().last().unwrap().chunk_type().to_string(),
+            "IEND"
+        );
+
+        assert_eq!(
+            decode(&png, "TeSt", &passphrase("password")).unwrap(),
+            Some(data)
+        );
+    }
+
+    #[test]
+    fn test_encode_decode_compressed() {
+        let msg = "all work and no play makes jack a dull boy\n".repeat(50);
+        let mut png = testing_png();
+        let options = EncodeOptions {
+            compression_level: Some(9),
+            ..Default::default()
+        };
+        encode(&mut png, "TeSt", msg.as_bytes(), &options).unwrap();
+        let options = EncodeOptions {
+            compression_level: Some(9),
+            ..encrypted()
+        };
+        encode(&mut png, "EnCr", msg.as_bytes(), &options).unwrap();
+
+        assert!((png.chunk_by_type("TeSt").unwrap().length() as usize) < msg.len() / 10);
+        let decoded = decode_text(&png, "TeSt", &DecodeOptions::default()).unwrap();
+        assert_eq!(decoded.as_deref(), Some(msg.as_str()));
+        let decoded = decode_text(&png, "EnCr", &passphrase("password")).unwrap();
+        assert_eq!(decoded.as_deref(), Some(msg.as_str()));
+    }
+
+    #[test]
+    fn test_encode_decode_text_chunks() {
+        let binary: Vec<u8> = (0..=255).collect();
+        for chunk_type in ["tEXt", "zTXt", "iTXt"] {
+            let mut png = testing_png();
+            encode(
+                &mut png,
+                chunk_type,
+                b"Hello World!",
+                &EncodeOptions::default(),
+            )
+            .unwrap();
+            let text = TextChunk::try_from(png.chunk_by_type(chunk_type).unwrap()).unwrap();
+            assert_eq!(text.keyword(), DEFAULT_KEYWORD);
+            assert_eq!(text.text(), "Hello World!");
+            let msg = decode(&png, chunk_type, &DecodeOptions::default()).unwrap();
+            assert_eq!(msg.as_deref(), Some(&b"Hello World!"[..]));
+
+            // binary and encrypted data is stored as base64
+            let mut png = testing_png();
+            encode(&mut png, chunk_type, &binary, &encrypted()).unwrap();
+            encode(&mut png, chunk_type, &binary, &EncodeOptions::default()).unwrap();
+            let text = TextChunk::try_from(png.chunk_by_type(chunk_type).unwrap()).unwrap();
+            assert!(BASE64.decode(text.text()).is_ok());
+            let msg = decode(&png, chunk_type, &DecodeOptions::default()).unwrap();
+            assert_eq!(msg.as_ref(), Some(&binary));
+
+            // base64 and the text chunk header still fit into the maximum chunk size
+            let mut png = testing_png();
+            let split = EncodeOptions {
+                max_chunk_size: Some(100),
+                ..Default::default()
+            };
+            encode(&mut png, chunk_type, &binary, &split).unwrap();
+            let chunks: Vec<&Chunk> = png.chunks_by_type(chunk_type).collect();
+            assert!(chunks.len() > 3);
+            assert!(chunks.iter().all(|c| c.length() <= 100));
+            let msg = decode(&png, chunk_type, &DecodeOptions::default()).unwrap();
+            assert_eq!(msg.as_ref(), Some(&binary));
+        }
+    }
+
+    #[test]
+    fn test_data_chunk_cannot_store() {
+        let chunk_type = ChunkType::from_str("tEXt").unwrap();
+        let res = data_chunk(&chunk_type, Some(TextKind::Text), "Comment", b"a\0b");
+        assert!(matches!(
+            res,
+            Err(crate::Error::TextChunk(TextChunkError::CannotStore))
+        ));
+    }
+
+    #[test]
+    fn test_text_chunk_keywords() {
+        let mut png = testing_png();
+        for keyword in ["Author", "Comment"] {
+            let options = EncodeOptions {
+                keyword: Some(keyword.to_string()),
+                ..Default::default()
+            };
+            encode(&mut png, "tEXt", keyword.as_bytes(), &options).unwrap();
+        }
+        let options = DecodeOptions {
+            keyword: Some("Author".to_string()),
+            ..Default::default()
+        };
+        let msg = decode_text(&png, "tEXt", &options).unwrap();
+        assert_eq!(msg.as_deref(), Some("Author"));
+        let msg = decode_text(&png, "tEXt", &DecodeOptions::default()).unwrap();
+        assert_eq!(msg.as_deref(), Some("Comment"));
+    }
+
+    #[test]
+    fn test_text_looking_like_base64() {
+        // decodes to 0x89 "PG", so it has to be stored in a container
+        let msg = BASE64.encode(b"\x89PGP");
+        let mut png = testing_png();
+        encode(&mut png, "tEXt", msg.as_bytes(), &EncodeOptions::default()).unwrap();
+        let msg_back = decode_text(&png, "tEXt", &DecodeOptions::default()).unwrap();
+        assert_eq!(msg_back, Some(msg));
+    }
+
+    #[test]
+    fn test_embed_extract() {
+        let mut png = dice();
+        let file = Payload::file("dice.txt", b"six sides".to_vec()).unwrap();
+        embed_payload(&mut png, &file, &encrypted(), &LsbOptions::default()).unwrap();
+
+        let extracted = extract_payload(&png, &passphrase("password"), &LsbOptions::default());
+        assert_eq!(extracted.unwrap(), Some(file));
+        assert!(matches!(
+            extract_payload(&png, &DecodeOptions::default(), &LsbOptions::default()),
+            Err(crate::Error::Crypto(CryptoError::PassphraseRequired))
+        ));
+    }
+
+    #[test]
+    fn test_overhead() {
+        let file = Payload::file("dice.txt", b"six sides".to_vec()).unwrap();
+        let mut png = testing_png();
+        encode_payload(&mut png, "TeSt", &file, &encrypted()).unwrap();
+        let hidden = png.chunk_by_type("TeSt").unwrap().length() as usize;
+        assert_eq!(hidden, file.data().len() + overhead(&file, &encrypted()));
+
+        let msg = Payload::message(b"Hello World!".to_vec());
+        assert_eq!(overhead(&msg, &EncodeOptions::default()), 0);
+    }
+
+    #[test]
+    fn test_decode_split_missing_piece() {
+        let mut png = testing_png();
+        let options = EncodeOptions {
+            max_chunk_size: Some(50),
+            ..Default::default()
+        };
+        encode(&mut png, "TeSt", &[7; 200], &options).unwrap();
+        png.remove_first_chunk("TeSt").unwrap();
+        assert!(matches!(
+            decode(&png, "TeSt", &DecodeOptions::default()),
+            Err(crate::Error::Sequence(sequence::SequenceError::Missing(m))) if m == [0]
+        ));
+    }
+
+    #[test]
+    fn test_decode_wrong_passphrase() {
+        let mut png = testing_png();
+        encode(&mut png, "TeSt", b"Hello World!", &encrypted()).unwrap();
+        assert!(matches!(
+            decode(&png, "TeSt", &passphrase("passw0rd")),
+            Err(crate::Error::Crypto(CryptoError::DecryptionFailed))
+        ));
+        assert!(matches!(
+            decode(&png, "TeSt", &DecodeOptions::default()),
+            Err(crate::Error::Crypto(CryptoError::PassphraseRequired))
+        ));
+    }
+
+    #[test]
+    fn test_decode_legacy_vigenere() {
+        let mut png = testing_png();
+        let chunk = Chunk::new(
+            ChunkType::from_str("TeSt").unwrap(),
+            "WeDDKn9rGlv´".as_bytes().to_vec(),
+        );
+        png.insert_chunk(chunk, Placement::default()).unwrap();
+        assert!(matches!(
+            decode_text(&png, "TeSt", &passphrase("password")),
+            Err(crate::Error::Crypto(CryptoError::NotEncrypted))
+        ));
+        let options = DecodeOptions {
+            legacy: true,
+            ..passphrase("password")
+        };
+        let msg = decode_text(&png, "TeSt", &options).unwrap();
+        assert_eq!(msg.as_deref(), Some("Hello World!"));
+    }
+
+    #[test]
+    fn test_decode_tampered_magic() {
+        let mut png = testing_png();
+        encode(&mut png, "TeSt", b"Hello World!", &encrypted()).unwrap();
+        let mut data = png.remove_first_chunk("TeSt").unwrap().data().to_vec();
+        data[3] ^= 1;
+        let chunk = Chunk::new(ChunkType::from_str("TeSt").unwrap(), data);
+        png.insert_chunk(chunk, Placement::default()).unwrap();
+        assert!(matches!(
+            decode(&png, "TeSt", &passphrase("password")),
+            Err(crate::Error::Crypto(CryptoError::BadEnvelope))
+        ));
+    }
+
+    #[test]
+    fn test_decode_missing_chunk() {
+        let png = testing_png();
+        assert!(
+            decode(&png, "TeSt", &DecodeOptions::default())
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut png = testing_png();
+        encode(&mut png, "TeSt", b"Hello World!", &EncodeOptions::default()).unwrap();
+        let removed = remove(&mut png, "TeSt", &DecodeOptions::default()).unwrap();
+        assert_eq!(removed[0].data_as_string().unwrap(), "Hello World!");
+        assert!(png.chunk_by_type("TeSt").is_none());
+    }
+
+    #[test]
+    fn test_decode_and_remove_agree() {
+        let mut png = testing_png();
+        let split = EncodeOptions {
+            max_chunk_size: Some(50),
+            ..Default::default()
+        };
+        encode(&mut png, "TeSt", b"first", &EncodeOptions::default()).unwrap();
+        encode(&mut png, "TeSt", &[7; 200], &split).unwrap();
+        encode(&mut png, "TeSt", b"last", &EncodeOptions::default()).unwrap();
+        let at = |index| DecodeOptions {
+            index: Some(index),
+            ..Default::default()
+        };
+
+        // the last message by default, any piece of a split one picks all of them
+        let default = DecodeOptions::default();
+        assert_eq!(decode(&png, "TeSt", &default).unwrap().unwrap(), b"last");
+        assert_eq!(decode(&png, "TeSt", &at(0)).unwrap().unwrap(), b"first");
+        assert_eq!(decode(&png, "TeSt", &at(2)).unwrap().unwrap(), [7; 200]);
+        assert!(decode(&png, "TeSt", &at(10)).is_err());
+
+        let removed = remove(&mut png, "TeSt", &default).unwrap();
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].data(), b"last");
+        let removed = remove(&mut png, "TeSt", &at(1)).unwrap();
+        assert!(removed.len() > 1);
+        assert_eq!(decode(&png, "TeSt", &default).unwrap().unwrap(), b"first");
+
+        encode(&mut png, "TeSt", b"again", &EncodeOptions::default()).unwrap();
+        assert_eq!(remove_all(&mut png, "TeSt", &default).unwrap().len(), 2);
+        assert!(decode(&png, "TeSt", &default).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_verify_at_index() {
+        let key = SigningKey::generate();
+        let signed = EncodeOptions {
+            signing_key: Some(key.clone()),
+            ..Default::default()
+        };
+        let mut png = testing_png();
+        encode(&mut png, "TeSt", b"first", &signed).unwrap();
+        encode(&mut png, "TeSt", b"second", &signed).unwrap();
+        let at = |index| DecodeOptions {
+            index: Some(index),
+            trusted_keys: vec![key.verifying_key()],
+            ..Default::default()
+        };
+
+        assert_eq!(decode(&png, "TeSt", &at(0)).unwrap().unwrap(), b"first");
+        assert_eq!(decode(&png, "TeSt", &at(1)).unwrap().unwrap(), b"second");
+        assert_eq!(verify(&png, "TeSt", &at(0)).unwrap(), key.verifying_key());
+
+        // a chunk that only looks like a signature doesn't hide the real ones
+        let junk = Chunk::new(
+            ChunkType::from_str(signature::CHUNK_TYPE).unwrap(),
+            vec![1; 9],
+        );
+        png.insert_chunk(junk, Placement::default()).unwrap();
+        assert_eq!(decode(&png, "TeSt", &at(0)).unwrap().unwrap(), b"first");
+        assert_eq!(verify(&png, "TeSt", &at(1)).unwrap(), key.verifying_key());
+    }
+}
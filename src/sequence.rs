@@ -0,0 +1,303 @@
+//! Splitting hidden data over multiple chunks and putting it back together.
+//!
+//! Every piece is prefixed with a sequence header:
+//!
+//! ```text
+//! magic     4 bytes   0x89 "PGS"
+//! version   1 byte    currently 1
+//! id        4 bytes   random id shared by all pieces of the same data
+//! index     4 bytes   position of this piece, starting at 0
+//! count     4 bytes   number of pieces
+//! length    8 bytes   length of the whole data
+//! piece
+//! ```
+//!
+//! all numbers are big endian
+
+use std::{collections::BTreeMap, error::Error, fmt::Display};
+
+use chacha20poly1305::aead::{OsRng, rand_core::RngCore};
+
+use crate::Result;
+
+/// first bytes of every piece
+pub const MAGIC: [u8; 4] = [0x89, b'P', b'G', b'S'];
+const VERSION: u8 = 1;
+/// length of the sequence header in front of every piece
+pub const HEADER_LEN: usize = 25;
+/// most missing pieces listed by index, the count in the header can't be trusted
+const MAX_LISTED_MISSING: usize = 32;
+
+/// true if data is a piece created by [`split`]
+pub fn is_piece(data: &[u8]) -> bool {
+    data.starts_with(&MAGIC)
+}
+
+/// split data into pieces (header included) of at most max_len bytes
+pub fn split(data: &[u8], max_len: usize) -> Result<Vec<Vec<u8>>> {
+    if max_len <= HEADER_LEN {
+        return Err(SequenceError::MaxLengthTooSmall(max_len).into());
+    }
+    let piece_len = max_len - HEADER_LEN;
+    let count = data.len().div_ceil(piece_len).max(1);
+    let count = <u32>::try_from(count).map_err(|_| SequenceError::TooManyPieces)?;
+    let id = OsRng.next_u32();
+
+    let pieces = (0..count)
+        .map(|index| {
+            let start = index as usize * piece_len;
+            let piece = &data[start..(start + piece_len).min(data.len())];
+
+            let mut bytes = MAGIC.to_vec();
+            bytes.push(VERSION);
+            bytes.extend_from_slice(&id.to_be_bytes());
+            bytes.extend_from_slice(&index.to_be_bytes());
+            bytes.extend_from_slice(&count.to_be_bytes());
+            bytes.extend_from_slice(&(data.len() as u64).to_be_bytes());
+            bytes.extend_from_slice(piece);
+            bytes
+        })
+        .collect();
+    Ok(pieces)
+}
+
+struct Piece<'a> {
+    id: u32,
+    index: u32,
+    count: u32,
+    length: u64,
+    data: &'a [u8],
+}
+
+impl<'a> TryFrom<&'a [u8]> for Piece<'a> {
+    type Error = crate::Error;
+
+    fn try_from(bytes: &'a [u8]) -> Result<Self> {
+        if !is_piece(bytes) || bytes.len() < HEADER_LEN {
+            return Err(SequenceError::BadHeader.into());
+        }
+        if bytes[4] != VERSION {
+            return Err(SequenceError::UnsupportedVersion(bytes[4]).into());
+        }
+        let u32_at = |i: usize| <u32>::from_be_bytes(bytes[i..i + 4].try_into().unwrap());
+        Ok(Piece {
+            id: u32_at(5),
+            index: u32_at(9),
+            count: u32_at(13),
+            length: <u64>::from_be_bytes(bytes[17..25].try_into().unwrap()),
+            data: &bytes[HEADER_LEN..],
+        })
+    }
+}
+
+/// put the pieces created by [`split`] back together, in whatever order they are given
+///
+/// all pieces have to belong to the same data, missing or duplicate pieces are errors
+pub fn join<'a>(pieces: impl IntoIterator<Item = &'a [u8]>) -> Result<Vec<u8>> {
+    let mut sorted: BTreeMap<u32, Piece> = BTreeMap::new();
+    let mut first: Option<(u32, u32, u64)> = None;
+
+    for bytes in pieces {
+        let piece = Piece::try_from(bytes)?;
+        let (id, count, length) = *first.get_or_insert((piece.id, piece.count, piece.length));
+        if (piece.id, piece.count, piece.length) != (id, count, length) {
+            return Err(SequenceError::Mismatch.into());
+        }
+        if piece.index >= count {
+            return Err(SequenceError::IndexOutOfRange {
+                index: piece.index,
+                count,
+            }
+            .into());
+        }
+        let index = piece.index;
+        if sorted.insert(index, piece).is_some() {
+            return Err(SequenceError::Duplicate(index).into());
+        }
+    }
+
+    let Some((_, count, length)) = first else {
+        return Err(SequenceError::Missing(vec![0]).into());
+    };
+    // a forged count could ask for billions of missing pieces
+    if count as usize - sorted.len() > MAX_LISTED_MISSING {
+        return Err(SequenceError::TooFewPieces {
+            found: sorted.len(),
+            count,
+        }
+        .into());
+    }
+    let missing: Vec<u32> = (0..count).filter(|i| !sorted.contains_key(i)).collect();
+    if !missing.is_empty() {
+        return Err(SequenceError::Missing(missing).into());
+    }
+
+    let data: Vec<u8> = sorted.values().flat_map(|p| p.data).copied().collect();
+    if data.len() as u64 != length {
+        return Err(SequenceError::LengthMismatch {
+            expected: length,
+            actual: data.len() as u64,
+        }
+        .into());
+    }
+    Ok(data)
+}
+
+/// id shared by all pieces of the same data
+pub fn piece_id(bytes: &[u8]) -> Result<u32> {
+    Ok(Piece::try_from(bytes)?.id)
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum SequenceError {
+    // header of a piece is too short or doesn't start with MAGIC
+    BadHeader,
+    // piece was created by a newer version of PNGecret
+    UnsupportedVersion(u8),
+    // pieces have different ids, counts or lengths
+    Mismatch,
+    IndexOutOfRange { index: u32, count: u32 },
+    // same piece is there twice
+    Duplicate(u32),
+    // these pieces are missing
+    Missing(Vec<u32>),
+    // too many pieces are missing to list them
+    TooFewPieces { found: usize, count: u32 },
+    // joined pieces don't have the length from the header
+    LengthMismatch { expected: u64, actual: u64 },
+    // maximum piece length doesn't leave room for any data
+    MaxLengthTooSmall(usize),
+    TooManyPieces,
+}
+
+impl Display for SequenceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SequenceError::BadHeader => write!(f, "Piece of the hidden data is malformed"),
+            SequenceError::UnsupportedVersion(v) => {
+                write!(f, "Piece of the hidden data has unsupported version {v}")
+            }
+            SequenceError::Mismatch => {
+                write!(f, "Pieces of the hidden data belong to different payloads")
+            }
+            SequenceError::IndexOutOfRange { index, count } => {
+                write!(
+                    f,
+                    "Piece {index} is out of range, there are only {count} pieces"
+                )
+            }
+            SequenceError::Duplicate(i) => write!(f, "Piece {i} of the hidden data is duplicated"),
+            SequenceError::Missing(missing) => {
+                let missing: Vec<String> = missing.iter().map(|i| i.to_string()).collect();
+                write!(
+                    f,
+                    "Pieces {} of the hidden data are missing",
+                    missing.join(", ")
+                )
+            }
+            SequenceError::TooFewPieces { found, count } => write!(
+                f,
+                "Only {found} of {count} pieces of the hidden data were found"
+            ),
+            SequenceError::LengthMismatch { expected, actual } => write!(
+                f,
+                "Hidden data should be {expected} bytes long, but the pieces add up to {actual} bytes"
+            ),
+            SequenceError::MaxLengthTooSmall(l) => write!(
+                f,
+                "Maximum chunk size {l} is too small, it has to be bigger than {HEADER_LEN}"
+            ),
+            SequenceError::TooManyPieces => write!(f, "Hidden data needs too many chunks"),
+        }
+    }
+}
+
+impl Error for SequenceError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_data() -> Vec<u8> {
+        (0..1000).map(|i| (i % 256) as u8).collect()
+    }
+
+    #[test]
+    fn test_split_join() {
+        let data = testing_data();
+        let pieces = split(&data, 125).unwrap();
+        assert_eq!(pieces.len(), 10);
+        assert!(pieces.iter().all(|p| p.len() <= 125 && is_piece(p)));
+        assert_eq!(join(pieces.iter().map(Vec::as_slice)).unwrap(), data);
+    }
+
+    #[test]
+    fn test_join_any_order() {
+        let data = testing_data();
+        let pieces = split(&data, 300).unwrap();
+        assert_eq!(join(pieces.iter().rev().map(Vec::as_slice)).unwrap(), data);
+    }
+
+    #[test]
+    fn test_split_empty() {
+        let pieces = split(&[], 100).unwrap();
+        assert_eq!(pieces.len(), 1);
+        assert_eq!(join(pieces.iter().map(Vec::as_slice)).unwrap(), b"");
+    }
+
+    #[test]
+    fn test_missing_piece() {
+        let pieces = split(&testing_data(), 125).unwrap();
+        let res = join(
+            pieces
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| *i != 3 && *i != 7)
+                .map(|(_, p)| p.as_slice()),
+        );
+        assert!(matches!(
+            res,
+            Err(crate::Error::Sequence(SequenceError::Missing(m))) if m == [3, 7]
+        ));
+    }
+
+    #[test]
+    fn test_forged_count() {
+        let mut piece = split(b"Hello World!", 100).unwrap().remove(0);
+        piece[13..17].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(matches!(
+            join([piece.as_slice()]),
+            Err(crate::Error::Sequence(SequenceError::TooFewPieces {
+                found: 1,
+                count: u32::MAX
+            }))
+        ));
+    }
+
+    #[test]
+    fn test_duplicate_piece() {
+        let pieces = split(&testing_data(), 125).unwrap();
+        let res = join(pieces.iter().chain([&pieces[4]]).map(Vec::as_slice));
+        assert!(matches!(
+            res,
+            Err(crate::Error::Sequence(SequenceError::Duplicate(4)))
+        ));
+    }
+
+    #[test]
+    fn test_pieces_of_different_data() {
+        let a = split(&testing_data(), 125).unwrap();
+        let b = split(&testing_data(), 125).unwrap();
+        let res = join([a[0].as_slice(), b[1].as_slice()]);
+        assert!(matches!(
+            res,
+            Err(crate::Error::Sequence(SequenceError::Mismatch))
+        ));
+    }
+
+    #[test]
+    fn test_max_length_too_small() {
+        assert!(split(&testing_data(), HEADER_LEN).is_err());
+    }
+}
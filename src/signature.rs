@@ -0,0 +1,495 @@
+//! Ed25519 signatures over hidden data, so the receiver can tell who hid it.
+//!
+//! A signature is stored in its own `pgSG` chunk next to the data it signs. It holds
+//! SHA-256 digests of everything it covers, the public key of the signer and the
+//! signature over all of that:
+//!
+//! ```text
+//! magic       4 bytes   0x89 "PGN"
+//! version     1 byte    currently 1
+//! target      1 byte length + chunk type the data is hidden in, followed by a NUL and
+//!             the keyword for text chunks (empty if the image itself is signed,
+//!             see [`crate::provenance`])
+//! digests     1 byte count + (1 byte kind, 32 byte SHA-256) for each of them,
+//!             1 = hidden data as stored, 2 = critical chunks, 3 = pixels
+//! public key 32 bytes
+//! signature  64 bytes   over everything in front of it
+//! ```
+//!
+//! Since every digest is signed on its own, verifying tells which of them changed.
+//! Keys are written as text like the keys in [`crate::crypt::x25519`]:
+//!
+//! ```text
+//! pngecret-sign-public:Vq0D8u...
+//! pngecret-sign-secret:8kR2aL...
+//! ```
+
+use std::{error::Error, fmt::Display, str::FromStr};
+
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use chacha20poly1305::aead::OsRng;
+use ed25519_dalek::Signer;
+use sha2::{Digest as _, Sha256};
+
+use crate::Result;
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::ihdr::ColorType;
+use crate::pixels::Pixels;
+use crate::png::Png;
+
+/// type of the chunks signatures are stored in
+pub const CHUNK_TYPE: &str = "pgSG";
+/// first bytes of every signature chunk
+pub const MAGIC: [u8; 4] = [0x89, b'P', b'G', b'N'];
+const VERSION: u8 = 1;
+const PUBLIC_PREFIX: &str = "pngecret-sign-public:";
+const SECRET_PREFIX: &str = "pngecret-sign-secret:";
+
+/// secret key hidden data is signed with
+#[derive(Clone)]
+pub struct SigningKey(ed25519_dalek::SigningKey);
+
+/// public key signatures are checked against
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VerifyingKey(ed25519_dalek::VerifyingKey);
+
+impl SigningKey {
+    /// new random key pair
+    pub fn generate() -> SigningKey {
+        SigningKey(ed25519_dalek::SigningKey::generate(&mut OsRng))
+    }
+
+    /// public half of the key pair
+    pub fn verifying_key(&self) -> VerifyingKey {
+        VerifyingKey(self.0.verifying_key())
+    }
+
+    /// the first signing key in a key file
+    ///
+    /// empty lines and comments starting with # are skipped
+    pub fn parse_file(content: &str) -> Result<SigningKey> {
+        content
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty() && !line.starts_with('#'))
+            .ok_or(SignatureError::BadKey)?
+            .parse()
+    }
+}
+
+/// what a digest is taken of
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Digest {
+    /// the hidden data as it is stored, before it is decrypted
+    HiddenData,
+    /// every critical chunk (length, type and data) in order
+    CriticalChunks,
+    /// the image header, the decoded pixels (whatever the compression and filters) and
+    /// the palette and transparency they are shown with
+    Pixels,
+}
+
+impl Digest {
+    fn id(&self) -> u8 {
+        match self {
+            Digest::HiddenData => 1,
+            Digest::CriticalChunks => 2,
+            Digest::Pixels => 3,
+        }
+    }
+
+    fn from_id(id: u8) -> Result<Digest> {
+        match id {
+            1 => Ok(Digest::HiddenData),
+            2 => Ok(Digest::CriticalChunks),
+            3 => Ok(Digest::Pixels),
+            _ => Err(SignatureError::UnknownDigest(id).into()),
+        }
+    }
+}
+
+impl Display for Digest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Digest::HiddenData => write!(f, "hidden data"),
+            Digest::CriticalChunks => write!(f, "critical chunks"),
+            Digest::Pixels => write!(f, "pixels"),
+        }
+    }
+}
+
+/// SHA-256 of data
+pub fn digest(data: &[u8]) -> [u8; 32] {
+    Sha256::digest(data).into()
+}
+
+/// SHA-256 of every critical chunk of png, see [`Digest::CriticalChunks`]
+pub fn critical_chunks_digest(png: &Png) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    for chunk in png.chunks().iter().filter(|c| c.chunk_type().is_critical()) {
+        hasher.update(chunk.length().to_be_bytes());
+        hasher.update(chunk.chunk_type().bytes());
+        hasher.update(chunk.data());
+    }
+    hasher.finalize().into()
+}
+
+/// SHA-256 of the image header, the unfiltered image data and the chunks that decide
+/// which colors it shows (PLTE of indexed images and tRNS), see [`Digest::Pixels`]
+///
+/// the same for every way of compressing and filtering the same pixels
+pub fn pixels_digest(png: &Png) -> Result<[u8; 32]> {
+    let pixels = Pixels::from_png(png)?;
+    let mut hasher = Sha256::new();
+    hasher.update(pixels.ihdr().to_chunk().data());
+    hasher.update(pixels.data());
+
+    // indexed pixels are only palette indices, and any image can have a transparent color
+    let indexed = pixels.ihdr().color_type() == ColorType::Indexed;
+    let colors = png.chunks().iter().filter(|c| {
+        let chunk_type = &c.chunk_type().bytes();
+        chunk_type == b"tRNS" || (indexed && chunk_type == b"PLTE")
+    });
+    for chunk in colors {
+        hasher.update(chunk.length().to_be_bytes());
+        hasher.update(chunk.chunk_type().bytes());
+        hasher.update(chunk.data());
+    }
+    Ok(hasher.finalize().into())
+}
+
+/// signed digests, as stored in a signature chunk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    target: String,
+    digests: Vec<(Digest, [u8; 32])>,
+    signer: VerifyingKey,
+    signature: ed25519_dalek::Signature,
+}
+
+impl Signature {
+    /// sign digests of the data hidden at target
+    ///
+    /// target is the chunk type, followed by a NUL and the keyword for text chunks
+    pub fn sign(
+        key: &SigningKey,
+        target: &str,
+        digests: Vec<(Digest, [u8; 32])>,
+    ) -> Result<Signature> {
+        // both are stored with a single length byte
+        if target.len() > u8::MAX as usize || digests.len() > u8::MAX as usize {
+            return Err(SignatureError::Malformed.into());
+        }
+        let mut signature = Signature {
+            target: target.to_string(),
+            digests,
+            signer: key.verifying_key(),
+            signature: ed25519_dalek::Signature::from_bytes(&[0; 64]),
+        };
+        signature.signature = key.0.sign(&signature.signed_bytes());
+        Ok(signature)
+    }
+
+    /// where the signed data is hidden
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+
+    pub fn digests(&self) -> &[(Digest, [u8; 32])] {
+        &self.digests
+    }
+
+    /// public key of whoever signed
+    pub fn signer(&self) -> VerifyingKey {
+        self.signer
+    }
+
+    /// check the signature and compare every signed digest with the one actual returns
+    ///
+    /// trusted keys are the only ones accepted, there has to be at least one: anyone can
+    /// replace a signature with their own
+    pub fn verify(
+        &self,
+        trusted: &[VerifyingKey],
+        actual: impl Fn(Digest) -> Result<[u8; 32]>,
+    ) -> Result<()> {
+        let changed = self.changed(trusted, actual)?;
+        if !changed.is_empty() {
+            return Err(SignatureError::Changed(changed).into());
+        }
+        Ok(())
+    }
+
+    /// like [`Signature::verify`], but changed digests are returned instead of failing
+    pub fn changed(
+        &self,
+        trusted: &[VerifyingKey],
+        actual: impl Fn(Digest) -> Result<[u8; 32]>,
+    ) -> Result<Vec<Digest>> {
+        self.signer
+            .0
+            .verify_strict(&self.signed_bytes(), &self.signature)
+            .map_err(|_| SignatureError::Invalid)?;
+        if trusted.is_empty() {
+            return Err(SignatureError::NoTrustedKeys.into());
+        }
+        if !trusted.contains(&self.signer) {
+            return Err(SignatureError::UntrustedKey(self.signer.to_string()).into());
+        }
+
+        let mut changed = Vec::new();
+        for (kind, digest) in &self.digests {
+            if actual(*kind)? != *digest {
+                changed.push(*kind);
+            }
+        }
+        Ok(changed)
+    }
+
+    /// everything in front of the signature
+    fn signed_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        bytes.push(self.target.len() as u8);
+        bytes.extend_from_slice(self.target.as_bytes());
+        bytes.push(self.digests.len() as u8);
+        for (kind, digest) in &self.digests {
+            bytes.push(kind.id());
+            bytes.extend_from_slice(digest);
+        }
+        bytes.extend_from_slice(self.signer.0.as_bytes());
+        bytes
+    }
+
+    pub fn to_chunk(&self) -> Chunk {
+        let mut data = self.signed_bytes();
+        data.extend_from_slice(&self.signature.to_bytes());
+        Chunk::new(ChunkType::from_str(CHUNK_TYPE).unwrap(), data)
+    }
+
+    /// the last signature in png for data hidden at target, None if there is none
+    pub fn find(png: &Png, target: &str) -> Option<Signature> {
+        Signature::find_all(png, target).into_iter().next()
+    }
+
+    /// every signature in png for data hidden at target, the last one first
+    ///
+    /// there is one for every signed message if several are hidden at the same target.
+    /// Chunks that aren't valid signatures are skipped, they can't block the others
+    pub fn find_all(png: &Png, target: &str) -> Vec<Signature> {
+        png.chunks()
+            .iter()
+            .rev()
+            .filter(|chunk| chunk.chunk_type().to_string() == CHUNK_TYPE)
+            .filter_map(|chunk| Signature::try_from(chunk).ok())
+            .filter(|signature| signature.target == target)
+            .collect()
+    }
+}
+
+impl TryFrom<&Chunk> for Signature {
+    type Error = crate::Error;
+
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        let data = chunk.data();
+        if !data.starts_with(&MAGIC) {
+            return Err(SignatureError::Malformed.into());
+        }
+        let mut rest = &data[MAGIC.len()..];
+        let mut take = |len: usize| -> Result<&[u8]> {
+            let (taken, tail) = rest
+                .split_at_checked(len)
+                .ok_or(SignatureError::Malformed)?;
+            rest = tail;
+            Ok(taken)
+        };
+
+        let version = take(1)?[0];
+        if version != VERSION {
+            return Err(SignatureError::UnsupportedVersion(version).into());
+        }
+        let target_len = take(1)?[0] as usize;
+        let target =
+            String::from_utf8(take(target_len)?.to_vec()).map_err(|_| SignatureError::Malformed)?;
+        let count = take(1)?[0];
+        let mut digests = Vec::new();
+        for _ in 0..count {
+            let kind = Digest::from_id(take(1)?[0])?;
+            digests.push((kind, take(32)?.try_into().unwrap()));
+        }
+        let signer = ed25519_dalek::VerifyingKey::from_bytes(take(32)?.try_into().unwrap())
+            .map_err(|_| SignatureError::BadKey)?;
+        let signature = ed25519_dalek::Signature::from_bytes(take(64)?.try_into().unwrap());
+        if !rest.is_empty() {
+            return Err(SignatureError::Malformed.into());
+        }
+
+        Ok(Signature {
+            target,
+            digests,
+            signer: VerifyingKey(signer),
+            signature,
+        })
+    }
+}
+
+/// 32 key bytes after prefix in s
+fn parse_key(s: &str, prefix: &str) -> Result<[u8; 32]> {
+    s.trim()
+        .strip_prefix(prefix)
+        .and_then(|key| BASE64.decode(key).ok())
+        .and_then(|key| key.try_into().ok())
+        .ok_or(SignatureError::BadKey.into())
+}
+
+impl FromStr for VerifyingKey {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let key = ed25519_dalek::VerifyingKey::from_bytes(&parse_key(s, PUBLIC_PREFIX)?)
+            .map_err(|_| SignatureError::BadKey)?;
+        Ok(VerifyingKey(key))
+    }
+}
+
+impl FromStr for SigningKey {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let key = ed25519_dalek::SigningKey::from_bytes(&parse_key(s, SECRET_PREFIX)?);
+        Ok(SigningKey(key))
+    }
+}
+
+impl Display for VerifyingKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{PUBLIC_PREFIX}{}", BASE64.encode(self.0.as_bytes()))
+    }
+}
+
+impl Display for SigningKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{SECRET_PREFIX}{}", BASE64.encode(self.0.as_bytes()))
+    }
+}
+
+impl std::fmt::Debug for SigningKey {
+    /// only the public key, secrets don't belong in logs
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("SigningKey")
+            .field(&self.verifying_key())
+            .finish()
+    }
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum SignatureError {
+    // key text is malformed, or the public key isn't a valid point
+    BadKey,
+    // signature chunk is too short, too long or has a bad magic, or there is too much to sign
+    Malformed,
+    UnsupportedVersion(u8),
+    UnknownDigest(u8),
+    // hidden data should be signed, but isn't
+    Missing,
+    // signature doesn't match the signed digests
+    Invalid,
+    // signature is valid, but from a key that isn't trusted
+    UntrustedKey(String),
+    // signatures can only be checked against at least one trusted key
+    NoTrustedKeys,
+    // signature is valid, but these parts of the image changed since
+    Changed(Vec<Digest>),
+}
+
+impl Display for SignatureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SignatureError::BadKey => write!(f, "Signing key is malformed or unusable"),
+            SignatureError::Malformed => write!(f, "Signature is malformed"),
+            SignatureError::UnsupportedVersion(v) => {
+                write!(f, "Signature has unsupported version {v}")
+            }
+            SignatureError::UnknownDigest(d) => write!(f, "Unknown digest kind {d} in signature"),
+            SignatureError::Missing => write!(f, "The hidden data is not signed"),
+            SignatureError::Invalid => write!(f, "Signature is invalid, it was tampered with"),
+            SignatureError::UntrustedKey(key) => {
+                write!(f, "Signature is valid, but made by the untrusted key {key}")
+            }
+            SignatureError::NoTrustedKeys => {
+                write!(f, "No trusted keys given to check the signature with")
+            }
+            SignatureError::Changed(changed) => {
+                let changed: Vec<String> = changed.iter().map(|d| d.to_string()).collect();
+                write!(
+                    f,
+                    "Signature is valid, but the {} changed since signing",
+                    changed.join(" and ")
+                )
+            }
+        }
+    }
+}
+
+impl Error for SignatureError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign_verify() {
+        let key = SigningKey::generate();
+        let digests = vec![(Digest::HiddenData, digest(b"Hello World!"))];
+        let signature = Signature::sign(&key, "teST", digests).unwrap();
+        let parsed = Signature::try_from(&signature.to_chunk()).unwrap();
+        assert_eq!(parsed, signature);
+        assert_eq!(parsed.target(), "teST");
+
+        let actual = |_| Ok(digest(b"Hello World!"));
+        let trusted = [key.verifying_key()];
+        parsed.verify(&trusted, actual).unwrap();
+        // anyone could have signed, so some key has to be trusted
+        assert!(matches!(
+            parsed.verify(&[], actual),
+            Err(crate::Error::Signature(SignatureError::NoTrustedKeys))
+        ));
+        assert!(matches!(
+            parsed.verify(&[SigningKey::generate().verifying_key()], actual),
+            Err(crate::Error::Signature(SignatureError::UntrustedKey(_)))
+        ));
+        assert!(matches!(
+            parsed.verify(&trusted, |_| Ok(digest(b"Hello World?"))),
+            Err(crate::Error::Signature(SignatureError::Changed(d))) if d == [Digest::HiddenData]
+        ));
+    }
+
+    #[test]
+    fn test_tampered_signature() {
+        let key = SigningKey::generate();
+        let signature = Signature::sign(&key, "teST", vec![(Digest::HiddenData, [1; 32])]).unwrap();
+        let chunk = signature.to_chunk();
+        // any flipped bit is either caught while parsing or by the signature
+        for i in 0..chunk.data().len() {
+            let mut data = chunk.data().to_vec();
+            data[i] ^= 1;
+            let tampered = Chunk::new(chunk.chunk_type().clone(), data);
+            let res = Signature::try_from(&tampered)
+                .and_then(|s| s.verify(&[key.verifying_key()], |_| Ok([1; 32])));
+            assert!(res.is_err(), "byte {i}");
+        }
+    }
+
+    #[test]
+    fn test_key_text() {
+        let key = SigningKey::generate();
+        let public = key.verifying_key();
+        assert_eq!(public.to_string().parse::<VerifyingKey>().unwrap(), public);
+        let file = format!("# public key: {public}\n{key}\n");
+        let parsed = SigningKey::parse_file(&file).unwrap();
+        assert_eq!(parsed.verifying_key(), public);
+        assert!(key.to_string().parse::<VerifyingKey>().is_err());
+    }
+}
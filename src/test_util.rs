@@ -0,0 +1,15 @@
+//! Fixtures shared by the unit tests.
+
+use crate::png::Png;
+
+/// raw bytes of the shrunken `dice.png` image on Wikipedia (50x50 RGBA)
+pub(crate) const DICE: &[u8] = include_bytes!("../tests/data/dice.png");
+
+pub(crate) fn dice() -> Png {
+    Png::try_from(DICE).unwrap()
+}
+
+/// dice reduced to a palette of 120 colors with transparency (50x50 indexed)
+pub(crate) fn dice_indexed() -> Png {
+    Png::try_from(&include_bytes!("../tests/data/dice-indexed.png")[..]).unwrap()
+}
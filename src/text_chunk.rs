@@ -0,0 +1,433 @@
+//! The standard keyword/value text chunks tEXt, zTXt and iTXt (section 4.2.3 of the PNG spec).
+//!
+//! tEXt and zTXt contain Latin-1 text, zTXt always compressed. iTXt contains UTF-8 text,
+//! optionally compressed, plus a language tag and a translation of the keyword.
+
+use std::{
+    error::Error,
+    fmt::Display,
+    io::{Read, Write},
+    str::FromStr,
+};
+
+use flate2::{Compression, read::ZlibDecoder, write::ZlibEncoder};
+
+use crate::Result;
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::payload::MAX_DECOMPRESSED_SIZE;
+
+/// which of the three text chunks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextKind {
+    /// tEXt: uncompressed Latin-1 text
+    Text,
+    /// zTXt: compressed Latin-1 text
+    CompressedText,
+    /// iTXt: UTF-8 text, optionally compressed
+    InternationalText,
+}
+
+impl TextKind {
+    pub fn chunk_type(&self) -> &'static str {
+        match self {
+            TextKind::Text => "tEXt",
+            TextKind::CompressedText => "zTXt",
+            TextKind::InternationalText => "iTXt",
+        }
+    }
+
+    /// kind of text chunk with this type, None if it isn't a text chunk
+    pub fn from_chunk_type(chunk_type: &str) -> Option<TextKind> {
+        match chunk_type {
+            "tEXt" => Some(TextKind::Text),
+            "zTXt" => Some(TextKind::CompressedText),
+            "iTXt" => Some(TextKind::InternationalText),
+            _ => None,
+        }
+    }
+
+    /// true if the text of this kind has to be Latin-1
+    pub fn is_latin1(&self) -> bool {
+        *self != TextKind::InternationalText
+    }
+}
+
+/// a parsed tEXt, zTXt or iTXt chunk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextChunk {
+    kind: TextKind,
+    keyword: String,
+    // language tag and translated keyword are only used by iTXt
+    language: String,
+    translated_keyword: String,
+    compressed: bool,
+    text: String,
+}
+
+impl TextChunk {
+    /// uncompressed tEXt chunk, text has to be Latin-1
+    pub fn plain(keyword: &str, text: &str) -> Result<TextChunk> {
+        TextChunk::new(TextKind::Text, keyword, "", "", false, text)
+    }
+
+    /// compressed zTXt chunk, text has to be Latin-1
+    pub fn compressed(keyword: &str, text: &str) -> Result<TextChunk> {
+        TextChunk::new(TextKind::CompressedText, keyword, "", "", true, text)
+    }
+
+    /// iTXt chunk with UTF-8 text
+    pub fn international(
+        keyword: &str,
+        language: &str,
+        translated_keyword: &str,
+        compressed: bool,
+        text: &str,
+    ) -> Result<TextChunk> {
+        TextChunk::new(
+            TextKind::InternationalText,
+            keyword,
+            language,
+            translated_keyword,
+            compressed,
+            text,
+        )
+    }
+
+    fn new(
+        kind: TextKind,
+        keyword: &str,
+        language: &str,
+        translated_keyword: &str,
+        compressed: bool,
+        text: &str,
+    ) -> Result<TextChunk> {
+        check_keyword(keyword)?;
+        if kind.is_latin1() {
+            to_latin1(text)?;
+        }
+        if !language
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+        {
+            return Err(TextChunkError::BadLanguageTag(language.to_string()).into());
+        }
+        Ok(TextChunk {
+            kind,
+            keyword: keyword.to_string(),
+            language: language.to_string(),
+            translated_keyword: translated_keyword.to_string(),
+            compressed,
+            text: text.to_string(),
+        })
+    }
+
+    pub fn kind(&self) -> TextKind {
+        self.kind
+    }
+
+    pub fn keyword(&self) -> &str {
+        &self.keyword
+    }
+
+    pub fn language(&self) -> &str {
+        &self.language
+    }
+
+    pub fn translated_keyword(&self) -> &str {
+        &self.translated_keyword
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        self.compressed
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// build the chunk with the encoded data
+    pub fn to_chunk(&self) -> Result<Chunk> {
+        // keyword and text were checked when this was created
+        let mut data = to_latin1(&self.keyword)?;
+        data.push(0);
+        match self.kind {
+            TextKind::Text => data.extend(to_latin1(&self.text)?),
+            TextKind::CompressedText => {
+                // compression method 0 is zlib, the only one there is
+                data.push(0);
+                data.extend(compress(&to_latin1(&self.text)?));
+            }
+            TextKind::InternationalText => {
+                data.extend_from_slice(&[self.compressed as u8, 0]);
+                data.extend_from_slice(self.language.as_bytes());
+                data.push(0);
+                data.extend_from_slice(self.translated_keyword.as_bytes());
+                data.push(0);
+                if self.compressed {
+                    data.extend(compress(self.text.as_bytes()));
+                } else {
+                    data.extend_from_slice(self.text.as_bytes());
+                }
+            }
+        }
+        Chunk::try_new(ChunkType::from_str(self.kind.chunk_type())?, data)
+    }
+}
+
+impl TryFrom<&Chunk> for TextChunk {
+    type Error = crate::Error;
+
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        let kind = TextKind::from_chunk_type(&chunk.chunk_type().to_string())
+            .ok_or(TextChunkError::NotATextChunk)?;
+        let (keyword, rest) = split_at_null(chunk.data())?;
+        let keyword = from_latin1(keyword);
+        check_keyword(&keyword)?;
+
+        let (language, translated_keyword, compressed, text) = match kind {
+            TextKind::Text => (String::new(), String::new(), false, from_latin1(rest)),
+            TextKind::CompressedText => {
+                let (&method, text) = rest.split_first().ok_or(TextChunkError::Truncated)?;
+                check_compression_method(method)?;
+                (
+                    String::new(),
+                    String::new(),
+                    true,
+                    from_latin1(&decompress(text)?),
+                )
+            }
+            TextKind::InternationalText => {
+                let [flag, method, rest @ ..] = rest else {
+                    return Err(TextChunkError::Truncated.into());
+                };
+                let compressed = *flag != 0;
+                if compressed {
+                    check_compression_method(*method)?;
+                }
+                let (language, rest) = split_at_null(rest)?;
+                let (translated_keyword, text) = split_at_null(rest)?;
+                let text = if compressed {
+                    decompress(text)?
+                } else {
+                    text.to_vec()
+                };
+                (
+                    from_utf8(language)?,
+                    from_utf8(translated_keyword)?,
+                    compressed,
+                    from_utf8(&text)?,
+                )
+            }
+        };
+
+        Ok(TextChunk {
+            kind,
+            keyword,
+            language,
+            translated_keyword,
+            compressed,
+            text,
+        })
+    }
+}
+
+impl Display for TextChunk {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.keyword)?;
+        if !self.language.is_empty() || !self.translated_keyword.is_empty() {
+            write!(f, " ({}, {})", self.language, self.translated_keyword)?;
+        }
+        write!(f, ": {}", self.text)
+    }
+}
+
+// keywords are 1-79 printable Latin-1 characters, see section 11.3.4.2 of the PNG spec
+fn check_keyword(keyword: &str) -> Result<()> {
+    let valid_chars = keyword
+        .chars()
+        .all(|c| matches!(c, ' '..='~' | '\u{a1}'..='\u{ff}'));
+    if (1..=79).contains(&keyword.chars().count()) && valid_chars {
+        Ok(())
+    } else {
+        Err(TextChunkError::BadKeyword(keyword.to_string()).into())
+    }
+}
+
+fn check_compression_method(method: u8) -> Result<()> {
+    match method {
+        0 => Ok(()),
+        _ => Err(TextChunkError::UnknownCompressionMethod(method).into()),
+    }
+}
+
+/// encode text as Latin-1, which fails for characters above U+00FF and null characters
+pub fn to_latin1(text: &str) -> Result<Vec<u8>> {
+    text.chars()
+        .map(|c| match u8::try_from(c) {
+            Ok(b) if b != 0 => Ok(b),
+            _ => Err(TextChunkError::NotLatin1(c).into()),
+        })
+        .collect()
+}
+
+/// decode Latin-1 bytes, every byte is the code point of its character
+pub fn from_latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| *b as char).collect()
+}
+
+fn from_utf8(bytes: &[u8]) -> Result<String> {
+    Ok(String::from_utf8(bytes.to_vec())?)
+}
+
+fn split_at_null(bytes: &[u8]) -> Result<(&[u8], &[u8])> {
+    let idx = bytes
+        .iter()
+        .position(|b| *b == 0)
+        .ok_or(TextChunkError::Truncated)?;
+    Ok((&bytes[..idx], &bytes[idx + 1..]))
+}
+
+fn compress(bytes: &[u8]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    // writing into a vec can't fail
+    encoder.write_all(bytes).unwrap();
+    encoder.finish().unwrap()
+}
+
+fn decompress(bytes: &[u8]) -> Result<Vec<u8>> {
+    let mut text = Vec::new();
+    ZlibDecoder::new(bytes)
+        .take(MAX_DECOMPRESSED_SIZE + 1)
+        .read_to_end(&mut text)
+        .map_err(|_| TextChunkError::BadCompression)?;
+    if text.len() as u64 > MAX_DECOMPRESSED_SIZE {
+        return Err(TextChunkError::BadCompression.into());
+    }
+    Ok(text)
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum TextChunkError {
+    // chunk isn't tEXt, zTXt or iTXt
+    NotATextChunk,
+    // keyword is empty, too long or has invalid characters
+    BadKeyword(String),
+    BadLanguageTag(String),
+    // char can't be stored in a Latin-1 text chunk
+    NotLatin1(char),
+    // a null separator or the compression fields are missing
+    Truncated,
+    UnknownCompressionMethod(u8),
+    // compressed text is corrupt or too large
+    BadCompression,
+    // hidden data can't be written as text without changing it
+    CannotStore,
+}
+
+impl Display for TextChunkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TextChunkError::NotATextChunk => write!(f, "Chunk is not a tEXt, zTXt or iTXt chunk"),
+            TextChunkError::BadKeyword(k) => write!(
+                f,
+                "Invalid keyword \"{k}\": must be 1-79 printable Latin-1 characters"
+            ),
+            TextChunkError::BadLanguageTag(l) => write!(f, "Invalid language tag \"{l}\""),
+            TextChunkError::NotLatin1(c) => {
+                write!(f, "{c:?} can't be stored in a Latin-1 text chunk")
+            }
+            TextChunkError::Truncated => write!(f, "Text chunk is truncated"),
+            TextChunkError::UnknownCompressionMethod(m) => {
+                write!(f, "Unknown text chunk compression method {m}")
+            }
+            TextChunkError::BadCompression => {
+                write!(f, "Compressed text chunk can't be decompressed")
+            }
+            TextChunkError::CannotStore => {
+                write!(f, "Hidden data can't be stored in a text chunk")
+            }
+        }
+    }
+}
+
+impl Error for TextChunkError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_roundtrip() {
+        let text = TextChunk::plain("Comment", "Grüße aus München").unwrap();
+        let chunk = text.to_chunk().unwrap();
+        assert_eq!(chunk.chunk_type().to_string(), "tEXt");
+        assert_eq!(&chunk.data()[..8], b"Comment\0");
+        // ü is a single Latin-1 byte
+        assert_eq!(chunk.data()[10], 0xfc);
+        assert_eq!(TextChunk::try_from(&chunk).unwrap(), text);
+    }
+
+    #[test]
+    fn test_compressed_text_roundtrip() {
+        let text = TextChunk::compressed("Description", &"dice ".repeat(100)).unwrap();
+        let chunk = text.to_chunk().unwrap();
+        assert_eq!(chunk.chunk_type().to_string(), "zTXt");
+        assert!(chunk.length() < 100);
+        assert_eq!(TextChunk::try_from(&chunk).unwrap(), text);
+    }
+
+    #[test]
+    fn test_international_text_roundtrip() {
+        for compressed in [false, true] {
+            let text =
+                TextChunk::international("Title", "ja", "タイトル", compressed, "サイコロ 🎲")
+                    .unwrap();
+            let chunk = text.to_chunk().unwrap();
+            assert_eq!(chunk.chunk_type().to_string(), "iTXt");
+            let parsed = TextChunk::try_from(&chunk).unwrap();
+            assert_eq!(parsed, text);
+            assert_eq!(parsed.language(), "ja");
+            assert_eq!(parsed.translated_keyword(), "タイトル");
+        }
+    }
+
+    #[test]
+    fn test_not_latin1() {
+        assert!(matches!(
+            TextChunk::plain("Comment", "🎲"),
+            Err(crate::Error::TextChunk(TextChunkError::NotLatin1('🎲')))
+        ));
+    }
+
+    #[test]
+    fn test_bad_keyword() {
+        assert!(TextChunk::plain("", "text").is_err());
+        assert!(TextChunk::plain(&"k".repeat(80), "text").is_err());
+        assert!(TextChunk::plain("new\nline", "text").is_err());
+    }
+
+    #[test]
+    fn test_parse_missing_separator() {
+        let chunk = Chunk::new(ChunkType::from_str("tEXt").unwrap(), b"Comment".to_vec());
+        assert!(matches!(
+            TextChunk::try_from(&chunk),
+            Err(crate::Error::TextChunk(TextChunkError::Truncated))
+        ));
+    }
+
+    #[test]
+    fn test_parse_not_a_text_chunk() {
+        let chunk = Chunk::new(ChunkType::from_str("RuSt").unwrap(), b"a\0b".to_vec());
+        assert!(TextChunk::try_from(&chunk).is_err());
+    }
+
+    #[test]
+    fn test_display() {
+        let text = TextChunk::plain("Comment", "hello").unwrap();
+        assert_eq!(text.to_string(), "Comment: hello");
+        let text = TextChunk::international("Title", "de", "Titel", false, "Würfel").unwrap();
+        assert_eq!(text.to_string(), "Title (de, Titel): Würfel");
+    }
+}
@@ -0,0 +1,374 @@
+//! Checking a png against the chunk rules of the spec (sections 5.6 and 11 of the PNG spec).
+//!
+//! [`Png::try_from`] only checks the signature and every single chunk, so it happily accepts
+//! files a strict decoder rejects. [`validate`] looks at the png as a whole: which chunks
+//! are there, how often and in which order.
+
+use std::fmt::Display;
+
+use crate::chunk::Chunk;
+use crate::ihdr::ColorType;
+use crate::png::Png;
+
+// chunks that can appear at most once
+const SINGLETONS: [&str; 17] = [
+    "IHDR", "PLTE", "IEND", "cHRM", "gAMA", "iCCP", "sBIT", "sRGB", "cICP", "mDCV", "cLLI", "bKGD",
+    "hIST", "tRNS", "eXIf", "pHYs", "tIME",
+];
+// chunks that have to come before PLTE
+const BEFORE_PLTE: [&str; 8] = [
+    "cHRM", "gAMA", "iCCP", "sBIT", "sRGB", "cICP", "mDCV", "cLLI",
+];
+// chunks that have to come after PLTE
+const AFTER_PLTE: [&str; 3] = ["tRNS", "bKGD", "hIST"];
+// chunks that have to come before the first IDAT, besides the ones above
+const BEFORE_IDAT: [&str; 3] = ["PLTE", "pHYs", "sPLT"];
+const CRITICAL: [&str; 4] = ["IHDR", "PLTE", "IDAT", "IEND"];
+
+/// how bad a violation is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// allowed by the spec, but unusual or ignored by decoders
+    Warning,
+    /// breaks the spec, decoders may reject the png
+    Error,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// the rule that is broken
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Rule {
+    MissingIhdr,
+    IhdrNotFirst,
+    // IHDR can't be parsed, with the reason
+    BadIhdr(String),
+    MissingIdat,
+    IdatNotContiguous,
+    MissingIend,
+    ChunkAfterIend(String),
+    // number of bytes after IEND that aren't a chunk
+    TrailingData(usize),
+    // indexed images need a palette
+    MissingPlte,
+    PlteInGrayscale,
+    // chunk only makes sense with a palette, but there is none
+    NeedsPlte(String),
+    // tRNS isn't allowed in images that have an alpha channel
+    TrnsWithAlpha,
+    Duplicate(String),
+    // chunk has to come before or after the chunk named in position
+    Misplaced {
+        chunk_type: String,
+        position: &'static str,
+    },
+    BothIccpAndSrgb,
+    // decoders have to reject critical chunks they don't know
+    UnknownCritical(String),
+    ReservedBitSet(String),
+}
+
+impl Rule {
+    pub fn severity(&self) -> Severity {
+        match self {
+            Rule::TrailingData(_) | Rule::BothIccpAndSrgb | Rule::ReservedBitSet(_) => {
+                Severity::Warning
+            }
+            _ => Severity::Error,
+        }
+    }
+}
+
+impl Display for Rule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Rule::MissingIhdr => write!(f, "IHDR chunk is missing"),
+            Rule::IhdrNotFirst => write!(f, "IHDR has to be the first chunk"),
+            Rule::BadIhdr(reason) => write!(f, "IHDR is invalid: {reason}"),
+            Rule::MissingIdat => write!(f, "IDAT chunk is missing"),
+            Rule::IdatNotContiguous => {
+                write!(f, "IDAT chunks have to follow each other directly")
+            }
+            Rule::MissingIend => write!(f, "IEND chunk is missing"),
+            Rule::ChunkAfterIend(ct) => write!(f, "{ct} comes after IEND"),
+            Rule::TrailingData(n) => write!(f, "{n} bytes of data after IEND"),
+            Rule::MissingPlte => write!(f, "PLTE chunk is missing in an indexed image"),
+            Rule::PlteInGrayscale => write!(f, "PLTE is not allowed in grayscale images"),
+            Rule::NeedsPlte(ct) => write!(f, "{ct} needs a PLTE chunk"),
+            Rule::TrnsWithAlpha => {
+                write!(f, "tRNS is not allowed in images with an alpha channel")
+            }
+            Rule::Duplicate(ct) => write!(f, "{ct} can only appear once"),
+            Rule::Misplaced {
+                chunk_type,
+                position,
+            } => write!(f, "{chunk_type} has to come {position}"),
+            Rule::BothIccpAndSrgb => write!(f, "iCCP and sRGB should not both be present"),
+            Rule::UnknownCritical(ct) => write!(f, "{ct} is an unknown critical chunk"),
+            Rule::ReservedBitSet(ct) => write!(f, "{ct} has the reserved bit set"),
+        }
+    }
+}
+
+/// a broken rule and where it is broken
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// index of the chunk breaking the rule, None if it's about the png as a whole
+    pub index: Option<usize>,
+    pub rule: Rule,
+}
+
+impl Violation {
+    pub fn severity(&self) -> Severity {
+        self.rule.severity()
+    }
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.index {
+            Some(index) => write!(f, "{}: chunk {index}: {}", self.severity(), self.rule),
+            None => write!(f, "{}: {}", self.severity(), self.rule),
+        }
+    }
+}
+
+/// every rule png breaks
+pub fn validate(png: &Png) -> Vec<Violation> {
+    let chunks = png.chunks();
+    let type_of = |c: &Chunk| c.chunk_type().to_string();
+    let first = |ct: &str| chunks.iter().position(|c| type_of(c) == ct);
+    let mut violations = Vec::new();
+    let mut violation =
+        |index: Option<usize>, rule: Rule| violations.push(Violation { index, rule });
+
+    // IHDR first, with valid values
+    let color_type = match first("IHDR") {
+        None => {
+            violation(None, Rule::MissingIhdr);
+            None
+        }
+        Some(i) => {
+            if i != 0 {
+                violation(Some(i), Rule::IhdrNotFirst);
+            }
+            match png.ihdr() {
+                Ok(ihdr) => Some(ihdr.color_type()),
+                Err(e) if i == 0 => {
+                    violation(Some(i), Rule::BadIhdr(e.to_string()));
+                    None
+                }
+                Err(_) => None,
+            }
+        }
+    };
+
+    let plte = first("PLTE");
+    let first_idat = first("IDAT");
+    let iend = first("IEND");
+    match (color_type, plte) {
+        (Some(ColorType::Indexed), None) => violation(None, Rule::MissingPlte),
+        (Some(ColorType::Grayscale | ColorType::GrayscaleAlpha), Some(i)) => {
+            violation(Some(i), Rule::PlteInGrayscale)
+        }
+        _ => {}
+    }
+    if first_idat.is_none() {
+        violation(None, Rule::MissingIdat);
+    }
+    if iend.is_none() {
+        violation(None, Rule::MissingIend);
+    }
+
+    for (i, chunk) in chunks.iter().enumerate() {
+        let ct = type_of(chunk);
+
+        if SINGLETONS.contains(&ct.as_str()) && first(&ct) != Some(i) {
+            violation(Some(i), Rule::Duplicate(ct.clone()));
+        }
+        if iend.is_some_and(|end| i > end) {
+            violation(Some(i), Rule::ChunkAfterIend(ct.clone()));
+        }
+        // IDAT chunks form a single block, whatever is between them breaks it up
+        if ct == "IDAT" && i > 0 && first_idat != Some(i) && type_of(&chunks[i - 1]) != "IDAT" {
+            violation(Some(i), Rule::IdatNotContiguous);
+        }
+
+        let misplaced = |position| Rule::Misplaced {
+            chunk_type: ct.clone(),
+            position,
+        };
+        if BEFORE_PLTE.contains(&ct.as_str()) && plte.is_some_and(|p| i > p) {
+            violation(Some(i), misplaced("before PLTE"));
+        }
+        if AFTER_PLTE.contains(&ct.as_str()) && plte.is_some_and(|p| i < p) {
+            violation(Some(i), misplaced("after PLTE"));
+        }
+        let before_idat = [&BEFORE_PLTE[..], &AFTER_PLTE, &BEFORE_IDAT].concat();
+        if before_idat.contains(&ct.as_str()) && first_idat.is_some_and(|d| i > d) {
+            violation(Some(i), misplaced("before IDAT"));
+        }
+
+        if ct == "hIST" && plte.is_none() {
+            violation(Some(i), Rule::NeedsPlte(ct.clone()));
+        }
+        if ct == "tRNS" && color_type.is_some_and(|c| c.has_alpha()) {
+            violation(Some(i), Rule::TrnsWithAlpha);
+        }
+        if ct == "sRGB" && first("iCCP").is_some() {
+            violation(Some(i), Rule::BothIccpAndSrgb);
+        }
+        if chunk.chunk_type().is_critical() && !CRITICAL.contains(&ct.as_str()) {
+            violation(Some(i), Rule::UnknownCritical(ct.clone()));
+        }
+        if !chunk.chunk_type().is_reserved_bit_valid() {
+            violation(Some(i), Rule::ReservedBitSet(ct));
+        }
+    }
+
+    if !png.trailing_data().is_empty() {
+        violation(None, Rule::TrailingData(png.trailing_data().len()));
+    }
+
+    violations
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::chunk_type::ChunkType;
+    use crate::ihdr::Ihdr;
+    use crate::test_util::{DICE, dice};
+
+    fn chunk(chunk_type: &str) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), vec![])
+    }
+
+    fn ihdr(color_type: ColorType) -> Chunk {
+        Ihdr::new(1, 1, 8, color_type, false).unwrap().to_chunk()
+    }
+
+    fn rules(png: &Png) -> Vec<(Option<usize>, Rule)> {
+        png.validate()
+            .into_iter()
+            .map(|v| (v.index, v.rule))
+            .collect()
+    }
+
+    #[test]
+    fn test_dice() {
+        // RuSt starts with an uppercase letter, which makes it critical
+        let png = dice();
+        assert_eq!(
+            rules(&png),
+            [(Some(5), Rule::UnknownCritical("RuSt".to_string()))]
+        );
+    }
+
+    #[test]
+    fn test_ordering() {
+        let png = Png::from_chunks(vec![
+            chunk("IDAT"),
+            ihdr(ColorType::Rgb),
+            chunk("IDAT"),
+            chunk("gAMA"),
+            chunk("IDAT"),
+        ]);
+        assert_eq!(
+            rules(&png),
+            [
+                (Some(1), Rule::IhdrNotFirst),
+                (None, Rule::MissingIend),
+                (Some(2), Rule::IdatNotContiguous),
+                (
+                    Some(3),
+                    Rule::Misplaced {
+                        chunk_type: "gAMA".to_string(),
+                        position: "before IDAT"
+                    }
+                ),
+                (Some(4), Rule::IdatNotContiguous),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_palette_rules() {
+        let png = Png::from_chunks(vec![
+            ihdr(ColorType::Grayscale),
+            chunk("PLTE"),
+            chunk("IDAT"),
+            chunk("IEND"),
+        ]);
+        assert_eq!(rules(&png), [(Some(1), Rule::PlteInGrayscale)]);
+
+        let png = Png::from_chunks(vec![
+            ihdr(ColorType::Indexed),
+            chunk("tRNS"),
+            chunk("PLTE"),
+            chunk("PLTE"),
+            chunk("IDAT"),
+            chunk("IEND"),
+        ]);
+        assert_eq!(
+            rules(&png),
+            [
+                (
+                    Some(1),
+                    Rule::Misplaced {
+                        chunk_type: "tRNS".to_string(),
+                        position: "after PLTE"
+                    }
+                ),
+                (Some(3), Rule::Duplicate("PLTE".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_after_iend() {
+        let mut bytes = DICE.to_vec();
+        bytes.extend_from_slice(&chunk("tEXt").as_bytes());
+        bytes.extend_from_slice(b"garbage");
+        let png = Png::try_from(bytes.as_slice()).unwrap();
+        assert_eq!(png.trailing_data(), b"garbage");
+        assert_eq!(png.as_bytes(), bytes);
+
+        let violations = png.validate();
+        assert_eq!(violations.len(), 3);
+        assert_eq!(
+            violations[1].to_string(),
+            "error: chunk 7: tEXt comes after IEND"
+        );
+        assert_eq!(violations[2].rule, Rule::TrailingData(7));
+        assert_eq!(violations[2].severity(), Severity::Warning);
+    }
+
+    #[test]
+    fn test_chunk_type_bits() {
+        let png = Png::from_chunks(vec![
+            ihdr(ColorType::Rgba),
+            chunk("IDAT"),
+            chunk("TeST"),
+            chunk("text"),
+            chunk("IEND"),
+        ]);
+        assert_eq!(
+            rules(&png),
+            [
+                (Some(2), Rule::UnknownCritical("TeST".to_string())),
+                (Some(3), Rule::ReservedBitSet("text".to_string())),
+            ]
+        );
+    }
+}
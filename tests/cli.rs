@@ -0,0 +1,87 @@
+use std::path::PathBuf;
+use std::process::{Command, Output};
+use std::str::FromStr;
+
+use pngecret::{Chunk, ChunkType, Placement, Png};
+
+const DICE: &[u8] = include_bytes!("data/dice.png");
+
+/// copy of dice in the temp dir, unique to the test
+fn temp_png(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("pngecret-{}-{name}.png", std::process::id()));
+    std::fs::write(&path, DICE).unwrap();
+    path
+}
+
+/// run the pngecret binary with args
+fn pngecret(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_pngecret"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn test_decode_plain_with_passphrase() {
+    let path = temp_png("plain");
+    let file = path.to_str().unwrap();
+    let encoded = pngecret(&["encode", file, "ruSt", "hello world"]);
+    assert!(encoded.status.success(), "{}", stderr(&encoded));
+
+    // a plain message isn't run through the Vigenère cipher of older versions
+    let decoded = pngecret(&["decode", file, "ruSt", "-d", "pass"]);
+    std::fs::remove_file(&path).unwrap();
+    assert!(!decoded.status.success());
+    assert!(decoded.stdout.is_empty());
+    assert!(stderr(&decoded).contains("isn't encrypted"));
+}
+
+#[test]
+fn test_decode_tampered_magic() {
+    let path = temp_png("tampered");
+    let file = path.to_str().unwrap();
+    let encoded = pngecret(&["encode", file, "ruSt", "hello world", "-e", "pass"]);
+    assert!(encoded.status.success(), "{}", stderr(&encoded));
+
+    let mut png = Png::try_from(std::fs::read(&path).unwrap().as_slice()).unwrap();
+    let mut data = png.remove_first_chunk("ruSt").unwrap().data().to_vec();
+    data[3] ^= 1;
+    let chunk = Chunk::new(ChunkType::from_str("ruSt").unwrap(), data);
+    png.insert_chunk(chunk, Placement::default()).unwrap();
+    std::fs::write(&path, png.as_bytes()).unwrap();
+
+    let decoded = pngecret(&["decode", file, "ruSt", "-d", "pass"]);
+    std::fs::remove_file(&path).unwrap();
+    assert!(!decoded.status.success());
+    assert!(decoded.stdout.is_empty());
+    assert!(stderr(&decoded).contains("Encrypted data is malformed"));
+}
+
+#[test]
+fn test_extract_needs_embed_costs() {
+    let path = temp_png("costs");
+    let file = path.to_str().unwrap();
+    let args = [
+        "embed",
+        file,
+        "hello world",
+        "-e",
+        "pass",
+        "--argon2-memory",
+        "64",
+    ];
+    let embedded = pngecret(&args);
+    assert!(embedded.status.success(), "{}", stderr(&embedded));
+
+    // the order of the samples is derived with the costs of the envelope
+    let extracted = pngecret(&["extract", file, "-d", "pass"]);
+    assert!(extracted.stdout.is_empty());
+    let extracted = pngecret(&["extract", file, "-d", "pass", "--argon2-memory", "64"]);
+    std::fs::remove_file(&path).unwrap();
+    assert!(extracted.status.success(), "{}", stderr(&extracted));
+    assert_eq!(extracted.stdout, b"hello world");
+}
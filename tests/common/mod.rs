@@ -0,0 +1,20 @@
+use pngecret::Png;
+
+/// shrunken version of the `dice.png` image on Wikipedia (50x50 RGBA)
+pub const DICE: &[u8] = include_bytes!("../data/dice.png");
+
+pub fn dice() -> Png {
+    Png::try_from(DICE).unwrap()
+}
+
+/// dice reduced to a palette of 120 colors with transparency (50x50 indexed)
+pub fn dice_indexed() -> Png {
+    Png::try_from(&include_bytes!("../data/dice-indexed.png")[..]).unwrap()
+}
+
+pub fn chunk_types(png: &Png) -> Vec<String> {
+    png.chunks()
+        .iter()
+        .map(|c| c.chunk_type().to_string())
+        .collect()
+}
@@ -0,0 +1,101 @@
+mod common;
+
+use std::str::FromStr;
+
+use common::{DICE, chunk_types, dice, dice_indexed};
+use pngecret::{Chunk, ChunkType, Placement, Png};
+
+#[test]
+fn test_parse_and_write_roundtrip() {
+    let png = dice();
+    assert_eq!(png.header(), &Png::STANDARD_HEADER);
+    assert_eq!(png.as_bytes(), DICE);
+}
+
+#[test]
+fn test_parse_chunks() {
+    let png = dice();
+    assert_eq!(
+        chunk_types(&png),
+        ["IHDR", "sRGB", "gAMA", "pHYs", "IDAT", "RuSt", "IEND"]
+    );
+    let ihdr = &png.chunks()[0];
+    assert!(ihdr.chunk_type().is_critical());
+    assert_eq!(ihdr.length(), 13);
+}
+
+#[test]
+fn test_parse_indexed() {
+    let png = dice_indexed();
+    assert_eq!(chunk_types(&png), ["IHDR", "PLTE", "tRNS", "IDAT", "IEND"]);
+    assert!(png.validate().is_empty());
+}
+
+#[test]
+fn test_parse_rejects_garbage() {
+    assert!(Png::try_from(&DICE[1..]).is_err());
+    assert!(Png::try_from(&b"not a png"[..]).is_err());
+}
+
+#[test]
+fn test_insert_and_reparse() {
+    let mut png = dice();
+    let chunk = Chunk::new(ChunkType::from_str("teXt").unwrap(), b"hello".to_vec());
+    png.insert_chunk(chunk, Placement::AfterIhdr).unwrap();
+
+    let reparsed = Png::try_from(png.as_bytes().as_slice()).unwrap();
+    assert_eq!(chunk_types(&reparsed)[..2], ["IHDR", "teXt"]);
+    assert_eq!(reparsed.chunks()[1].data(), b"hello");
+}
+
+#[test]
+fn test_typed_errors() {
+    use pngecret::chunk::ChunkError;
+    use pngecret::png::PngError;
+
+    let mut bytes = DICE.to_vec();
+    // flip a bit in the IDAT data, which makes its crc wrong
+    bytes[100] ^= 1;
+    match Png::try_from(bytes.as_slice()) {
+        Err(pngecret::Error::InvalidChunk { index, source, .. }) => {
+            assert_eq!(index, 4);
+            assert!(matches!(
+                *source,
+                pngecret::Error::Chunk(ChunkError::BadChecksum { .. })
+            ));
+        }
+        _ => panic!("expected an invalid chunk error"),
+    }
+
+    assert!(matches!(
+        Png::try_from(&b"not a png"[..]),
+        Err(pngecret::Error::Png(PngError::BadHeader))
+    ));
+
+    let mut png = dice();
+    assert!(matches!(
+        pngecret::remove(&mut png, "TeST", &Default::default()),
+        Err(pngecret::Error::Png(PngError::ChunkNotFound(_)))
+    ));
+}
+
+#[test]
+fn test_validate() {
+    use pngecret::validate::{Rule, Severity};
+
+    let mut png = dice();
+    png.remove_first_chunk("RuSt").unwrap();
+    assert!(png.validate().is_empty());
+
+    // chunks inserted by encode don't break the rules, as long as they are ancillary
+    let chunk = Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"hello".to_vec());
+    png.insert_chunk(chunk, Placement::BeforeIend).unwrap();
+    assert!(png.validate().is_empty());
+
+    let chunk = Chunk::new(ChunkType::from_str("gAMA").unwrap(), vec![0, 0, 0, 1]);
+    png.insert_chunk(chunk, Placement::AfterLastIdat).unwrap();
+    let violations = png.validate();
+    assert_eq!(violations.len(), 2);
+    assert_eq!(violations[0].rule, Rule::Duplicate("gAMA".to_string()));
+    assert!(violations.iter().all(|v| v.severity() == Severity::Error));
+}
@@ -0,0 +1,290 @@
+mod common;
+
+use common::{chunk_types, dice, dice_indexed};
+use pngecret::capacity::Method;
+use pngecret::crypt::{Cipher, CryptoError, Kdf};
+use pngecret::lsb::StegoKey;
+use pngecret::pixels::Pixels;
+use pngecret::{DecodeOptions, EncodeOptions, LsbOptions, Payload, Png, TextChunk};
+
+fn passphrase(passphrase: &str) -> DecodeOptions {
+    DecodeOptions {
+        passphrase: Some(passphrase.to_string()),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_encode_decode_roundtrip() {
+    let mut png = dice();
+    pngecret::encode(
+        &mut png,
+        "TeST",
+        b"hidden message",
+        &EncodeOptions::default(),
+    )
+    .unwrap();
+
+    // encoded image stays a valid png with IEND last
+    let png = Png::try_from(png.as_bytes().as_slice()).unwrap();
+    assert_eq!(chunk_types(&png).last().unwrap(), "IEND");
+
+    let msg = pngecret::decode_text(&png, "TeST", &DecodeOptions::default()).unwrap();
+    assert_eq!(msg.as_deref(), Some("hidden message"));
+}
+
+#[test]
+fn test_encode_decode_encrypted() {
+    for cipher in [Cipher::XChaCha20Poly1305, Cipher::Aes256Gcm] {
+        let mut png = dice();
+        let options = EncodeOptions {
+            passphrase: Some("passphrase".to_string()),
+            recipients: Vec::new(),
+            cipher,
+            kdf: Kdf::default(),
+            compression_level: None,
+            max_chunk_size: None,
+            keyword: None,
+            signing_key: None,
+            sign_critical_chunks: false,
+        };
+        pngecret::encode(&mut png, "TeST", b"hidden message", &options).unwrap();
+        let png = Png::try_from(png.as_bytes().as_slice()).unwrap();
+
+        let msg = pngecret::decode(&png, "TeST", &passphrase("passphrase")).unwrap();
+        assert_eq!(msg.as_deref(), Some(&b"hidden message"[..]));
+
+        assert!(matches!(
+            pngecret::decode(&png, "TeST", &passphrase("wrong")),
+            Err(pngecret::Error::Crypto(CryptoError::DecryptionFailed))
+        ));
+    }
+}
+
+#[test]
+fn test_encode_decode_unicode_and_binary() {
+    let text = "Ünïcödé 中文 emoji 🎉🔒";
+    let binary: Vec<u8> = (0..=255).rev().collect();
+
+    let mut png = dice();
+    pngecret::encode(&mut png, "TeXT", text.as_bytes(), &EncodeOptions::default()).unwrap();
+    pngecret::encode(&mut png, "BiNA", &binary, &EncodeOptions::default()).unwrap();
+    let png = Png::try_from(png.as_bytes().as_slice()).unwrap();
+
+    let decoded = pngecret::decode_text(&png, "TeXT", &DecodeOptions::default()).unwrap();
+    assert_eq!(decoded.as_deref(), Some(text));
+    let decoded = pngecret::decode(&png, "BiNA", &DecodeOptions::default()).unwrap();
+    assert_eq!(decoded, Some(binary));
+}
+
+#[test]
+fn test_encode_decode_file() {
+    let content: Vec<u8> = (0..10_000).map(|i| (i % 251) as u8).collect();
+    let file = Payload::file("report.pdf", content.clone()).unwrap();
+
+    let mut png = dice();
+    let options = EncodeOptions {
+        passphrase: Some("passphrase".to_string()),
+        ..Default::default()
+    };
+    pngecret::encode_payload(&mut png, "FiLE", &file, &options).unwrap();
+    let png = Png::try_from(png.as_bytes().as_slice()).unwrap();
+
+    let decoded = pngecret::decode_payload(&png, "FiLE", &passphrase("passphrase"))
+        .unwrap()
+        .unwrap();
+    assert_eq!(decoded.file_name(), Some("report.pdf"));
+    assert_eq!(decoded.data(), content);
+}
+
+#[test]
+fn test_encode_decode_split_file() {
+    let content: Vec<u8> = (0..50_000).map(|i| (i * 7 % 256) as u8).collect();
+    let file = Payload::file("archive.tar", content.clone()).unwrap();
+
+    let mut png = dice();
+    let options = EncodeOptions {
+        max_chunk_size: Some(4096),
+        ..Default::default()
+    };
+    assert!(content.len() > 4096);
+    pngecret::encode_payload(&mut png, "SpLt", &file, &options).unwrap();
+    let png = Png::try_from(png.as_bytes().as_slice()).unwrap();
+
+    let types = chunk_types(&png);
+    assert_eq!(types.iter().filter(|t| *t == "SpLt").count(), 13);
+    assert_eq!(types.last().unwrap(), "IEND");
+
+    let decoded = pngecret::decode_payload(&png, "SpLt", &DecodeOptions::default())
+        .unwrap()
+        .unwrap();
+    assert_eq!(decoded.file_name(), Some("archive.tar"));
+    assert_eq!(decoded.data(), content);
+}
+
+#[test]
+fn test_encode_decode_compressed_file() {
+    let content = "PNGecret hides things in PNG files. ".repeat(1000);
+    let file = Payload::file("readme.txt", content.clone().into_bytes()).unwrap();
+
+    let mut png = dice();
+    let size_before = png.as_bytes().len();
+    let options = EncodeOptions {
+        passphrase: Some("passphrase".to_string()),
+        compression_level: Some(9),
+        ..Default::default()
+    };
+    pngecret::encode_payload(&mut png, "CoMp", &file, &options).unwrap();
+    assert!(png.as_bytes().len() - size_before < content.len() / 10);
+
+    let decoded = pngecret::decode_payload(&png, "CoMp", &passphrase("passphrase"))
+        .unwrap()
+        .unwrap();
+    assert_eq!(decoded, file);
+}
+
+#[test]
+fn test_encode_decode_text_chunks() {
+    let mut png = dice();
+    let options = EncodeOptions {
+        keyword: Some("Author".to_string()),
+        ..Default::default()
+    };
+    pngecret::encode(&mut png, "tEXt", b"hidden message", &options).unwrap();
+    pngecret::encode(&mut png, "iTXt", "Würfel 🎲".as_bytes(), &options).unwrap();
+    let png = Png::try_from(png.as_bytes().as_slice()).unwrap();
+
+    // other programs see normal text chunks
+    let text = TextChunk::try_from(png.chunk_by_type("tEXt").unwrap()).unwrap();
+    assert_eq!(text.keyword(), "Author");
+    assert_eq!(text.text(), "hidden message");
+
+    let options = DecodeOptions {
+        keyword: Some("Author".to_string()),
+        ..Default::default()
+    };
+    let msg = pngecret::decode_text(&png, "tEXt", &options).unwrap();
+    assert_eq!(msg.as_deref(), Some("hidden message"));
+    let msg = pngecret::decode_text(&png, "iTXt", &options).unwrap();
+    assert_eq!(msg.as_deref(), Some("Würfel 🎲"));
+    // nothing is hidden under the default keyword
+    let msg = pngecret::decode(&png, "tEXt", &DecodeOptions::default()).unwrap();
+    assert!(msg.is_none());
+}
+
+#[test]
+fn test_embed_extract_in_pixels() {
+    let mut png = dice();
+    let options = EncodeOptions {
+        passphrase: Some("passphrase".to_string()),
+        ..Default::default()
+    };
+    let lsb_options = LsbOptions {
+        channels: "rgba".parse().unwrap(),
+        bits: 2,
+        key: Some(StegoKey::derive("passphrase", &png.ihdr().unwrap(), options.kdf).unwrap()),
+        ..Default::default()
+    };
+    let payload = Payload::message(b"hidden message".to_vec());
+    pngecret::embed_payload(&mut png, &payload, &options, &lsb_options).unwrap();
+    let png = Png::try_from(png.as_bytes().as_slice()).unwrap();
+
+    // no chunk was added and the pixels barely changed
+    assert_eq!(chunk_types(&png), chunk_types(&dice()));
+    let before = Pixels::from_png(&dice()).unwrap();
+    let after = Pixels::from_png(&png).unwrap();
+    assert!(
+        before
+            .data()
+            .iter()
+            .zip(after.data())
+            .all(|(a, b)| a.abs_diff(*b) <= 3)
+    );
+
+    let extracted = pngecret::extract_payload(&png, &passphrase("passphrase"), &lsb_options);
+    assert_eq!(extracted.unwrap(), Some(payload));
+
+    // the samples are shuffled by the key, in order there is nothing
+    let in_order = LsbOptions {
+        key: None,
+        ..lsb_options
+    };
+    let extracted = pngecret::extract_payload(&png, &passphrase("passphrase"), &in_order);
+    assert_eq!(extracted.unwrap(), None);
+}
+
+#[test]
+fn test_embed_extract_palette() {
+    let mut png = dice_indexed();
+    let options = EncodeOptions {
+        passphrase: Some("passphrase".to_string()),
+        ..Default::default()
+    };
+    let lsb_options = LsbOptions::default();
+    let payload = Payload::message(b"hidden in the palette".to_vec());
+    pngecret::embed_payload(&mut png, &payload, &options, &lsb_options).unwrap();
+    let png = Png::try_from(png.as_bytes().as_slice()).unwrap();
+
+    assert_eq!(chunk_types(&png), chunk_types(&dice_indexed()));
+    let extracted = pngecret::extract_payload(&png, &passphrase("passphrase"), &lsb_options);
+    assert_eq!(extracted.unwrap(), Some(payload));
+}
+
+#[test]
+fn test_embed_extract_transparent() {
+    let transparent = LsbOptions {
+        transparent: true,
+        ..Default::default()
+    };
+    let small = Payload::message(b"invisible".to_vec());
+    let mut png = dice();
+    let method = pngecret::embed_payload(&mut png, &small, &EncodeOptions::default(), &transparent);
+    assert_eq!(method.unwrap(), Method::Alpha);
+    // found without asking for transparent pixels
+    let extracted =
+        pngecret::extract_payload(&png, &DecodeOptions::default(), &LsbOptions::default());
+    assert_eq!(extracted.unwrap(), Some(small));
+
+    // too large for the transparent pixels, but not for 4 bits of every sample
+    let transparent = LsbOptions {
+        channels: "rgba".parse().unwrap(),
+        bits: 4,
+        ..transparent
+    };
+    let large = Payload::message(vec![7; 4500]);
+    let mut png = dice();
+    let method = pngecret::embed_payload(&mut png, &large, &EncodeOptions::default(), &transparent);
+    assert_eq!(method.unwrap(), Method::Lsb { bits: 4 });
+    let extracted = pngecret::extract_payload(&png, &DecodeOptions::default(), &transparent);
+    assert_eq!(extracted.unwrap(), Some(large));
+}
+
+#[test]
+fn test_decode_missing() {
+    let png = dice();
+    assert!(
+        pngecret::decode(&png, "TeST", &DecodeOptions::default())
+            .unwrap()
+            .is_none()
+    );
+}
+
+#[test]
+fn test_remove() {
+    let mut png = dice();
+    pngecret::encode(
+        &mut png,
+        "TeST",
+        b"hidden message",
+        &EncodeOptions::default(),
+    )
+    .unwrap();
+    let removed = pngecret::remove(&mut png, "TeST", &DecodeOptions::default()).unwrap();
+    assert_eq!(removed[0].data(), b"hidden message");
+    assert!(
+        pngecret::decode(&png, "TeST", &DecodeOptions::default())
+            .unwrap()
+            .is_none()
+    );
+    assert!(pngecret::remove(&mut png, "TeST", &DecodeOptions::default()).is_err());
+}